@@ -16,6 +16,7 @@
 //! Configuration for the CatP2P library.
 
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
 
 /// Resource allocation modes.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -30,6 +31,131 @@ pub enum ResourceMode {
     Custom,
 }
 
+impl ResourceMode {
+    /// Returns the default adaptive throttling thresholds for this mode.
+    ///
+    /// Lighter modes back off earlier and shrink further when the host is busy.
+    pub fn default_throttle_config(&self) -> ThrottleConfig {
+        let (pause_cpu_load, resume_cpu_load, pause_memory_pressure, resume_memory_pressure, min_budget) = match self {
+            ResourceMode::Light => (0.5, 0.3, 0.8, 0.7, 0.1),
+            ResourceMode::Medium | ResourceMode::Custom => (0.7, 0.5, 0.85, 0.75, 0.25),
+            ResourceMode::HighPerformance => (0.9, 0.75, 0.95, 0.9, 0.5),
+        };
+
+        ThrottleConfig {
+            pause_cpu_load,
+            resume_cpu_load,
+            pause_memory_pressure,
+            resume_memory_pressure,
+            min_budget,
+            budget_step: 0.1,
+            idle_after: Duration::from_secs(300),
+            input_interrupts: vec!["i8042".to_string()],
+        }
+    }
+}
+
+/// Thresholds for adaptively throttling the node while the host is in use.
+///
+/// Loads and pressures are fractions (0.0 - 1.0). The scheduler pauses when either
+/// pause threshold is reached, and resumes once both are back below the resume
+/// thresholds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ThrottleConfig {
+    /// CPU load of other processes at which the scheduler pauses.
+    pub pause_cpu_load: f32,
+    /// CPU load of other processes below which a paused scheduler resumes.
+    pub resume_cpu_load: f32,
+    /// Fraction of memory in use at which the scheduler pauses.
+    pub pause_memory_pressure: f32,
+    /// Fraction of memory in use below which a paused scheduler resumes.
+    pub resume_memory_pressure: f32,
+    /// Smallest fraction of the mode's budget kept while the host is busy.
+    pub min_budget: f32,
+    /// Largest increase of the budget fraction per sample.
+    pub budget_step: f32,
+    /// Time without user input after which the full budget is used.
+    pub idle_after: Duration,
+    /// Names of the `/proc/interrupts` sources that indicate user input.
+    pub input_interrupts: Vec<String>,
+}
+
+/// Adaptive throttling thresholds for each resource mode.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ThrottleSettings {
+    /// Thresholds in light mode.
+    pub light: ThrottleConfig,
+    /// Thresholds in medium mode.
+    pub medium: ThrottleConfig,
+    /// Thresholds in high performance mode.
+    pub high_performance: ThrottleConfig,
+    /// Thresholds in custom mode.
+    pub custom: ThrottleConfig,
+}
+
+impl ThrottleSettings {
+    /// Returns the thresholds for the given mode.
+    pub fn for_mode(&self, mode: ResourceMode) -> &ThrottleConfig {
+        match mode {
+            ResourceMode::Light => &self.light,
+            ResourceMode::Medium => &self.medium,
+            ResourceMode::HighPerformance => &self.high_performance,
+            ResourceMode::Custom => &self.custom,
+        }
+    }
+}
+
+impl Default for ThrottleSettings {
+    fn default() -> Self {
+        Self {
+            light: ResourceMode::Light.default_throttle_config(),
+            medium: ResourceMode::Medium.default_throttle_config(),
+            high_performance: ResourceMode::HighPerformance.default_throttle_config(),
+            custom: ResourceMode::Custom.default_throttle_config(),
+        }
+    }
+}
+
+/// Thermal and power limits on task intake.
+///
+/// Temperatures are in Celsius. Between the throttle and pause temperatures the
+/// budget shrinks linearly; task intake resumes once temperatures are
+/// `resume_margin` below the pause temperature.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PowerConfig {
+    /// CPU temperature at which the budget starts shrinking.
+    pub cpu_throttle_temperature: f32,
+    /// CPU temperature at which task intake pauses.
+    pub cpu_pause_temperature: f32,
+    /// GPU temperature at which the budget starts shrinking.
+    pub gpu_throttle_temperature: f32,
+    /// GPU temperature at which task intake pauses.
+    pub gpu_pause_temperature: f32,
+    /// How far temperatures must drop below the pause temperature to resume.
+    pub resume_margin: f32,
+    /// Whether task intake pauses on battery power.
+    pub pause_on_battery: bool,
+    /// Fraction of the budget used on battery power (0.0 - 1.0).
+    pub battery_budget: f32,
+    /// Battery charge in percent below which task intake pauses on battery power.
+    pub min_battery_capacity: u8,
+}
+
+impl Default for PowerConfig {
+    fn default() -> Self {
+        Self {
+            cpu_throttle_temperature: 80.0,
+            cpu_pause_temperature: 90.0,
+            gpu_throttle_temperature: 80.0,
+            gpu_pause_temperature: 90.0,
+            resume_margin: 5.0,
+            pause_on_battery: false,
+            battery_budget: 0.5,
+            min_battery_capacity: 20,
+        }
+    }
+}
+
 /// Resource limits for custom mode.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ResourceLimits {
@@ -41,6 +167,136 @@ pub struct ResourceLimits {
     pub gpu_limit: Option<f32>,
     /// Storage usage limit in bytes.
     pub storage_limit: u64,
+    /// Disk read and write bandwidth limit in bytes per second, if any.
+    #[serde(default)]
+    pub io_limit: Option<u64>,
+}
+
+/// Days of the week.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Weekday {
+    /// Monday.
+    Monday,
+    /// Tuesday.
+    Tuesday,
+    /// Wednesday.
+    Wednesday,
+    /// Thursday.
+    Thursday,
+    /// Friday.
+    Friday,
+    /// Saturday.
+    Saturday,
+    /// Sunday.
+    Sunday,
+}
+
+impl Weekday {
+    const ALL: [Weekday; 7] = [
+        Weekday::Monday,
+        Weekday::Tuesday,
+        Weekday::Wednesday,
+        Weekday::Thursday,
+        Weekday::Friday,
+        Weekday::Saturday,
+        Weekday::Sunday,
+    ];
+
+    /// Returns the previous day.
+    pub fn previous(&self) -> Weekday {
+        Self::ALL[(*self as usize + 6) % 7]
+    }
+}
+
+/// A time of day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeOfDay {
+    /// Hour (0 - 23).
+    pub hour: u8,
+    /// Minute (0 - 59).
+    pub minute: u8,
+}
+
+impl TimeOfDay {
+    /// Creates a new TimeOfDay.
+    pub fn new(hour: u8, minute: u8) -> Self {
+        Self { hour, minute }
+    }
+
+    /// Returns the number of minutes since midnight.
+    pub fn minutes(&self) -> u32 {
+        self.hour as u32 * 60 + self.minute as u32
+    }
+
+    fn is_valid(&self) -> bool {
+        self.hour < 24 && self.minute < 60
+    }
+}
+
+/// A weekly time window with its own resource mode.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduleWindow {
+    /// Days the window starts on; empty means every day.
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    /// Start of the window.
+    pub start: TimeOfDay,
+    /// End of the window; a window ending at or before its start runs past midnight.
+    pub end: TimeOfDay,
+    /// Resource mode during the window.
+    pub mode: ResourceMode,
+    /// Resource limits during the window (only used if mode is Custom).
+    #[serde(default)]
+    pub limits: Option<ResourceLimits>,
+}
+
+impl ScheduleWindow {
+    /// Returns whether the window covers the given day and minute of the day.
+    pub fn contains(&self, day: Weekday, minute: u32) -> bool {
+        let starts_on = |day: Weekday| self.days.is_empty() || self.days.contains(&day);
+        let (start, end) = (self.start.minutes(), self.end.minutes());
+
+        if start < end {
+            starts_on(day) && (start..end).contains(&minute)
+        } else {
+            (starts_on(day) && minute >= start) || (starts_on(day.previous()) && minute < end)
+        }
+    }
+}
+
+/// Time-of-day resource schedule.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ResourceSchedule {
+    /// Windows in order of precedence; outside of all of them the configured mode applies.
+    pub windows: Vec<ScheduleWindow>,
+    /// Offset of the schedule's time zone from UTC in minutes; the local time zone if None.
+    #[serde(default)]
+    pub utc_offset_minutes: Option<i32>,
+}
+
+impl ResourceSchedule {
+    /// Returns the window active at the given time (seconds since the Unix epoch).
+    pub fn active_window(&self, timestamp: u64) -> Option<&ScheduleWindow> {
+        if self.windows.is_empty() {
+            return None;
+        }
+
+        let offset_secs = match self.utc_offset_minutes {
+            Some(minutes) => minutes as i64 * 60,
+            None => {
+                use chrono::{Offset, TimeZone};
+                chrono::Local.timestamp_opt(timestamp as i64, 0).single()
+                    .map(|time| time.offset().fix().local_minus_utc() as i64)
+                    .unwrap_or(0)
+            },
+        };
+        let local = timestamp as i64 + offset_secs;
+        // The Unix epoch was a Thursday
+        let day = Weekday::ALL[(local.div_euclid(86_400) + 3).rem_euclid(7) as usize];
+        let minute = (local.rem_euclid(86_400) / 60) as u32;
+
+        self.windows.iter().find(|window| window.contains(day, minute))
+    }
 }
 
 /// Network configuration.
@@ -81,6 +337,15 @@ pub struct Config {
     pub resource_mode: ResourceMode,
     /// Custom resource limits (only used if resource_mode is Custom).
     pub resource_limits: Option<ResourceLimits>,
+    /// Adaptive throttling thresholds for each resource mode.
+    #[serde(default)]
+    pub throttle: ThrottleSettings,
+    /// Time-of-day schedule overriding the resource mode.
+    #[serde(default)]
+    pub schedule: ResourceSchedule,
+    /// Thermal and power limits on task intake.
+    #[serde(default)]
+    pub power: PowerConfig,
     /// Network configuration.
     pub network: NetworkConfig,
     /// Storage configuration.
@@ -94,6 +359,9 @@ impl Default for Config {
         Self {
             resource_mode: ResourceMode::Medium,
             resource_limits: None,
+            throttle: ThrottleSettings::default(),
+            schedule: ResourceSchedule::default(),
+            power: PowerConfig::default(),
             network: NetworkConfig {
                 port: 4001,
                 bootstrap_nodes: vec![],
@@ -120,7 +388,44 @@ impl Config {
             return false;
         }
 
-        if let Some(limits) = &self.resource_limits {
+        // The schedule may switch to any mode, so the thresholds of every mode must be valid
+        let in_range = |fraction: f32| (0.0..=1.0).contains(&fraction);
+        let throttles = [&self.throttle.light, &self.throttle.medium, &self.throttle.high_performance, &self.throttle.custom];
+        for throttle in throttles {
+            let fractions = [
+                throttle.pause_cpu_load,
+                throttle.resume_cpu_load,
+                throttle.pause_memory_pressure,
+                throttle.resume_memory_pressure,
+                throttle.min_budget,
+            ];
+            if !fractions.into_iter().all(in_range) {
+                return false;
+            }
+            if throttle.resume_cpu_load > throttle.pause_cpu_load || throttle.resume_memory_pressure > throttle.pause_memory_pressure {
+                return false;
+            }
+        }
+
+        if !in_range(self.power.battery_budget) || self.power.resume_margin < 0.0 {
+            return false;
+        }
+        if self.power.cpu_throttle_temperature > self.power.cpu_pause_temperature
+            || self.power.gpu_throttle_temperature > self.power.gpu_pause_temperature {
+            return false;
+        }
+
+        for window in &self.schedule.windows {
+            if !window.start.is_valid() || !window.end.is_valid() {
+                return false;
+            }
+            if window.mode == ResourceMode::Custom && window.limits.is_none() {
+                return false;
+            }
+        }
+
+        let window_limits = self.schedule.windows.iter().filter_map(|window| window.limits.as_ref());
+        for limits in self.resource_limits.iter().chain(window_limits) {
             if limits.cpu_limit < 0.0 || limits.cpu_limit > 1.0 {
                 return false;
             }
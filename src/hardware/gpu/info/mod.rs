@@ -22,6 +22,7 @@ use std::fmt;
 use std::process::Command;
 use std::time::{Duration, Instant};
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 
 // Import platform-specific modules
 mod common; // Add the common module
@@ -33,7 +34,7 @@ mod linux;
 mod macos;
 
 /// GPU information structure with comprehensive details.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct GpuInfo {
     /// GPU model name
     pub name: String,
@@ -142,6 +143,20 @@ impl GpuInfo {
             }
         })
     }
+
+    /// Updates the utilization and free VRAM from a usage sample of this GPU.
+    pub fn apply_usage(&mut self, usage: &GpuUsageInfo) {
+        self.utilization_percent = Some(usage.gpu_usage_percent);
+
+        if usage.total_vram_bytes > 0 {
+            if self.vram_bytes == 0 {
+                self.vram_bytes = usage.total_vram_bytes;
+            }
+            let free_bytes = usage.total_vram_bytes.saturating_sub(usage.used_vram_bytes);
+            self.vram_free_bytes = Some(free_bytes);
+            self.vram_free = Some(format_bytes(free_bytes));
+        }
+    }
 }
 
 impl fmt::Display for GpuInfo {
@@ -447,6 +462,19 @@ pub fn get_gpu_usage() -> Result<GpuUsageInfo, Error> {
     Ok(usage_info)
 }
 
+/// Gets the current GPU temperature in Celsius, if it can be read.
+pub fn get_gpu_temperature() -> Option<f32> {
+    #[cfg(target_os = "linux")]
+    {
+        linux::get_temperature()
+    }
+    
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
+
 /// Gets current usage information for a specific GPU by name.
 pub fn get_gpu_usage_by_name(gpu_name: &str) -> Result<GpuUsageInfo, Error> {
     // First get all GPUs
@@ -457,26 +485,31 @@ pub fn get_gpu_usage_by_name(gpu_name: &str) -> Result<GpuUsageInfo, Error> {
         .find(|info| info.name.contains(gpu_name))
         .ok_or_else(|| Error::Benchmark(format!("GPU with name '{}' not found", gpu_name)))?;
     
-        // Create a usage info struct with basic information
-        let mut usage_info = GpuUsageInfo {
-            name: gpu_info.name.clone(),
-            vendor: gpu_info.vendor.clone(),
-            timestamp: Instant::now(),
-            ..Default::default()
-        };
-        
-        // Get platform-specific usage information
-        #[cfg(target_os = "windows")]
-        windows::get_gpu_usage_by_name(&mut usage_info);
-        
-        #[cfg(target_os = "linux")]
-        linux::get_gpu_usage_by_name(&mut usage_info);
-        
-        #[cfg(target_os = "macos")]
-        macos::get_gpu_usage_by_name(&mut usage_info);
-        
-        Ok(usage_info)
-    }
+    Ok(get_gpu_usage_for(gpu_info))
+}
+
+/// Gets current usage information for an already detected GPU.
+pub fn get_gpu_usage_for(gpu_info: &GpuInfo) -> GpuUsageInfo {
+    // Create a usage info struct with basic information
+    let mut usage_info = GpuUsageInfo {
+        name: gpu_info.name.clone(),
+        vendor: gpu_info.vendor.clone(),
+        timestamp: Instant::now(),
+        ..Default::default()
+    };
+    
+    // Get platform-specific usage information
+    #[cfg(target_os = "windows")]
+    windows::get_gpu_usage_by_name(&mut usage_info);
+    
+    #[cfg(target_os = "linux")]
+    linux::get_gpu_usage_by_name(&mut usage_info);
+    
+    #[cfg(target_os = "macos")]
+    macos::get_gpu_usage_by_name(&mut usage_info);
+    
+    usage_info
+}
     
     /// Monitors GPU usage over a specified duration.
     pub fn monitor_gpu_usage(duration: Duration, sample_interval: Duration) -> Result<super::GpuUsageStats, Error> {
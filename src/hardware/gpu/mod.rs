@@ -176,6 +176,31 @@ pub fn get_usage_by_name(gpu_name: &str) -> Result<GpuUsageInfo, Error> {
     info::get_gpu_usage_by_name(gpu_name)
 }
 
+/// Gets current usage information for an already detected GPU.
+///
+/// Unlike [`get_usage_by_name`], this does not enumerate the GPUs again, so it
+/// is cheap enough to call periodically for GPUs found with [`get_all_info`].
+///
+/// # Arguments
+///
+/// * `gpu_info` - The GPU to get usage information for
+///
+/// # Examples
+///
+/// ```
+/// use catp2p::hardware::gpu;
+///
+/// if let Ok(mut gpus) = gpu::get_all_info() {
+///     for gpu_info in &mut gpus {
+///         let usage = gpu::get_usage_for(gpu_info);
+///         gpu_info.apply_usage(&usage);
+///     }
+/// }
+/// ```
+pub fn get_usage_for(gpu_info: &GpuInfo) -> GpuUsageInfo {
+    info::get_gpu_usage_for(gpu_info)
+}
+
 /// Monitors GPU usage over a specified duration.
 ///
 /// This function monitors the primary GPU's usage over the specified duration,
@@ -47,6 +47,13 @@ pub trait MessageHandler {
     async fn handle_message(&self, peer_id: &PeerId, message: &[u8]) -> Result<Vec<u8>, Error>;
 }
 
+/// A trait for sending request/response messages to peers.
+#[async_trait]
+pub trait MessageSender {
+    /// Sends a message to a peer and waits for its response.
+    async fn send_message(&self, peer_id: &PeerId, message: &[u8]) -> Result<Vec<u8>, Error>;
+}
+
 /// A simple message format for peer communication.
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Message {
@@ -68,12 +75,12 @@ impl Message {
     /// Serializes the message to bytes.
     pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
         serde_json::to_vec(self)
-            .map_err(|e| Error::Serialization(e))
+            .map_err(Error::Serialization)
     }
 
     /// Deserializes a message from bytes.
     pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
         serde_json::from_slice(bytes)
-            .map_err(|e| Error::Serialization(e))
+            .map_err(Error::Serialization)
     }
 }
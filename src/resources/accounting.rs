@@ -0,0 +1,313 @@
+/* Copyright 2025 Joao Guimaraes, Catp2p Project
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Per-task resource accounting.
+//!
+//! In-process work is measured by the CPU time of the thread running it, and
+//! subprocesses through `/proc/<pid>` and the bytes they leave in their scratch
+//! directory. Outside Linux, CPU time falls back to wall-clock time. The memory
+//! of in-process work cannot be told apart from the rest of the node's, so only
+//! subprocesses report their peak memory.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+/// How often the memory of a running subprocess is sampled.
+const PROCESS_SAMPLE_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Resources used by a task.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceUsage {
+    /// CPU time consumed.
+    pub cpu_time: Duration,
+    /// GPU time consumed.
+    pub gpu_time: Duration,
+    /// Peak resident memory in bytes.
+    pub peak_memory: u64,
+    /// Bytes written to disk.
+    pub disk_written: u64,
+}
+
+/// Measures the CPU time of work running on the current thread.
+pub struct ThreadUsageMeter {
+    start_time: Instant,
+    start_cpu: Option<Duration>,
+}
+
+impl ThreadUsageMeter {
+    /// Starts measuring on the current thread.
+    pub fn start() -> Self {
+        Self {
+            start_time: Instant::now(),
+            start_cpu: thread_cpu_time(),
+        }
+    }
+
+    /// Returns the CPU time consumed by the current thread since the meter started,
+    /// or the wall-clock time if the thread's CPU time is not available.
+    ///
+    /// Must be called on the thread the meter was started on.
+    pub fn cpu_time(&self) -> Duration {
+        match (self.start_cpu, thread_cpu_time()) {
+            (Some(start), Some(end)) => end.saturating_sub(start),
+            _ => self.start_time.elapsed(),
+        }
+    }
+}
+
+/// CPU time and peak memory of a process.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProcessStats {
+    /// CPU time of the process and of the children it waited for.
+    pub cpu_time: Duration,
+    /// Peak resident memory in bytes.
+    pub peak_memory: u64,
+}
+
+/// Tracks the resources used by a subprocess while it runs.
+///
+/// The peak memory is sampled periodically, as it is no longer available once
+/// the process has exited. The CPU time is read by [`finish`](Self::finish),
+/// which must be called after the process exited but before it is reaped.
+pub struct ProcessUsageTracker {
+    pid: u32,
+    start_time: Instant,
+    peak_memory: Arc<AtomicU64>,
+    sampler: JoinHandle<()>,
+}
+
+impl ProcessUsageTracker {
+    /// Starts tracking the process with the given ID.
+    pub fn start(pid: u32) -> Self {
+        let peak_memory = Arc::new(AtomicU64::new(0));
+        let sampled_peak = peak_memory.clone();
+        let sampler = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(PROCESS_SAMPLE_INTERVAL);
+            loop {
+                interval.tick().await;
+                match process_stats(pid) {
+                    Some(stats) => {
+                        sampled_peak.fetch_max(stats.peak_memory, Ordering::Relaxed);
+                    },
+                    None => break,
+                }
+            }
+        });
+
+        Self {
+            pid,
+            start_time: Instant::now(),
+            peak_memory,
+            sampler,
+        }
+    }
+
+    /// Stops tracking and returns the resources used by the process.
+    pub fn finish(self) -> ProcessStats {
+        let stats = process_stats(self.pid);
+        let peak_memory = self.peak_memory.load(Ordering::Relaxed);
+
+        match stats {
+            Some(stats) => ProcessStats {
+                cpu_time: stats.cpu_time,
+                peak_memory: peak_memory.max(stats.peak_memory),
+            },
+            None => ProcessStats {
+                cpu_time: self.start_time.elapsed(),
+                peak_memory,
+            },
+        }
+    }
+}
+
+impl Drop for ProcessUsageTracker {
+    fn drop(&mut self) {
+        self.sampler.abort();
+    }
+}
+
+/// Gets the CPU time consumed by the current thread.
+#[cfg(target_os = "linux")]
+pub fn thread_cpu_time() -> Option<Duration> {
+    // The first field of schedstat is the time spent on the CPU in nanoseconds
+    let schedstat = std::fs::read_to_string("/proc/thread-self/schedstat").ok()?;
+    let nanos = schedstat.split_whitespace().next()?.parse::<u64>().ok()?;
+    Some(Duration::from_nanos(nanos))
+}
+
+/// Gets the CPU time consumed by the current thread.
+#[cfg(not(target_os = "linux"))]
+pub fn thread_cpu_time() -> Option<Duration> {
+    None
+}
+
+/// Gets the CPU time and peak memory of a process.
+///
+/// Returns None if the process does not exist. The peak memory of a process that
+/// has exited is reported as 0.
+#[cfg(target_os = "linux")]
+pub fn process_stats(pid: u32) -> Option<ProcessStats> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // SAFETY: sysconf has no memory-safety preconditions.
+    let ticks_per_sec = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    let cpu_time = parse_cpu_time(&stat, ticks_per_sec.max(1) as u64)?;
+
+    let peak_memory = std::fs::read_to_string(format!("/proc/{}/status", pid)).ok()
+        .and_then(|status| parse_peak_memory(&status))
+        .unwrap_or(0);
+
+    Some(ProcessStats {
+        cpu_time,
+        peak_memory,
+    })
+}
+
+/// Gets the CPU time and peak memory of a process.
+#[cfg(not(target_os = "linux"))]
+pub fn process_stats(_pid: u32) -> Option<ProcessStats> {
+    None
+}
+
+/// Returns the total size in bytes of the files under a directory.
+///
+/// Symbolic links are not followed.
+pub fn directory_size(path: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return 0;
+    };
+
+    entries.filter_map(Result::ok)
+        .filter_map(|entry| {
+            let metadata = entry.path().symlink_metadata().ok()?;
+            Some(if metadata.is_dir() {
+                directory_size(&entry.path())
+            } else {
+                metadata.len()
+            })
+        })
+        .sum()
+}
+
+/// Parses the user and system CPU time of a process and of its waited-for
+/// children from the contents of `/proc/<pid>/stat`.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_cpu_time(stat: &str, ticks_per_sec: u64) -> Option<Duration> {
+    let ticks = parse_cpu_ticks(stat)?;
+    Some(Duration::from_nanos(ticks.saturating_mul(1_000_000_000) / ticks_per_sec))
+}
+
+/// Parses the user and system CPU time in clock ticks of a process and of its
+/// waited-for children from the contents of `/proc/<pid>/stat`.
+pub(crate) fn parse_cpu_ticks(stat: &str) -> Option<u64> {
+    // The command name may contain spaces and parentheses, so skip past its closing parenthesis
+    let fields: Vec<&str> = stat.get(stat.rfind(')')? + 1..)?.split_whitespace().collect();
+
+    // utime, stime, cutime and cstime are fields 14 to 17, the state (field 3) being first here
+    fields.get(11..15)?.iter()
+        .map(|field| field.parse::<i64>().ok().map(|ticks| ticks.max(0) as u64))
+        .sum::<Option<u64>>()
+}
+
+/// Parses the peak resident memory in bytes from the contents of `/proc/<pid>/status`.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_peak_memory(status: &str) -> Option<u64> {
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kib = line.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+    Some(kib * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scoring::ScoringSystem;
+    use crate::tasks::cpu::CpuTaskExecutor;
+    use crate::tasks::scheduler::TaskScheduler;
+    use crate::tasks::{Task, TaskPayload, TaskResourceType, TaskStatus};
+
+    fn task(id: &str, data: Vec<u8>) -> Task {
+        Task {
+            id: id.to_string(),
+            resource_type: TaskResourceType::Cpu,
+            data,
+            status: TaskStatus::Pending,
+            created_at: 0,
+            completed_at: None,
+            checkpoint: None,
+            deterministic: false,
+        }
+    }
+
+    #[test]
+    fn test_parse_cpu_time() {
+        let stat = "4242 (odd (name) x) S 1 4242 4242 0 -1 4194304 100 0 0 0 150 50 20 30 20 0 1 0 1000 0 0";
+        assert_eq!(parse_cpu_time(stat, 100), Some(Duration::from_millis(2500)));
+        assert_eq!(parse_cpu_time("4242 (truncated) S 1", 100), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_usage_is_measured_and_scored() {
+        use crate::tasks::cancel::CancellationToken;
+        use crate::tasks::events::ProgressReporter;
+        use crate::tasks::process::{InputMode, ProcessConfig, ProcessTask, ProcessTaskExecutor};
+        use crate::tasks::TaskExecutor;
+
+        let mut executor = ProcessTaskExecutor::new(ProcessConfig {
+            scratch_root: std::env::temp_dir().join(format!("catp2p-accounting-test-{:016x}", rand::random::<u64>())),
+            ..ProcessConfig::default()
+        });
+        executor.allow_command("sh", "/bin/sh");
+        let script = "i=0; while [ $i -lt 100000 ]; do i=$((i+1)); done; head -c 4096 /dev/zero > out.bin";
+        let process_task = ProcessTask {
+            command: "sh".to_string(),
+            args: vec!["-c".to_string(), script.to_string()],
+            input: b"input".to_vec(),
+            input_mode: InputMode::File,
+        };
+        let result = executor.execute(
+            &task("process-1", process_task.to_bytes().unwrap()),
+            &ProgressReporter::disabled("test"),
+            &CancellationToken::new(),
+        ).await.unwrap();
+        let usage = result.usage();
+        assert!(usage.cpu_time >= Duration::from_millis(10), "{:?}", usage);
+        assert!(usage.peak_memory > 0);
+        assert_eq!(usage.disk_written, 4096);
+
+        // Usage of locally executed tasks is credited to the local peer
+        let executor = CpuTaskExecutor::new(1).unwrap();
+        executor.registry().register_typed("spin", |millis: u64| {
+            let meter = ThreadUsageMeter::start();
+            while meter.cpu_time() < Duration::from_millis(millis) {
+                std::hint::spin_loop();
+            }
+            Ok(())
+        }).unwrap();
+        let scoring = Arc::new(ScoringSystem::new());
+        let mut scheduler = TaskScheduler::new(1, Duration::from_secs(5));
+        scheduler.set_cpu_executor(Arc::new(executor));
+        scheduler.set_scoring(scoring.clone(), "local");
+
+        let result = scheduler.execute_locally(&task("spin-1", TaskPayload::typed("spin", &20u64).unwrap().to_bytes().unwrap())).await.unwrap();
+        assert!(result.cpu_time >= Duration::from_millis(20));
+        assert_eq!(result.peak_memory, 0);
+        let contribution = scoring.get_contribution("local").unwrap().unwrap();
+        assert_eq!(contribution.cpu_time_secs, result.cpu_time.as_secs_f64());
+    }
+}
@@ -16,15 +16,175 @@
 //! Resource allocation functionality.
 
 use crate::error::Error;
-use crate::config::{ResourceMode, ResourceLimits};
+use crate::config::{ResourceMode, ResourceLimits, ResourceSchedule};
 use crate::resources::SystemResources;
-use std::sync::{Arc, Mutex};
+use crate::tasks::clock::{Clock, SystemClock};
+use crate::tasks::gpu::GpuTask;
+use crate::tasks::{Task, TaskResourceType};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// Resources requested by or reserved for a task.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceRequest {
+    /// Number of CPU cores.
+    pub cpu_cores: u32,
+    /// Memory in bytes.
+    pub memory: u64,
+    /// Disk space in bytes.
+    pub disk: u64,
+    /// GPU memory in bytes.
+    pub gpu_memory: u64,
+}
+
+impl ResourceRequest {
+    /// Creates a request for the given number of CPU cores only.
+    pub fn cores(cpu_cores: u32) -> Self {
+        Self {
+            cpu_cores,
+            ..Self::default()
+        }
+    }
+    
+    /// Creates the request a task reserves while it runs: a core, plus the GPU
+    /// memory of its buffers for GPU tasks.
+    pub fn for_task(task: &Task) -> Self {
+        let gpu_memory = match task.resource_type {
+            // A task that cannot be decoded fails in the executor without using the GPU
+            TaskResourceType::Gpu => GpuTask::from_bytes(&task.data).map(|gpu_task| gpu_task.gpu_memory()).unwrap_or(0),
+            _ => 0,
+        };
+        
+        Self {
+            gpu_memory,
+            ..Self::cores(1)
+        }
+    }
+    
+    /// Returns whether every resource of the request is within the given amounts.
+    pub fn fits_within(&self, available: &ResourceRequest) -> bool {
+        self.cpu_cores <= available.cpu_cores
+            && self.memory <= available.memory
+            && self.disk <= available.disk
+            && self.gpu_memory <= available.gpu_memory
+    }
+    
+    fn saturating_add(&self, other: &ResourceRequest) -> Self {
+        Self {
+            cpu_cores: self.cpu_cores.saturating_add(other.cpu_cores),
+            memory: self.memory.saturating_add(other.memory),
+            disk: self.disk.saturating_add(other.disk),
+            gpu_memory: self.gpu_memory.saturating_add(other.gpu_memory),
+        }
+    }
+    
+    fn saturating_sub(&self, other: &ResourceRequest) -> Self {
+        Self {
+            cpu_cores: self.cpu_cores.saturating_sub(other.cpu_cores),
+            memory: self.memory.saturating_sub(other.memory),
+            disk: self.disk.saturating_sub(other.disk),
+            gpu_memory: self.gpu_memory.saturating_sub(other.gpu_memory),
+        }
+    }
+}
+
+/// Reserved resources and when the reservation expires (seconds since the Unix epoch).
+struct Reservation {
+    request: ResourceRequest,
+    expires_at: u64,
+}
+
+/// The reservations held by leases.
+#[derive(Default)]
+struct Ledger {
+    next_id: u64,
+    reservations: HashMap<u64, Reservation>,
+}
+
+impl Ledger {
+    /// Removes the expired reservations.
+    fn purge(&mut self, now: u64) {
+        self.reservations.retain(|_, reservation| reservation.expires_at > now);
+    }
+    
+    fn reserved(&self) -> ResourceRequest {
+        self.reservations.values()
+            .fold(ResourceRequest::default(), |total, reservation| total.saturating_add(&reservation.request))
+    }
+}
+
+fn lock_ledger(ledger: &Mutex<Ledger>) -> Result<MutexGuard<'_, Ledger>, Error> {
+    ledger.lock().map_err(|_| {
+        Error::Resource("Failed to lock reservations".to_string())
+    })
+}
+
+/// Returns when a reservation taken at `now` with the given time to live expires.
+fn expiry(now: u64, ttl: Duration) -> u64 {
+    let secs = ttl.as_secs() + u64::from(ttl.subsec_nanos() > 0);
+    now.saturating_add(secs.max(1))
+}
+
+/// Resources reserved from a [`ResourceAllocator`], released when dropped.
+pub struct ResourceLease {
+    id: u64,
+    request: ResourceRequest,
+    ledger: Arc<Mutex<Ledger>>,
+    clock: Arc<dyn Clock + Send + Sync>,
+}
+
+impl ResourceLease {
+    /// Returns the reserved resources.
+    pub fn request(&self) -> ResourceRequest {
+        self.request
+    }
+    
+    /// Extends the lease to expire `ttl` from now.
+    ///
+    /// Returns false if the lease has already expired; its resources may have
+    /// been reserved by someone else since.
+    pub fn renew(&self, ttl: Duration) -> Result<bool, Error> {
+        let now = self.clock.now();
+        let mut ledger = lock_ledger(&self.ledger)?;
+        ledger.purge(now);
+        
+        match ledger.reservations.get_mut(&self.id) {
+            Some(reservation) => {
+                reservation.expires_at = expiry(now, ttl);
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+}
+
+impl Drop for ResourceLease {
+    fn drop(&mut self) {
+        if let Ok(mut ledger) = self.ledger.lock() {
+            ledger.reservations.remove(&self.id);
+        }
+    }
+}
 
 /// A resource allocator that manages system resources.
+///
+/// With a schedule, the mode switches automatically at window boundaries. The
+/// switch only affects admission: tasks admitted under the previous mode keep
+/// running, but no new ones are admitted until usage fits the new budget.
+///
+/// Resources can be reserved with leases, which are accounted atomically against
+/// the budget, so that concurrent admissions cannot overcommit it.
 pub struct ResourceAllocator {
     mode: ResourceMode,
     limits: Option<ResourceLimits>,
+    schedule: ResourceSchedule,
+    clock: Arc<dyn Clock + Send + Sync>,
+    active_mode: Mutex<ResourceMode>,
     current_resources: Arc<Mutex<SystemResources>>,
+    budget: Mutex<f32>,
+    ledger: Arc<Mutex<Ledger>>,
 }
 
 impl ResourceAllocator {
@@ -33,7 +193,12 @@ impl ResourceAllocator {
         Self {
             mode,
             limits,
+            schedule: ResourceSchedule::default(),
+            clock: Arc::new(SystemClock),
+            active_mode: Mutex::new(mode),
             current_resources: Arc::new(Mutex::new(resources)),
+            budget: Mutex::new(1.0),
+            ledger: Arc::new(Mutex::new(Ledger::default())),
         }
     }
     
@@ -57,50 +222,184 @@ impl ResourceAllocator {
         Ok(current.clone())
     }
     
+    /// Sets the time-of-day schedule overriding the mode.
+    pub fn set_schedule(&mut self, schedule: ResourceSchedule) {
+        self.schedule = schedule;
+    }
+    
+    /// Sets the clock used to follow the schedule.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock + Send + Sync>) {
+        self.clock = clock;
+    }
+    
+    /// Gets the mode and limits currently in effect, following the schedule.
+    pub fn active_mode(&self) -> Result<(ResourceMode, Option<ResourceLimits>), Error> {
+        let (mode, limits) = match self.schedule.active_window(self.clock.now()) {
+            Some(window) => (window.mode, window.limits.clone()),
+            None => (self.mode, self.limits.clone()),
+        };
+        
+        let mut active_mode = self.active_mode.lock().map_err(|_| {
+            Error::Resource("Failed to lock active mode".to_string())
+        })?;
+        if *active_mode != mode {
+            log::info!("Switching from {:?} to {:?} resource mode", *active_mode, mode);
+            *active_mode = mode;
+        }
+        
+        Ok((mode, limits))
+    }
+    
+    /// Scales the resources allowed by the mode and limits by a fraction (0.0 - 1.0).
+    ///
+    /// This is how adaptive throttling shrinks the budget while the host is busy.
+    pub fn set_budget(&self, fraction: f32) -> Result<(), Error> {
+        let mut budget = self.budget.lock().map_err(|_| {
+            Error::Resource("Failed to lock budget".to_string())
+        })?;
+        
+        *budget = fraction.clamp(0.0, 1.0);
+        
+        Ok(())
+    }
+    
+    /// Gets the fraction of the resources allowed by the mode and limits that may be used.
+    pub fn get_budget(&self) -> Result<f32, Error> {
+        let budget = self.budget.lock().map_err(|_| {
+            Error::Resource("Failed to lock budget".to_string())
+        })?;
+        
+        Ok(*budget)
+    }
+    
     /// Checks if there are enough resources available for the given requirements.
+    ///
+    /// Resources held by leases are not available.
     pub fn has_enough_resources(&self, cpu_cores: u32, memory: u64, disk: u64) -> Result<bool, Error> {
+        let request = ResourceRequest {
+            cpu_cores,
+            memory,
+            disk,
+            gpu_memory: 0,
+        };
+        
+        Ok(request.fits_within(&self.available()?))
+    }
+    
+    /// Reserves resources until the returned lease is dropped or expires.
+    ///
+    /// The lease expires `ttl` after it was taken or last renewed, so that the
+    /// resources of a task that crashed without dropping it are released.
+    pub fn reserve(&self, request: ResourceRequest, ttl: Duration) -> Result<ResourceLease, Error> {
+        let now = self.clock.now();
+        let mut ledger = lock_ledger(&self.ledger)?;
+        
+        ledger.purge(now);
+        let reserved = ledger.reserved();
+        let available = self.capacity(&reserved)?.saturating_sub(&reserved);
+        if !request.fits_within(&available) {
+            return Err(Error::Resource(format!("Not enough resources to reserve {:?} (available: {:?})", request, available)));
+        }
+        
+        let id = ledger.next_id;
+        ledger.next_id += 1;
+        ledger.reservations.insert(id, Reservation {
+            request,
+            expires_at: expiry(now, ttl),
+        });
+        
+        Ok(ResourceLease {
+            id,
+            request,
+            ledger: self.ledger.clone(),
+            clock: self.clock.clone(),
+        })
+    }
+    
+    /// Gets the resources currently held by leases.
+    pub fn reserved(&self) -> Result<ResourceRequest, Error> {
+        let mut ledger = lock_ledger(&self.ledger)?;
+        ledger.purge(self.clock.now());
+        
+        Ok(ledger.reserved())
+    }
+    
+    /// Gets the resources allowed by the mode, limits and budget that are not held by leases.
+    pub fn available(&self) -> Result<ResourceRequest, Error> {
+        let reserved = self.reserved()?;
+        
+        Ok(self.capacity(&reserved)?.saturating_sub(&reserved))
+    }
+    
+    /// Gets the resources allowed by the active mode and limits, scaled by the budget.
+    ///
+    /// The budget is a share of the total resources rather than of the free ones,
+    /// since the memory and disk used by leased tasks are already reserved. Memory,
+    /// disk and GPU memory are still capped by what is free on the host plus what
+    /// the leases hold, so that usage by other processes counts.
+    fn capacity(&self, reserved: &ResourceRequest) -> Result<ResourceRequest, Error> {
+        let budget = self.get_budget()?;
+        let (mode, limits) = self.active_mode()?;
         let current = self.current_resources.lock().map_err(|_| {
             Error::Resource("Failed to lock resources".to_string())
         })?;
+        let gpu_memory: u64 = current.gpus.iter().map(|gpu| gpu.vram_bytes).sum();
         
         // Calculate available resources based on mode and limits
-        let (available_cpu, available_memory, available_disk) = match self.mode {
+        let (available_cpu, available_memory, available_disk, available_gpu_memory) = match mode {
             ResourceMode::Light => {
                 let cpu_limit = current.cpu_cores / 4;
-                let memory_limit = current.available_memory / 4;
-                let disk_limit = current.available_disk / 4;
-                (cpu_limit, memory_limit, disk_limit)
+                let memory_limit = current.total_memory / 4;
+                let disk_limit = current.total_disk / 4;
+                (cpu_limit, memory_limit, disk_limit, gpu_memory / 4)
             },
             ResourceMode::Medium => {
                 let cpu_limit = current.cpu_cores / 2;
-                let memory_limit = current.available_memory / 2;
-                let disk_limit = current.available_disk / 2;
-                (cpu_limit, memory_limit, disk_limit)
+                let memory_limit = current.total_memory / 2;
+                let disk_limit = current.total_disk / 2;
+                (cpu_limit, memory_limit, disk_limit, gpu_memory / 2)
             },
             ResourceMode::HighPerformance => {
                 let cpu_limit = current.cpu_cores * 3 / 4;
-                let memory_limit = current.available_memory * 3 / 4;
-                let disk_limit = current.available_disk * 3 / 4;
-                (cpu_limit, memory_limit, disk_limit)
+                let memory_limit = current.total_memory * 3 / 4;
+                let disk_limit = current.total_disk * 3 / 4;
+                (cpu_limit, memory_limit, disk_limit, gpu_memory * 3 / 4)
             },
             ResourceMode::Custom => {
-                if let Some(limits) = &self.limits {
+                if let Some(limits) = &limits {
                     let cpu_limit = (current.cpu_cores as f32 * limits.cpu_limit) as u32;
-                    let memory_limit = limits.memory_limit.min(current.available_memory);
-                    let disk_limit = limits.storage_limit.min(current.available_disk);
-                    (cpu_limit, memory_limit, disk_limit)
+                    let memory_limit = limits.memory_limit.min(current.total_memory);
+                    let disk_limit = limits.storage_limit.min(current.total_disk);
+                    let gpu_memory_limit = match limits.gpu_limit {
+                        Some(gpu_limit) => (gpu_memory as f64 * gpu_limit as f64) as u64,
+                        None => gpu_memory / 2,
+                    };
+                    (cpu_limit, memory_limit, disk_limit, gpu_memory_limit)
                 } else {
                     // Default to medium if no limits are specified
                     let cpu_limit = current.cpu_cores / 2;
-                    let memory_limit = current.available_memory / 2;
-                    let disk_limit = current.available_disk / 2;
-                    (cpu_limit, memory_limit, disk_limit)
+                    let memory_limit = current.total_memory / 2;
+                    let disk_limit = current.total_disk / 2;
+                    (cpu_limit, memory_limit, disk_limit, gpu_memory / 2)
                 }
             },
         };
         
-        // Check if the requirements can be met
-        Ok(cpu_cores <= available_cpu && memory <= available_memory && disk <= available_disk)
+        // What is free on the host already excludes what leased tasks use
+        let free_gpu_memory: u64 = current.gpus.iter()
+            .map(|gpu| gpu.vram_free_bytes.unwrap_or(gpu.vram_bytes))
+            .sum();
+        let free_memory = current.available_memory.saturating_add(reserved.memory);
+        let free_disk = current.available_disk.saturating_add(reserved.disk);
+        let free_gpu_memory = free_gpu_memory.saturating_add(reserved.gpu_memory);
+        
+        // Scale down to the current budget
+        Ok(ResourceRequest {
+            cpu_cores: (available_cpu as f32 * budget) as u32,
+            memory: ((available_memory as f64 * budget as f64) as u64).min(free_memory),
+            disk: ((available_disk as f64 * budget as f64) as u64).min(free_disk),
+            gpu_memory: ((available_gpu_memory as f64 * budget as f64) as u64).min(free_gpu_memory),
+        })
     }
     
     /// Sets the resource mode.
@@ -113,3 +412,164 @@ impl ResourceAllocator {
         self.limits = Some(limits);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ScheduleWindow, TimeOfDay, Weekday};
+    use crate::tasks::clock::ManualClock;
+    
+    /// Monday 2024-01-01 00:00 UTC.
+    const MONDAY: u64 = 1_704_067_200;
+    const HOUR: u64 = 3600;
+    
+    fn window(days: Vec<Weekday>, start: u8, end: u8, mode: ResourceMode, limits: Option<ResourceLimits>) -> ScheduleWindow {
+        ScheduleWindow {
+            days,
+            start: TimeOfDay::new(start, 0),
+            end: TimeOfDay::new(end, 0),
+            mode,
+            limits,
+        }
+    }
+    
+    /// Returns the number of cores the allocator admits at the given time.
+    fn cores_at(allocator: &ResourceAllocator, clock: &ManualClock, time: u64) -> usize {
+        clock.set(time);
+        (1..=8).take_while(|cores| allocator.has_enough_resources(*cores, 0, 0).unwrap()).count()
+    }
+    
+    #[test]
+    fn test_mode_follows_schedule() {
+        let resources = SystemResources {
+            cpu_usage: 0.0,
+            cpu_cores: 8,
+            total_memory: 0,
+            available_memory: 0,
+            total_disk: 0,
+            available_disk: 0,
+            gpus: Vec::new(),
+            network_interfaces: Vec::new(),
+            numa_nodes: Vec::new(),
+        };
+        let workdays = vec![Weekday::Monday, Weekday::Tuesday, Weekday::Wednesday, Weekday::Thursday, Weekday::Friday];
+        let full = ResourceLimits {
+            cpu_limit: 1.0,
+            memory_limit: 0,
+            gpu_limit: None,
+            storage_limit: 0,
+            io_limit: None,
+        };
+        let clock = Arc::new(ManualClock::new(MONDAY + 12 * HOUR));
+        let mut allocator = ResourceAllocator::new(ResourceMode::Medium, None, resources);
+        allocator.set_clock(clock.clone());
+        allocator.set_schedule(ResourceSchedule {
+            windows: vec![
+                window(vec![Weekday::Wednesday], 12, 13, ResourceMode::Custom, Some(full)),
+                window(workdays, 9, 17, ResourceMode::Light, None),
+                window(Vec::new(), 22, 6, ResourceMode::HighPerformance, None),
+            ],
+            utc_offset_minutes: Some(0),
+        });
+        
+        assert_eq!(cores_at(&allocator, &clock, MONDAY + 12 * HOUR), 2);
+        assert_eq!(cores_at(&allocator, &clock, MONDAY + 20 * HOUR), 4);
+        assert_eq!(cores_at(&allocator, &clock, MONDAY + 23 * HOUR), 6);
+        // Overnight windows run past midnight
+        assert_eq!(cores_at(&allocator, &clock, MONDAY + 27 * HOUR), 6);
+        // Work hours only apply on workdays
+        assert_eq!(cores_at(&allocator, &clock, MONDAY + 5 * 24 * HOUR + 12 * HOUR), 4);
+        // Earlier windows take precedence
+        assert_eq!(cores_at(&allocator, &clock, MONDAY + 2 * 24 * HOUR + 12 * HOUR), 8);
+        assert_eq!(allocator.active_mode().unwrap().0, ResourceMode::Custom);
+        
+        // Work hours in UTC+2 start at 07:00 UTC
+        allocator.set_schedule(ResourceSchedule {
+            utc_offset_minutes: Some(120),
+            ..allocator.schedule.clone()
+        });
+        assert_eq!(cores_at(&allocator, &clock, MONDAY + 7 * HOUR), 2);
+    }
+    
+    #[test]
+    fn test_leases_are_accounted_and_expire() {
+        let resources = SystemResources {
+            cpu_usage: 0.0,
+            cpu_cores: 8,
+            total_memory: 16 << 30,
+            available_memory: 4 << 30,
+            total_disk: 0,
+            available_disk: 0,
+            gpus: vec![crate::resources::GpuInfo {
+                vram_bytes: 8 << 30,
+                vram_free_bytes: Some(4 << 30),
+                ..Default::default()
+            }],
+            network_interfaces: Vec::new(),
+            numa_nodes: Vec::new(),
+        };
+        let clock = Arc::new(ManualClock::new(MONDAY));
+        let mut allocator = ResourceAllocator::new(ResourceMode::HighPerformance, None, resources);
+        allocator.set_clock(clock.clone());
+        
+        let first = allocator.reserve(ResourceRequest {
+            cpu_cores: 4,
+            memory: 2 << 30,
+            ..Default::default()
+        }, Duration::from_secs(10)).unwrap();
+        let second = allocator.reserve(ResourceRequest::cores(2), Duration::from_secs(60)).unwrap();
+        assert_eq!(allocator.reserved().unwrap(), first.request().saturating_add(&second.request()));
+        // Only what is free on the host, besides what leased tasks use, is available
+        assert_eq!(allocator.available().unwrap(), ResourceRequest {
+            cpu_cores: 0,
+            memory: 4 << 30,
+            disk: 0,
+            gpu_memory: 4 << 30,
+        });
+        assert!(allocator.reserve(ResourceRequest::cores(1), Duration::from_secs(60)).is_err());
+        assert!(!allocator.has_enough_resources(1, 0, 0).unwrap());
+        let gpu = ResourceRequest {
+            gpu_memory: 7 << 30,
+            ..Default::default()
+        };
+        assert!(allocator.reserve(gpu, Duration::from_secs(60)).is_err());
+        // Memory used by other processes is not available
+        let mut busy = allocator.get_resources().unwrap();
+        busy.available_memory = 1 << 30;
+        allocator.update_resources(busy).unwrap();
+        assert_eq!(allocator.available().unwrap().memory, 1 << 30);
+        
+        // GPU tasks reserve their buffers, output buffers twice for read-back
+        let gpu_task = crate::tasks::gpu::GpuTask {
+            shader: String::new(),
+            entry_point: "main".to_string(),
+            workgroups: [1, 1, 1],
+            buffers: vec![crate::tasks::gpu::GpuBufferDescriptor::output(0, 2 << 30)],
+        };
+        let task = Task {
+            id: "gpu".to_string(),
+            resource_type: TaskResourceType::Gpu,
+            data: gpu_task.to_bytes().unwrap(),
+            status: crate::tasks::TaskStatus::Pending,
+            created_at: 0,
+            completed_at: None,
+            checkpoint: None,
+            deterministic: false,
+        };
+        assert_eq!(ResourceRequest::for_task(&task).gpu_memory, 4 << 30);
+        
+        // Dropping a lease releases its resources
+        drop(second);
+        let third = allocator.reserve(ResourceRequest::cores(1), Duration::from_secs(60)).unwrap();
+        
+        // Leases that are not renewed expire
+        clock.advance(Duration::from_secs(5));
+        assert!(third.renew(Duration::from_secs(60)).unwrap());
+        clock.advance(Duration::from_secs(5));
+        assert!(!first.renew(Duration::from_secs(60)).unwrap());
+        assert_eq!(allocator.reserved().unwrap(), ResourceRequest::cores(1));
+        assert_eq!(allocator.available().unwrap().cpu_cores, 5);
+        drop(first);
+        assert_eq!(allocator.reserved().unwrap(), ResourceRequest::cores(1));
+    }
+}
@@ -0,0 +1,393 @@
+/* Copyright 2025 Joao Guimaraes, Catp2p Project
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Enforcement of resource limits with Linux cgroup v2.
+//!
+//! A child cgroup is created for task workers and its `cpu.max`, `memory.max`
+//! and `io.max` are written from the active `ResourceMode` and limits. This
+//! requires the parent cgroup to be delegated to the current user with the
+//! controllers available; otherwise limits are left unenforced. A cgroup other
+//! than the root with processes of its own cannot enable controllers for its
+//! children, so the processes of the parent are first moved into a leaf cgroup
+//! next to the workers. This is only done if they all belong to this process tree.
+
+use crate::config::{ResourceLimits, ResourceMode};
+use crate::error::Error;
+use crate::resources::SystemResources;
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+
+/// The default mount point of the cgroup v2 hierarchy.
+pub const DEFAULT_CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// The `cpu.max` period in microseconds.
+const CPU_PERIOD_MICROS: u64 = 100_000;
+
+/// The controllers used to enforce limits.
+const CONTROLLERS: [&str; 3] = ["cpu", "memory", "io"];
+
+/// The leaf cgroup the processes of the parent cgroup are moved into.
+const MAIN_CGROUP: &str = "catp2p-main";
+
+/// Limits written to a cgroup; None means unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CgroupLimits {
+    /// CPU time in microseconds per 100ms period.
+    pub cpu_quota: Option<u64>,
+    /// Memory limit in bytes.
+    pub memory_max: Option<u64>,
+    /// Read and write bandwidth limit in bytes per second.
+    pub io_bps: Option<u64>,
+}
+
+impl CgroupLimits {
+    /// Computes the limits for a resource mode.
+    ///
+    /// The preset modes allow a quarter, half or three quarters of the cores and
+    /// memory, as the allocator does. Custom limits fall back to medium when unset.
+    pub fn from_mode(mode: ResourceMode, limits: Option<&ResourceLimits>, resources: &SystemResources) -> Self {
+        let fraction = |fraction: f64| {
+            let cores = resources.cpu_cores.max(1) as f64;
+            Self {
+                cpu_quota: Some(((cores * fraction * CPU_PERIOD_MICROS as f64) as u64).max(1_000)),
+                memory_max: Some((resources.total_memory as f64 * fraction) as u64),
+                io_bps: None,
+            }
+        };
+
+        match (mode, limits) {
+            (ResourceMode::Light, _) => fraction(0.25),
+            (ResourceMode::Medium, _) | (ResourceMode::Custom, None) => fraction(0.5),
+            (ResourceMode::HighPerformance, _) => fraction(0.75),
+            (ResourceMode::Custom, Some(limits)) => Self {
+                memory_max: Some(limits.memory_limit),
+                io_bps: limits.io_limit,
+                ..fraction(limits.cpu_limit as f64)
+            },
+        }
+    }
+}
+
+/// Manages the cgroup that task workers run in.
+#[derive(Debug)]
+pub struct CgroupManager {
+    parent: PathBuf,
+    path: PathBuf,
+    io_devices: Vec<String>,
+    controllers: Vec<String>,
+}
+
+impl CgroupManager {
+    /// Creates a new CgroupManager for a child cgroup of the current process's cgroup.
+    pub fn new(name: &str) -> Self {
+        let own = std::fs::read_to_string("/proc/self/cgroup").ok()
+            .and_then(|cgroups| {
+                cgroups.lines()
+                    .find_map(|line| line.strip_prefix("0::").map(|path| path.trim_start_matches('/').to_string()))
+            })
+            .unwrap_or_default();
+
+        Self::new_with_root(Path::new(DEFAULT_CGROUP_ROOT).join(own), name)
+    }
+
+    /// Creates a new CgroupManager for a child cgroup of the given cgroup directory.
+    pub fn new_with_root(parent: impl Into<PathBuf>, name: &str) -> Self {
+        let parent = parent.into();
+        Self {
+            path: parent.join(name),
+            parent,
+            io_devices: Vec::new(),
+            controllers: Vec::new(),
+        }
+    }
+
+    /// Sets the block devices (as `major:minor`) whose bandwidth `io.max` limits.
+    pub fn set_io_devices(&mut self, devices: Vec<String>) {
+        self.io_devices = devices;
+    }
+
+    /// Returns the path of the cgroup.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns whether limits are enforced.
+    pub fn is_enforced(&self) -> bool {
+        !self.controllers.is_empty()
+    }
+
+    /// Creates the cgroup and writes the limits.
+    ///
+    /// Returns false, leaving limits unenforced, if cgroup v2 or delegation of the
+    /// controllers is not available.
+    pub fn setup(&mut self, limits: &CgroupLimits) -> bool {
+        match self.try_setup(limits) {
+            Ok(()) => {
+                log::info!("Enforcing resource limits with cgroup {} ({})", self.path.display(), self.controllers.join(", "));
+                true
+            },
+            Err(e) => {
+                log::info!("Resource limits are not enforced: {}", e);
+                self.controllers.clear();
+                false
+            },
+        }
+    }
+
+    /// Writes new limits to the cgroup.
+    pub fn set_limits(&self, limits: &CgroupLimits) -> Result<(), Error> {
+        let format_max = |value: Option<u64>| value.map_or_else(|| "max".to_string(), |value| value.to_string());
+
+        if self.has_controller("cpu") {
+            self.write("cpu.max", &format!("{} {}", format_max(limits.cpu_quota), CPU_PERIOD_MICROS))?;
+        }
+        if self.has_controller("memory") {
+            self.write("memory.max", &format_max(limits.memory_max))?;
+        }
+        if self.has_controller("io") {
+            for device in &self.io_devices {
+                let bps = format_max(limits.io_bps);
+                self.write("io.max", &format!("{} rbps={} wbps={}", device, bps, bps))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Moves a process into the cgroup.
+    pub fn add_process(&self, pid: u32) -> Result<(), Error> {
+        self.write("cgroup.procs", &pid.to_string())
+    }
+
+    /// Opens `cgroup.procs` for writing, so that a process can move itself into the cgroup.
+    pub(crate) fn open_procs(&self) -> Result<File, Error> {
+        let path = self.path.join("cgroup.procs");
+        OpenOptions::new().write(true).open(&path)
+            .map_err(|e| Error::Resource(format!("Failed to open {}: {}", path.display(), e)))
+    }
+
+    fn try_setup(&mut self, limits: &CgroupLimits) -> Result<(), Error> {
+        let available = std::fs::read_to_string(self.parent.join("cgroup.controllers"))
+            .map_err(|_| Error::Resource(format!("cgroup v2 is not available at {}", self.parent.display())))?;
+        let controllers: Vec<String> = CONTROLLERS.iter()
+            .filter(|controller| available.split_whitespace().any(|name| name == **controller))
+            .map(|controller| controller.to_string())
+            .collect();
+        if controllers.is_empty() {
+            return Err(Error::Resource(format!("No controllers are delegated to {}", self.parent.display())));
+        }
+
+        self.move_parent_processes()?;
+        let enable: Vec<String> = controllers.iter().map(|controller| format!("+{}", controller)).collect();
+        std::fs::write(self.parent.join("cgroup.subtree_control"), enable.join(" "))
+            .map_err(|e| Error::Resource(format!("Failed to enable controllers in {}: {}", self.parent.display(), e)))?;
+        std::fs::create_dir_all(&self.path)
+            .map_err(|e| Error::Resource(format!("Failed to create {}: {}", self.path.display(), e)))?;
+
+        self.controllers = controllers;
+        self.set_limits(limits)
+    }
+
+    /// Moves the processes of the parent cgroup, including this one, into a leaf
+    /// cgroup, since only cgroups without processes can enable controllers.
+    ///
+    /// The root cgroup is exempt and left alone. Fails without moving anything if
+    /// the parent holds processes outside this process tree, and moves the processes
+    /// back if one of them cannot be moved.
+    fn move_parent_processes(&self) -> Result<(), Error> {
+        // The root cgroup has no cgroup.type
+        if !self.parent.join("cgroup.type").exists() {
+            return Ok(());
+        }
+
+        let procs = std::fs::read_to_string(self.parent.join("cgroup.procs")).unwrap_or_default();
+        let pids: Vec<&str> = procs.split_whitespace().collect();
+        if pids.is_empty() {
+            return Ok(());
+        }
+        if let Some(foreign) = pids.iter().find(|pid| !pid.parse().is_ok_and(is_own_process)) {
+            return Err(Error::Resource(format!(
+                "{} holds process {} that does not belong to this process", self.parent.display(), foreign
+            )));
+        }
+
+        let main = self.parent.join(MAIN_CGROUP);
+        std::fs::create_dir_all(&main)
+            .map_err(|e| Error::Resource(format!("Failed to create {}: {}", main.display(), e)))?;
+        // Each write moves a single process
+        for (moved, pid) in pids.iter().enumerate() {
+            if let Err(e) = std::fs::write(main.join("cgroup.procs"), pid) {
+                for pid in &pids[..moved] {
+                    if let Err(e) = std::fs::write(self.parent.join("cgroup.procs"), pid) {
+                        log::warn!("Failed to move process {} back to {}: {}", pid, self.parent.display(), e);
+                    }
+                }
+                return Err(Error::Resource(format!("Failed to move process {} to {}: {}", pid, main.display(), e)));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn has_controller(&self, controller: &str) -> bool {
+        self.controllers.iter().any(|name| name == controller)
+    }
+
+    fn write(&self, file: &str, value: &str) -> Result<(), Error> {
+        let path = self.path.join(file);
+        std::fs::write(&path, value)
+            .map_err(|e| Error::Resource(format!("Failed to write {}: {}", path.display(), e)))
+    }
+}
+
+/// Returns whether a process is this process or one of its descendants.
+fn is_own_process(pid: u32) -> bool {
+    let own = std::process::id();
+    let mut pid = pid;
+    // Bounded in case of a parent cycle while processes exit and PIDs are reused
+    for _ in 0..64 {
+        if pid == own {
+            return true;
+        }
+        if pid <= 1 {
+            return false;
+        }
+        pid = match parent_pid(pid) {
+            Some(parent) => parent,
+            None => return false,
+        };
+    }
+
+    false
+}
+
+/// Reads the parent PID of a process from `/proc/<pid>/stat`.
+fn parent_pid(pid: u32) -> Option<u32> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The command name may contain spaces and parentheses, so skip past the last ')'
+    let fields = &stat[stat.rfind(')')? + 1..];
+    fields.split_whitespace().nth(1)?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(path: &Path) -> String {
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn test_limits_are_written_to_delegated_cgroup() {
+        let resources = SystemResources {
+            cpu_usage: 0.0,
+            cpu_cores: 8,
+            total_memory: 16 << 30,
+            available_memory: 8 << 30,
+            total_disk: 0,
+            available_disk: 0,
+            gpus: Vec::new(),
+            network_interfaces: Vec::new(),
+            numa_nodes: Vec::new(),
+        };
+        let limits = CgroupLimits::from_mode(ResourceMode::Light, None, &resources);
+        assert_eq!(limits.cpu_quota, Some(200_000));
+        assert_eq!(limits.memory_max, Some(4 << 30));
+
+        // Without delegation the limits are not enforced
+        let root = tempfile::tempdir().unwrap();
+        let mut cgroup = CgroupManager::new_with_root(root.path(), "catp2p-workers");
+        assert!(!cgroup.setup(&limits));
+        assert!(!cgroup.is_enforced());
+
+        // Processes outside this process tree are never moved
+        std::fs::write(root.path().join("cgroup.controllers"), "cpuset cpu io memory pids\n").unwrap();
+        std::fs::write(root.path().join("cgroup.type"), "domain\n").unwrap();
+        std::fs::write(root.path().join("cgroup.procs"), format!("{}\n1\n", std::process::id())).unwrap();
+        assert!(!cgroup.setup(&limits));
+        assert!(!root.path().join("catp2p-main").exists());
+
+        // The processes of the parent are moved out so that it can enable controllers
+        std::fs::write(root.path().join("cgroup.procs"), format!("{}\n", std::process::id())).unwrap();
+        cgroup.set_io_devices(vec!["8:0".to_string()]);
+        let custom = ResourceLimits {
+            cpu_limit: 0.5,
+            memory_limit: 1 << 30,
+            gpu_limit: None,
+            storage_limit: 0,
+            io_limit: Some(10 << 20),
+        };
+        assert!(cgroup.setup(&CgroupLimits::from_mode(ResourceMode::Custom, Some(&custom), &resources)));
+        assert_eq!(read(&root.path().join("catp2p-main/cgroup.procs")), std::process::id().to_string());
+        assert_eq!(read(&root.path().join("cgroup.subtree_control")), "+cpu +memory +io");
+        assert_eq!(read(&cgroup.path().join("cpu.max")), "400000 100000");
+        assert_eq!(read(&cgroup.path().join("memory.max")), "1073741824");
+        assert_eq!(read(&cgroup.path().join("io.max")), "8:0 rbps=10485760 wbps=10485760");
+
+        cgroup.set_limits(&CgroupLimits::default()).unwrap();
+        assert_eq!(read(&cgroup.path().join("cpu.max")), "max 100000");
+        assert_eq!(read(&cgroup.path().join("memory.max")), "max");
+
+        // Subprocess tasks move themselves into the cgroup before the command starts
+        #[cfg(target_os = "linux")]
+        {
+            use crate::tasks::process::{InputMode, ProcessConfig, ProcessTask, ProcessTaskExecutor};
+            use crate::tasks::{Task, TaskExecutor, TaskResourceType, TaskStatus};
+
+            std::fs::write(cgroup.path().join("cgroup.procs"), "").unwrap();
+            let mut executor = ProcessTaskExecutor::new(ProcessConfig {
+                scratch_root: root.path().join("scratch"),
+                ..ProcessConfig::default()
+            });
+            executor.allow_command("true", "/bin/true");
+            executor.set_cgroup(std::sync::Arc::new(cgroup));
+            let task = Task {
+                id: "cgroup-1".to_string(),
+                resource_type: TaskResourceType::Cpu,
+                data: ProcessTask {
+                    command: "true".to_string(),
+                    args: Vec::new(),
+                    input: Vec::new(),
+                    input_mode: InputMode::Stdin,
+                }.to_bytes().unwrap(),
+                status: TaskStatus::Pending,
+                created_at: 0,
+                completed_at: None,
+                checkpoint: None,
+                deterministic: false,
+            };
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            runtime.block_on(executor.execute(
+                &task,
+                &crate::tasks::events::ProgressReporter::disabled("test"),
+                &crate::tasks::cancel::CancellationToken::new(),
+            )).unwrap();
+            assert_eq!(read(&root.path().join("catp2p-workers/cgroup.procs")), "0");
+        }
+    }
+
+    #[test]
+    fn test_root_cgroup_processes_are_not_moved() {
+        // The root cgroup may enable controllers while holding processes
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join("cgroup.controllers"), "cpu memory\n").unwrap();
+        std::fs::write(root.path().join("cgroup.procs"), "1\n2\n").unwrap();
+        let mut cgroup = CgroupManager::new_with_root(root.path(), "catp2p-workers");
+
+        assert!(cgroup.setup(&CgroupLimits::default()));
+        assert!(!root.path().join("catp2p-main").exists());
+        assert_eq!(read(&root.path().join("cgroup.procs")), "1\n2\n");
+    }
+}
@@ -0,0 +1,60 @@
+/* Copyright 2025 Joao Guimaraes, Catp2p Project
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! GPU probing for resource reports.
+
+use crate::error::Error;
+use crate::hardware::gpu::{self, GpuInfo};
+use std::sync::OnceLock;
+
+/// A source of information about the GPUs of the system.
+pub trait GpuProbe: Send + Sync {
+    /// Returns every GPU of the system with its current utilization.
+    fn probe(&self) -> Result<Vec<GpuInfo>, Error>;
+}
+
+/// Probes the GPUs through the hardware module.
+///
+/// The GPUs are enumerated once, as that requires creating a wgpu instance;
+/// their utilization and free VRAM are sampled on every probe.
+#[derive(Debug, Default)]
+pub struct HardwareGpuProbe {
+    gpus: OnceLock<Vec<GpuInfo>>,
+}
+
+impl HardwareGpuProbe {
+    /// Creates a new HardwareGpuProbe.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl GpuProbe for HardwareGpuProbe {
+    fn probe(&self) -> Result<Vec<GpuInfo>, Error> {
+        let gpus = self.gpus.get_or_init(|| {
+            gpu::get_all_info().unwrap_or_else(|e| {
+                log::debug!("No GPUs detected: {}", e);
+                Vec::new()
+            })
+        });
+
+        Ok(gpus.iter().map(|gpu_info| {
+            let mut gpu_info = gpu_info.clone();
+            let usage = gpu::get_usage_for(&gpu_info);
+            gpu_info.apply_usage(&usage);
+            gpu_info
+        }).collect())
+    }
+}
@@ -15,11 +15,27 @@
 
 //! Resource monitoring and allocation functionality.
 
-pub mod monitor;
+pub mod accounting;
 pub mod allocation;
+pub mod cgroup;
+pub mod gpu;
+pub mod monitor;
+pub mod power;
+pub mod throttle;
+pub mod topology;
 
+use crate::error::Error;
+use crate::resources::gpu::{GpuProbe, HardwareGpuProbe};
+use crate::resources::topology::{link_speed, numa_nodes, DEFAULT_SYSFS_ROOT};
+use crate::storage::quota::{DiskQuota, DiskUsage};
 use serde::{Deserialize, Serialize};
-use sysinfo::{System, SystemExt, CpuExt, DiskExt}; 
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+use sysinfo::{CpuExt, CpuRefreshKind, DiskExt, NetworkExt, NetworksExt, RefreshKind, System, SystemExt};
+
+pub use crate::hardware::gpu::GpuInfo;
+pub use crate::resources::topology::{NetworkInterface, NumaNode};
 
 /// System resource information.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -36,83 +52,170 @@ pub struct SystemResources {
     pub total_disk: u64,
     /// Available disk space in bytes.
     pub available_disk: u64,
-    /// GPU information, if available.
-    pub gpu_info: Option<GpuInfo>,
+    /// Information about every GPU of the system.
+    #[serde(default)]
+    pub gpus: Vec<GpuInfo>,
+    /// The network interfaces of the system.
+    #[serde(default)]
+    pub network_interfaces: Vec<NetworkInterface>,
+    /// The NUMA nodes of the system; empty if the topology is unknown.
+    #[serde(default)]
+    pub numa_nodes: Vec<NumaNode>,
 }
 
-/// GPU information.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct GpuInfo {
-    /// GPU name.
-    pub name: String,
-    /// GPU usage as a percentage (0.0 - 100.0).
-    pub usage: f32,
-    /// Total GPU memory in bytes.
-    pub total_memory: u64,
-    /// Available GPU memory in bytes.
-    pub available_memory: u64,
+impl SystemResources {
+    /// Collects the current resources of the system.
+    ///
+    /// Only the CPU, memory, disks and networks are refreshed; GPUs that cannot
+    /// be probed are left out of the report. Network rates are averaged since the
+    /// previous sample, and NUMA nodes are as read when the sampler was created.
+    pub(crate) fn collect(sampler: &mut SystemSampler, gpu_probe: &dyn GpuProbe) -> Self {
+        let system = &mut sampler.system;
+        system.refresh_cpu_specifics(CpuRefreshKind::new().with_cpu_usage());
+        system.refresh_memory();
+        system.refresh_disks();
+        system.refresh_networks_list();
+
+        let total_disk: u64 = system.disks().iter()
+            .map(|disk| disk.total_space())
+            .sum();
+        let available_disk: u64 = system.disks().iter()
+            .map(|disk| disk.available_space())
+            .sum();
+
+        let now = Instant::now();
+        let elapsed = now.duration_since(sampler.networks_refreshed_at).as_secs_f64();
+        sampler.networks_refreshed_at = now;
+        let per_sec = |bytes: u64| if elapsed > 0.0 { (bytes as f64 / elapsed) as u64 } else { 0 };
+        let mut network_interfaces: Vec<NetworkInterface> = system.networks().iter()
+            .map(|(name, data)| NetworkInterface {
+                name: name.clone(),
+                speed_mbps: link_speed(Path::new(DEFAULT_SYSFS_ROOT), name),
+                rx_bytes_per_sec: per_sec(data.received()),
+                tx_bytes_per_sec: per_sec(data.transmitted()),
+            })
+            .collect();
+        network_interfaces.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let gpus = gpu_probe.probe().unwrap_or_else(|e| {
+            log::warn!("Failed to probe GPUs: {}", e);
+            Vec::new()
+        });
+
+        Self {
+            cpu_usage: system.global_cpu_info().cpu_usage(),
+            cpu_cores: system.cpus().len() as u32,
+            total_memory: system.total_memory(),
+            available_memory: system.available_memory(),
+            total_disk,
+            available_disk,
+            gpus,
+            network_interfaces,
+            numa_nodes: sampler.numa_nodes.clone(),
+        }
+    }
+
+    /// Returns true if the system has at least one GPU.
+    pub fn has_gpu(&self) -> bool {
+        !self.gpus.is_empty()
+    }
+}
+
+/// A System tracking only what [`SystemResources`] reports.
+///
+/// Unlike `System::new_all`, this does not enumerate processes or users.
+pub(crate) struct SystemSampler {
+    pub(crate) system: System,
+    networks_refreshed_at: Instant,
+    numa_nodes: Vec<NumaNode>,
+}
+
+impl SystemSampler {
+    /// Creates a new SystemSampler.
+    pub(crate) fn new() -> Self {
+        Self {
+            system: System::new_with_specifics(RefreshKind::new()
+                .with_cpu(CpuRefreshKind::new().with_cpu_usage())
+                .with_memory()
+                .with_disks_list()
+                .with_networks_list()
+                .with_networks()),
+            networks_refreshed_at: Instant::now(),
+            // The topology does not change while the node runs
+            numa_nodes: numa_nodes(),
+        }
+    }
 }
 
 /// The main resource manager for CatP2P.
 pub struct ResourceManager {
-    system: System,
-    // Will add more fields as we implement the resource management functionality
+    sampler: SystemSampler,
+    gpu_probe: Arc<dyn GpuProbe>,
+    disk_quota: Option<Arc<DiskQuota>>,
 }
 
 impl ResourceManager {
     /// Creates a new ResourceManager.
     pub fn new() -> Self {
-        let mut system = System::new_all();
-        system.refresh_all();
-        
+        Self::new_with_gpu_probe(Arc::new(HardwareGpuProbe::new()))
+    }
+
+    /// Creates a new ResourceManager that gets GPU information from the given probe.
+    pub fn new_with_gpu_probe(gpu_probe: Arc<dyn GpuProbe>) -> Self {
         Self {
-            system,
+            sampler: SystemSampler::new(),
+            gpu_probe,
+            disk_quota: None,
         }
     }
 
+    /// Sets the probe used to get GPU information.
+    pub fn set_gpu_probe(&mut self, gpu_probe: Arc<dyn GpuProbe>) {
+        self.gpu_probe = gpu_probe;
+    }
+
+    /// Sets the disk quota whose usage is reported and checked.
+    pub fn set_disk_quota(&mut self, disk_quota: Arc<DiskQuota>) {
+        self.disk_quota = Some(disk_quota);
+    }
+
+    /// Gets the disk usage covered by the quota, if one is set.
+    pub fn get_disk_usage(&self) -> Result<Option<DiskUsage>, Error> {
+        self.disk_quota.as_ref()
+            .map(|disk_quota| disk_quota.usage())
+            .transpose()
+    }
+
     /// Gets the current system resources.
     pub fn get_system_resources(&mut self) -> SystemResources {
-        self.system.refresh_all();
-        
-        let cpu_usage = self.system.global_cpu_info().cpu_usage();
-        let cpu_cores = self.system.cpus().len() as u32;
-        
-        let total_memory = self.system.total_memory();
-        let available_memory = self.system.available_memory();
-        
-        let total_disk: u64 = self.system.disks().iter()
-            .map(|disk| disk.total_space())
-            .sum();
-        let available_disk: u64 = self.system.disks().iter()
-            .map(|disk| disk.available_space())
-            .sum();
-        
-        // GPU info is not directly available through sysinfo
-        // We'll need to implement this using wgpu or another library
-        let gpu_info = None;
-        
-        SystemResources {
-            cpu_usage,
-            cpu_cores,
-            total_memory,
-            available_memory,
-            total_disk,
-            available_disk,
-            gpu_info,
-        }
+        SystemResources::collect(&mut self.sampler, self.gpu_probe.as_ref())
     }
 
     /// Checks if the system has enough resources for a given task.
     pub fn has_enough_resources(&mut self, _cpu: f32, memory: u64, disk: u64) -> bool {
-        self.system.refresh_all();
+        let system = &mut self.sampler.system;
+        system.refresh_memory();
+        system.refresh_disks();
         
-        let available_memory = self.system.available_memory();
-        let available_disk: u64 = self.system.disks().iter()
+        let available_memory = system.available_memory();
+        let available_disk: u64 = system.disks().iter()
             .map(|disk| disk.available_space())
             .sum();
         
+        // The disk space left within the quota, if any
+        let quota_left = match &self.disk_quota {
+            Some(disk_quota) => match disk_quota.tracked_usage() {
+                Ok(used) => disk_quota.limit().saturating_sub(used),
+                Err(e) => {
+                    log::warn!("Failed to get disk usage: {}", e);
+                    0
+                },
+            },
+            None => u64::MAX,
+        };
+        
         // Simple check for now
-        available_memory >= memory && available_disk >= disk
+        available_memory >= memory && available_disk >= disk && quota_left >= disk
     }
 }
 
@@ -121,3 +224,70 @@ impl Default for ResourceManager {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hardware::gpu::GpuUsageInfo;
+    use crate::resources::monitor::ResourceMonitor;
+    use std::time::Duration;
+
+    const GB: u64 = 1024 * 1024 * 1024;
+
+    struct StubGpuProbe(Vec<GpuInfo>);
+
+    impl GpuProbe for StubGpuProbe {
+        fn probe(&self) -> Result<Vec<GpuInfo>, Error> {
+            Ok(self.0.clone())
+        }
+    }
+
+    struct FailingGpuProbe;
+
+    impl GpuProbe for FailingGpuProbe {
+        fn probe(&self) -> Result<Vec<GpuInfo>, Error> {
+            Err(Error::Resource("GPU driver unavailable".to_string()))
+        }
+    }
+
+    fn gpu(name: &str, used_vram_bytes: u64, usage_percent: f32) -> GpuInfo {
+        let mut gpu_info = GpuInfo {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        gpu_info.apply_usage(&GpuUsageInfo {
+            name: name.to_string(),
+            total_vram_bytes: 8 * GB,
+            used_vram_bytes,
+            gpu_usage_percent: usage_percent,
+            ..Default::default()
+        });
+        gpu_info
+    }
+
+    #[tokio::test]
+    async fn test_gpus_are_reported_from_probe() {
+        let probe = Arc::new(StubGpuProbe(vec![gpu("Stub A", 2 * GB, 40.0), gpu("Stub B", 0, 0.0)]));
+        let mut manager = ResourceManager::new_with_gpu_probe(probe.clone());
+
+        let resources = manager.get_system_resources();
+        assert!(resources.has_gpu());
+        let names: Vec<&str> = resources.gpus.iter().map(|gpu_info| gpu_info.name.as_str()).collect();
+        assert_eq!(names, ["Stub A", "Stub B"]);
+        assert_eq!(resources.gpus[0].vram_bytes, 8 * GB);
+        assert_eq!(resources.gpus[0].vram_free_bytes, Some(6 * GB));
+        assert_eq!(resources.gpus[0].utilization_percent, Some(40.0));
+
+        // Reports keep every GPU when sent to peers
+        let decoded: SystemResources = serde_json::from_slice(&serde_json::to_vec(&resources).unwrap()).unwrap();
+        assert_eq!(decoded.gpus.len(), 2);
+        assert_eq!(decoded.gpus[1].vram_free_bytes, Some(8 * GB));
+
+        manager.set_gpu_probe(Arc::new(FailingGpuProbe));
+        assert!(!manager.get_system_resources().has_gpu());
+
+        let mut monitor = ResourceMonitor::new_with_gpu_probe(Duration::from_millis(10), probe);
+        let mut updates = monitor.start().unwrap();
+        assert_eq!(updates.recv().await.unwrap().gpus.len(), 2);
+    }
+}
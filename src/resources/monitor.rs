@@ -14,137 +14,393 @@
  */
 
 //! Resource monitoring functionality.
+//!
+//! A [`ResourceMonitor`] samples the system resources in the background,
+//! broadcasts every sample to its subscribers and keeps the most recent ones in
+//! a [`ResourceHistory`] for window queries.
 
 use crate::error::Error;
-use crate::resources::SystemResources;
-// Remove unused import
-// use crate::resources::GpuInfo;
-use sysinfo::{System, SystemExt, CpuExt, DiskExt}; // Added DiskExt
-use std::time::Duration;
-use tokio::sync::mpsc;
+use crate::resources::gpu::{GpuProbe, HardwareGpuProbe};
+use crate::resources::{SystemResources, SystemSampler};
+use crate::tasks::cancel::CancellationToken;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
 use tokio::time;
 
+/// Default number of samples kept in the history.
+pub const DEFAULT_HISTORY_CAPACITY: usize = 600;
+
+/// Number of samples buffered for each subscriber.
+const UPDATES_BUFFER: usize = 16;
+
+/// A resource sample and when it was taken.
+#[derive(Debug, Clone)]
+pub struct ResourceSample {
+    /// When the sample was taken.
+    pub taken_at: Instant,
+    /// The sampled resources.
+    pub resources: SystemResources,
+}
+
+/// The minimum, average and maximum of a metric.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MetricSummary {
+    /// The minimum value.
+    pub min: f64,
+    /// The average value.
+    pub avg: f64,
+    /// The maximum value.
+    pub max: f64,
+}
+
+impl MetricSummary {
+    fn from_values(values: impl Iterator<Item = f64>) -> Option<Self> {
+        let mut count = 0usize;
+        let mut summary = Self {
+            min: f64::MAX,
+            avg: 0.0,
+            max: f64::MIN,
+        };
+        for value in values {
+            count += 1;
+            summary.min = summary.min.min(value);
+            summary.max = summary.max.max(value);
+            summary.avg += value;
+        }
+
+        if count == 0 {
+            return None;
+        }
+        summary.avg /= count as f64;
+
+        Some(summary)
+    }
+}
+
+/// Statistics over the samples of a time window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResourceStats {
+    /// Number of samples in the window.
+    pub sample_count: usize,
+    /// CPU usage as a percentage (0.0 - 100.0).
+    pub cpu_usage: MetricSummary,
+    /// Available memory in bytes.
+    pub available_memory: MetricSummary,
+    /// Available disk space in bytes.
+    pub available_disk: MetricSummary,
+}
+
+/// A fixed-size ring buffer of resource samples.
+#[derive(Debug, Clone)]
+pub struct ResourceHistory {
+    capacity: usize,
+    samples: VecDeque<ResourceSample>,
+}
+
+impl ResourceHistory {
+    /// Creates a new ResourceHistory keeping at most `capacity` samples.
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+
+        Self {
+            capacity,
+            samples: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Sets the number of samples kept, dropping the oldest ones if needed.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.max(1);
+        while self.samples.len() > self.capacity {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Records a sample, dropping the oldest one if the history is full.
+    pub fn record(&mut self, taken_at: Instant, resources: SystemResources) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(ResourceSample {
+            taken_at,
+            resources,
+        });
+    }
+
+    /// Returns the number of samples kept.
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Returns true if no sample has been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Returns the most recent sample.
+    pub fn latest(&self) -> Option<&ResourceSample> {
+        self.samples.back()
+    }
+
+    /// Returns the samples, oldest first.
+    pub fn samples(&self) -> impl Iterator<Item = &ResourceSample> {
+        self.samples.iter()
+    }
+
+    /// Returns statistics over the samples taken within `window` before `now`,
+    /// or None if there are no such samples.
+    pub fn stats(&self, window: Duration, now: Instant) -> Option<ResourceStats> {
+        let in_window: Vec<&SystemResources> = self.samples.iter()
+            .filter(|sample| now.saturating_duration_since(sample.taken_at) <= window)
+            .map(|sample| &sample.resources)
+            .collect();
+
+        Some(ResourceStats {
+            sample_count: in_window.len(),
+            cpu_usage: MetricSummary::from_values(in_window.iter().map(|resources| resources.cpu_usage as f64))?,
+            available_memory: MetricSummary::from_values(in_window.iter().map(|resources| resources.available_memory as f64))?,
+            available_disk: MetricSummary::from_values(in_window.iter().map(|resources| resources.available_disk as f64))?,
+        })
+    }
+}
+
+impl Default for ResourceHistory {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY_CAPACITY)
+    }
+}
+
 /// A resource monitor that periodically checks system resources.
 pub struct ResourceMonitor {
-    system: System,
+    system: SystemSampler,
+    gpu_probe: Arc<dyn GpuProbe>,
     update_interval: Duration,
-    running: bool,
+    history: Arc<Mutex<ResourceHistory>>,
+    updates: broadcast::Sender<SystemResources>,
+    running: Option<(CancellationToken, JoinHandle<()>)>,
 }
 
 impl ResourceMonitor {
     /// Creates a new ResourceMonitor with the given update interval.
     pub fn new(update_interval: Duration) -> Self {
-        let mut system = System::new_all();
-        system.refresh_all();
-        
-        Self {
-            system,
-            update_interval,
-            running: false,
-        }
+        Self::new_with_gpu_probe(update_interval, Arc::new(HardwareGpuProbe::new()))
     }
     
     /// Creates a new ResourceMonitor with a default update interval of 1 second.
     pub fn new_with_default_interval() -> Self {
         Self::new(Duration::from_secs(1))
     }
+
+    /// Creates a new ResourceMonitor that gets GPU information from the given probe.
+    pub fn new_with_gpu_probe(update_interval: Duration, gpu_probe: Arc<dyn GpuProbe>) -> Self {
+        let (updates, _) = broadcast::channel(UPDATES_BUFFER);
+
+        Self {
+            system: SystemSampler::new(),
+            gpu_probe,
+            update_interval,
+            history: Arc::new(Mutex::new(ResourceHistory::default())),
+            updates,
+            running: None,
+        }
+    }
+
+    /// Sets the probe used to get GPU information.
+    ///
+    /// A running monitor keeps using the previous probe until restarted.
+    pub fn set_gpu_probe(&mut self, gpu_probe: Arc<dyn GpuProbe>) {
+        self.gpu_probe = gpu_probe;
+    }
+
+    /// Sets the number of samples kept in the history.
+    pub fn set_history_capacity(&mut self, capacity: usize) -> Result<(), Error> {
+        self.history.lock()
+            .map_err(|_| Error::Resource("Failed to lock resource history".to_string()))?
+            .set_capacity(capacity);
+
+        Ok(())
+    }
     
     /// Gets the current system resources.
     pub fn get_current_resources(&mut self) -> SystemResources {
-        self.system.refresh_all();
-        
-        // Use global_cpu_info() instead of global_processor_info()
-        let cpu_usage = self.system.global_cpu_info().cpu_usage();
-        // Use cpus() to get the list of CPUs
-        let cpu_cores = self.system.cpus().len() as u32;
-        
-        let total_memory = self.system.total_memory();
-        let available_memory = self.system.available_memory();
-        
-        // Add explicit type annotations for sum operations
-        let total_disk: u64 = self.system.disks().iter()
-            .map(|disk| disk.total_space())
-            .sum();
-        let available_disk: u64 = self.system.disks().iter()
-            .map(|disk| disk.available_space())
-            .sum();
-        
-        // GPU info is not directly available through sysinfo
-        // We'll need to implement this using wgpu or another library
-        let gpu_info = None;
-        
-        SystemResources {
-            cpu_usage,
-            cpu_cores,
-            total_memory,
-            available_memory,
-            total_disk,
-            available_disk,
-            gpu_info,
-        }
+        SystemResources::collect(&mut self.system, self.gpu_probe.as_ref())
+    }
+
+    /// Subscribes to the samples taken while the monitor is running.
+    pub fn subscribe(&self) -> broadcast::Receiver<SystemResources> {
+        self.updates.subscribe()
+    }
+
+    /// Returns whether the monitor is running.
+    pub fn is_running(&self) -> bool {
+        self.running.as_ref().is_some_and(|(_, handle)| !handle.is_finished())
+    }
+
+    /// Returns a copy of the sample history.
+    pub fn history(&self) -> Result<ResourceHistory, Error> {
+        Ok(self.history.lock()
+            .map_err(|_| Error::Resource("Failed to lock resource history".to_string()))?
+            .clone())
+    }
+
+    /// Returns statistics over the samples taken within the given window.
+    pub fn stats(&self, window: Duration) -> Result<Option<ResourceStats>, Error> {
+        Ok(self.history.lock()
+            .map_err(|_| Error::Resource("Failed to lock resource history".to_string()))?
+            .stats(window, Instant::now()))
     }
     
-    /// Starts the resource monitor and returns a channel for receiving resource updates.
-    pub fn start(&mut self) -> Result<mpsc::Receiver<SystemResources>, Error> {
-        if self.running {
+    /// Starts the resource monitor and returns a subscription to its samples.
+    pub fn start(&mut self) -> Result<broadcast::Receiver<SystemResources>, Error> {
+        if self.is_running() {
             return Err(Error::Resource("Resource monitor is already running".to_string()));
         }
+
+        let token = CancellationToken::new();
+        let receiver = self.subscribe();
+        let handle = tokio::spawn(run_monitor(
+            self.update_interval,
+            self.gpu_probe.clone(),
+            self.history.clone(),
+            self.updates.clone(),
+            token.clone(),
+        ));
+        self.running = Some((token, handle));
         
-        self.running = true;
-        
-        let (tx, rx) = mpsc::channel(100);
-        let update_interval = self.update_interval;
-        
-        // Clone the system for the monitoring task
-        let mut system = System::new_all();
-        
-        tokio::spawn(async move {
-            let mut interval = time::interval(update_interval);
-            
-            loop {
-                interval.tick().await;
-                
-                system.refresh_all();
-                
-                // Use global_cpu_info() instead of global_processor_info()
-                let cpu_usage = system.global_cpu_info().cpu_usage();
-                // Use cpus() to get the list of CPUs
-                let cpu_cores = system.cpus().len() as u32;
-                
-                let total_memory = system.total_memory();
-                let available_memory = system.available_memory();
-                
-                // Add explicit type annotations for sum operations
-                let total_disk: u64 = system.disks().iter()
-                    .map(|disk| disk.total_space())
-                    .sum();
-                let available_disk: u64 = system.disks().iter()
-                    .map(|disk| disk.available_space())
-                    .sum();
-                
-                // GPU info is not directly available through sysinfo
-                let gpu_info = None;
-                
-                let resources = SystemResources {
-                    cpu_usage,
-                    cpu_cores,
-                    total_memory,
-                    available_memory,
-                    total_disk,
-                    available_disk,
-                    gpu_info,
-                };
-                
-                // Send the resources update, but don't block if the channel is full
-                let _ = tx.try_send(resources);
-            }
-        });
-        
-        Ok(rx)
+        Ok(receiver)
     }
     
     /// Stops the resource monitor.
+    ///
+    /// The background task ends without taking further samples.
     pub fn stop(&mut self) {
-        self.running = false;
+        if let Some((token, _)) = self.running.take() {
+            token.cancel();
+        }
+    }
+}
+
+impl Drop for ResourceMonitor {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+async fn run_monitor(
+    update_interval: Duration,
+    gpu_probe: Arc<dyn GpuProbe>,
+    history: Arc<Mutex<ResourceHistory>>,
+    updates: broadcast::Sender<SystemResources>,
+    token: CancellationToken,
+) {
+    let mut system = SystemSampler::new();
+    let mut interval = time::interval(update_interval);
+
+    loop {
+        tokio::select! {
+            _ = token.cancelled() => break,
+            _ = interval.tick() => {},
+        }
+
+        // Sampling reads procfs and may run GPU tools, so keep it off the async workers
+        let probe = gpu_probe.clone();
+        let sample = tokio::task::spawn_blocking(move || {
+            let resources = SystemResources::collect(&mut system, probe.as_ref());
+            (system, resources)
+        }).await;
+        let resources = match sample {
+            Ok((sampled_system, resources)) => {
+                system = sampled_system;
+                resources
+            },
+            Err(e) => {
+                log::error!("Resource sampling failed: {}", e);
+                break;
+            },
+        };
+        if token.is_cancelled() {
+            break;
+        }
+
+        match history.lock() {
+            Ok(mut history) => history.record(Instant::now(), resources.clone()),
+            Err(_) => {
+                log::error!("Failed to lock resource history");
+                break;
+            },
+        }
+
+        // Having no subscribers is not an error
+        let _ = updates.send(resources);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::GpuInfo;
+
+    struct NoGpus;
+
+    impl GpuProbe for NoGpus {
+        fn probe(&self) -> Result<Vec<GpuInfo>, Error> {
+            Ok(Vec::new())
+        }
+    }
+
+    fn resources(cpu_usage: f32, available_memory: u64) -> SystemResources {
+        SystemResources {
+            cpu_usage,
+            cpu_cores: 4,
+            total_memory: 1000,
+            available_memory,
+            total_disk: 1000,
+            available_disk: 500,
+            gpus: Vec::new(),
+            network_interfaces: Vec::new(),
+            numa_nodes: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_history_and_subscribers() {
+        let start = Instant::now();
+        let mut history = ResourceHistory::new(3);
+        for i in 0..5u64 {
+            history.record(start + Duration::from_secs(i), resources(10.0 * i as f32, 100 * i));
+        }
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.samples().next().unwrap().resources.cpu_usage, 20.0);
+
+        let stats = history.stats(Duration::from_secs(1), start + Duration::from_secs(4)).unwrap();
+        assert_eq!(stats.sample_count, 2);
+        assert_eq!(stats.cpu_usage, MetricSummary { min: 30.0, avg: 35.0, max: 40.0 });
+        assert_eq!(stats.available_memory.avg, 350.0);
+        assert!(history.stats(Duration::from_secs(1), start + Duration::from_secs(10)).is_none());
+
+        let mut monitor = ResourceMonitor::new_with_gpu_probe(Duration::from_millis(5), Arc::new(NoGpus));
+        let mut first = monitor.start().unwrap();
+        let mut second = monitor.subscribe();
+        assert!(monitor.start().is_err());
+        for _ in 0..3 {
+            first.recv().await.unwrap();
+            second.recv().await.unwrap();
+        }
+        assert!(monitor.stats(Duration::from_secs(60)).unwrap().unwrap().sample_count >= 3);
+
+        // No samples are taken once stopped
+        monitor.stop();
+        assert!(!monitor.is_running());
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let sample_count = monitor.history().unwrap().len();
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert_eq!(monitor.history().unwrap().len(), sample_count);
+
+        // The monitor can be restarted
+        let mut restarted = monitor.start().unwrap();
+        restarted.recv().await.unwrap();
     }
 }
@@ -0,0 +1,296 @@
+/* Copyright 2025 Joao Guimaraes, Catp2p Project
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Thermal and power-aware limits on task intake.
+//!
+//! CPU temperatures are read from the thermal zones under `/sys/class/thermal`,
+//! GPU temperatures from the hwmon sensors of `/sys/class/drm` cards, and the
+//! AC and battery status from `/sys/class/power_supply`. The policy is applied
+//! through an [`AdaptiveThrottle`](crate::resources::throttle::AdaptiveThrottle).
+
+use crate::config::PowerConfig;
+use crate::hardware::gpu::info::get_gpu_temperature;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The default sysfs mount point.
+pub const DEFAULT_SYSFS_ROOT: &str = "/sys";
+
+/// Thermal and power readings.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PowerReadings {
+    /// Temperature of the hottest thermal zone, in Celsius.
+    pub cpu_temperature: Option<f32>,
+    /// Temperature of the hottest GPU, in Celsius.
+    pub gpu_temperature: Option<f32>,
+    /// Whether the system runs on battery power.
+    pub on_battery: bool,
+    /// Lowest battery charge in percent.
+    pub battery_capacity: Option<u8>,
+}
+
+/// The decision of the policy for the last readings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PowerState {
+    /// Whether task intake is paused.
+    pub paused: bool,
+    /// Fraction of the budget that may be used (0.0 - 1.0).
+    pub budget: f32,
+    /// The readings the decision was based on.
+    pub readings: PowerReadings,
+}
+
+/// Limits task intake on thermal limits and on battery power.
+#[derive(Debug)]
+pub struct PowerPolicy {
+    config: PowerConfig,
+    sysfs_root: PathBuf,
+    query_gpu_driver: AtomicBool,
+    paused: bool,
+}
+
+impl PowerPolicy {
+    /// Creates a new PowerPolicy reading `/sys`.
+    ///
+    /// GPU temperatures not exposed through sysfs are queried from the driver tools,
+    /// until a query finds no GPU.
+    pub fn new(config: PowerConfig) -> Self {
+        Self {
+            query_gpu_driver: AtomicBool::new(true),
+            ..Self::new_with_sysfs_root(config, DEFAULT_SYSFS_ROOT)
+        }
+    }
+
+    /// Creates a new PowerPolicy reading only the given sysfs directory.
+    pub fn new_with_sysfs_root(config: PowerConfig, sysfs_root: impl Into<PathBuf>) -> Self {
+        Self {
+            config,
+            sysfs_root: sysfs_root.into(),
+            query_gpu_driver: AtomicBool::new(false),
+            paused: false,
+        }
+    }
+
+    /// Returns whether task intake is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Reads the current temperatures and power status.
+    pub fn read(&self) -> PowerReadings {
+        let class = self.sysfs_root.join("class");
+
+        let cpu_temperature = max_temperature(
+            subdirectories(&class.join("thermal"))
+                .into_iter()
+                .filter(|zone| zone.file_name().is_some_and(|name| name.to_string_lossy().starts_with("thermal_zone")))
+                .map(|zone| zone.join("temp")),
+        );
+
+        let gpu_sensors = subdirectories(&class.join("drm"))
+            .into_iter()
+            .flat_map(|card| subdirectories(&card.join("device/hwmon")))
+            .map(|hwmon| hwmon.join("temp1_input"));
+        let gpu_temperature = max_temperature(gpu_sensors).or_else(|| self.query_gpu_driver());
+
+        let mut ac_online = None;
+        let mut discharging = false;
+        let mut battery_capacity: Option<u8> = None;
+        for supply in subdirectories(&class.join("power_supply")) {
+            match read_trimmed(&supply.join("type")).as_deref() {
+                Some("Mains") | Some("USB") => {
+                    let online = read_trimmed(&supply.join("online")).as_deref() == Some("1");
+                    ac_online = Some(ac_online.unwrap_or(false) || online);
+                },
+                Some("Battery") => {
+                    discharging |= read_trimmed(&supply.join("status")).as_deref() == Some("Discharging");
+                    if let Some(capacity) = read_trimmed(&supply.join("capacity")).and_then(|capacity| capacity.parse().ok()) {
+                        battery_capacity = Some(battery_capacity.map_or(capacity, |lowest: u8| lowest.min(capacity)));
+                    }
+                },
+                _ => {},
+            }
+        }
+        // Without an AC adapter to check, trust the battery status
+        let on_battery = battery_capacity.is_some() && ac_online.map_or(discharging, |online| !online);
+
+        PowerReadings {
+            cpu_temperature,
+            gpu_temperature,
+            on_battery,
+            battery_capacity,
+        }
+    }
+
+    /// Queries the GPU temperature from the driver tools, which run as subprocesses.
+    ///
+    /// Hosts without a GPU are not queried again after the first failure.
+    fn query_gpu_driver(&self) -> Option<f32> {
+        if !self.query_gpu_driver.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        let temperature = get_gpu_temperature();
+        if temperature.is_none() {
+            log::debug!("No GPU temperature reported by the driver tools, no longer querying them");
+            self.query_gpu_driver.store(false, Ordering::Relaxed);
+        }
+        temperature
+    }
+
+    /// Reads the current temperatures and power status and decides the state.
+    pub fn evaluate(&mut self) -> PowerState {
+        let readings = self.read();
+        self.update(readings)
+    }
+
+    /// Decides the state for the given readings.
+    pub fn update(&mut self, readings: PowerReadings) -> PowerState {
+        let config = &self.config;
+        let margin = if self.paused { config.resume_margin } else { 0.0 };
+        let too_hot = |temperature: Option<f32>, pause: f32| temperature.is_some_and(|temperature| temperature >= pause - margin);
+
+        let battery_low = readings.battery_capacity.is_some_and(|capacity| capacity < config.min_battery_capacity);
+        let paused = too_hot(readings.cpu_temperature, config.cpu_pause_temperature)
+            || too_hot(readings.gpu_temperature, config.gpu_pause_temperature)
+            || (readings.on_battery && (config.pause_on_battery || battery_low));
+
+        let thermal_budget = |temperature: Option<f32>, throttle: f32, pause: f32| match temperature {
+            Some(temperature) if temperature > throttle && pause > throttle => ((pause - temperature) / (pause - throttle)).clamp(0.0, 1.0),
+            Some(temperature) if temperature > throttle => 0.0,
+            _ => 1.0,
+        };
+        let mut budget = thermal_budget(readings.cpu_temperature, config.cpu_throttle_temperature, config.cpu_pause_temperature)
+            .min(thermal_budget(readings.gpu_temperature, config.gpu_throttle_temperature, config.gpu_pause_temperature));
+        if readings.on_battery {
+            budget = budget.min(config.battery_budget);
+        }
+
+        if paused != self.paused {
+            if paused {
+                log::info!("Pausing tasks on thermal or power limits ({:?})", readings);
+            } else {
+                log::info!("Thermal and power limits cleared, resuming tasks");
+            }
+            self.paused = paused;
+        }
+
+        PowerState {
+            paused,
+            budget: if paused { 0.0 } else { budget },
+            readings,
+        }
+    }
+}
+
+/// Lists the subdirectories of a directory, following symbolic links as sysfs uses them.
+fn subdirectories(path: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return Vec::new();
+    };
+
+    entries.filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect()
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    std::fs::read_to_string(path).ok().map(|value| value.trim().to_string())
+}
+
+/// Returns the highest temperature in Celsius of sensors reporting millidegrees.
+fn max_temperature(sensors: impl Iterator<Item = PathBuf>) -> Option<f32> {
+    sensors
+        .filter_map(|sensor| read_trimmed(&sensor)?.parse::<i64>().ok())
+        .map(|millidegrees| millidegrees as f32 / 1000.0)
+        .reduce(f32::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ThrottleSettings;
+    use crate::resources::throttle::{AdaptiveThrottle, HostLoad};
+    use crate::tasks::scheduler::TaskScheduler;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn write(root: &Path, path: &str, value: &str) {
+        let path = root.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, value).unwrap();
+    }
+
+    #[test]
+    fn test_thermal_and_battery_limits() {
+        let sysfs = tempfile::tempdir().unwrap();
+        let root = sysfs.path();
+        write(root, "class/thermal/thermal_zone0/temp", "50000\n");
+        write(root, "class/thermal/thermal_zone1/temp", "45000\n");
+        write(root, "class/drm/card0/device/hwmon/hwmon3/temp1_input", "60000\n");
+        write(root, "class/power_supply/AC/type", "Mains\n");
+        write(root, "class/power_supply/AC/online", "1\n");
+        write(root, "class/power_supply/BAT0/type", "Battery\n");
+        write(root, "class/power_supply/BAT0/status", "Charging\n");
+        write(root, "class/power_supply/BAT0/capacity", "60\n");
+
+        let mut policy = PowerPolicy::new_with_sysfs_root(PowerConfig::default(), root);
+        let state = policy.evaluate();
+        assert_eq!(state.readings, PowerReadings {
+            cpu_temperature: Some(50.0),
+            gpu_temperature: Some(60.0),
+            on_battery: false,
+            battery_capacity: Some(60),
+        });
+        assert!(!state.paused);
+        assert_eq!(state.budget, 1.0);
+
+        // Throttled between the throttle and pause temperatures
+        write(root, "class/thermal/thermal_zone1/temp", "85000\n");
+        let state = policy.evaluate();
+        assert!(!state.paused);
+        assert_eq!(state.budget, 0.5);
+
+        // Paused at the pause temperature, until it drops by the resume margin
+        write(root, "class/drm/card0/device/hwmon/hwmon3/temp1_input", "92000\n");
+        assert!(policy.evaluate().paused);
+        write(root, "class/drm/card0/device/hwmon/hwmon3/temp1_input", "87000\n");
+        assert!(policy.evaluate().paused);
+        write(root, "class/drm/card0/device/hwmon/hwmon3/temp1_input", "60000\n");
+        write(root, "class/thermal/thermal_zone1/temp", "45000\n");
+        assert!(!policy.evaluate().paused);
+
+        // On battery the budget shrinks, and intake pauses when the battery runs low
+        write(root, "class/power_supply/AC/online", "0\n");
+        write(root, "class/power_supply/BAT0/status", "Discharging\n");
+        let state = policy.evaluate();
+        assert!(state.readings.on_battery && !state.paused);
+        assert_eq!(state.budget, 0.5);
+        write(root, "class/power_supply/BAT0/capacity", "15\n");
+        let state = policy.evaluate();
+        assert!(state.paused);
+
+        // The throttle applies the policy to the scheduler
+        let scheduler = Arc::new(TaskScheduler::new(4, Duration::from_secs(5)));
+        let mut throttle = AdaptiveThrottle::new(ThrottleSettings::default());
+        throttle.set_scheduler(scheduler.clone());
+        let state = throttle.update(HostLoad::default(), Some(state)).unwrap();
+        assert!(state.paused && scheduler.is_paused());
+        write(root, "class/power_supply/AC/online", "1\n");
+        let state = throttle.update(HostLoad::default(), Some(policy.evaluate())).unwrap();
+        assert!(!state.paused && !scheduler.is_paused());
+    }
+}
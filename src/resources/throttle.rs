@@ -0,0 +1,540 @@
+/* Copyright 2025 Joao Guimaraes, Catp2p Project
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Adaptive throttling while the host is in use.
+//!
+//! The throttle watches the CPU load of other processes, the memory in use and
+//! the time since the last user input, all read from `/proc`. It shrinks the
+//! budget of the scheduler and allocator as the host gets busier, pauses the
+//! scheduler above the configured thresholds, and resumes it once the host is
+//! back below the lower resume thresholds. The thresholds are those of the
+//! allocator's active resource mode, so they follow its schedule. A [`PowerPolicy`]
+//! can further limit the budget on thermal limits and on battery power.
+
+use crate::config::{ResourceMode, ThrottleConfig, ThrottleSettings};
+use crate::error::Error;
+use crate::resources::accounting::parse_cpu_ticks;
+use crate::resources::allocation::ResourceAllocator;
+use crate::resources::power::{PowerPolicy, PowerState};
+use crate::tasks::clock::{Clock, SystemClock};
+use crate::tasks::scheduler::TaskScheduler;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// Load of the host, excluding this process.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct HostLoad {
+    /// CPU load of other processes (0.0 - 1.0).
+    pub cpu_load: f32,
+    /// Fraction of memory in use (0.0 - 1.0).
+    pub memory_pressure: f32,
+    /// Time since the last user input, if input activity can be observed.
+    pub user_idle: Option<Duration>,
+}
+
+/// The decision of the throttle for the last sample.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThrottleState {
+    /// Whether the scheduler is paused.
+    pub paused: bool,
+    /// Fraction of the mode's budget that may be used (0.0 - 1.0).
+    pub budget: f32,
+    /// The load the decision was based on.
+    pub load: HostLoad,
+    /// The decision of the power policy, if any.
+    pub power: Option<PowerState>,
+}
+
+impl Default for ThrottleState {
+    fn default() -> Self {
+        Self {
+            paused: false,
+            budget: 1.0,
+            load: HostLoad::default(),
+            power: None,
+        }
+    }
+}
+
+/// CPU counters from `/proc/stat` and `/proc/self/stat`, in clock ticks.
+#[derive(Debug, Clone, Copy)]
+struct CpuCounters {
+    busy: u64,
+    total: u64,
+    own: u64,
+}
+
+/// Reads the host load from a `/proc` directory.
+///
+/// The CPU load is computed between two reads. The CPU time of subprocesses is
+/// only attributed to this process once they have been reaped, so running
+/// process tasks count as load of other processes.
+#[derive(Debug)]
+pub struct HostLoadProbe {
+    proc_root: PathBuf,
+    input_interrupts: Vec<String>,
+    last_cpu: Option<CpuCounters>,
+    last_input: Option<(u64, u64)>,
+}
+
+impl HostLoadProbe {
+    /// Creates a new HostLoadProbe reading `/proc`.
+    ///
+    /// User input is detected through the `/proc/interrupts` sources with the given names.
+    pub fn new(input_interrupts: Vec<String>) -> Self {
+        Self::new_with_proc_root("/proc", input_interrupts)
+    }
+
+    /// Creates a new HostLoadProbe reading the given `/proc` directory.
+    pub fn new_with_proc_root(proc_root: impl Into<PathBuf>, input_interrupts: Vec<String>) -> Self {
+        Self {
+            proc_root: proc_root.into(),
+            input_interrupts,
+            last_cpu: None,
+            last_input: None,
+        }
+    }
+
+    /// Sets the `/proc/interrupts` sources that indicate user input.
+    pub fn set_input_interrupts(&mut self, input_interrupts: Vec<String>) {
+        self.input_interrupts = input_interrupts;
+    }
+
+    /// Reads the current host load; `now` is the current time in seconds.
+    ///
+    /// The CPU load is reported as 0 on the first read.
+    pub fn sample(&mut self, now: u64) -> Result<HostLoad, Error> {
+        let cpu = self.read_cpu_counters()?;
+        let cpu_load = match self.last_cpu.replace(cpu) {
+            Some(last) if cpu.total > last.total => {
+                let busy = cpu.busy.saturating_sub(last.busy);
+                let own = cpu.own.saturating_sub(last.own);
+                busy.saturating_sub(own) as f32 / (cpu.total - last.total) as f32
+            },
+            _ => 0.0,
+        };
+
+        let memory_pressure = self.read_memory_pressure()?;
+
+        let user_idle = self.read_input_count().map(|count| {
+            let last_input_at = match self.last_input {
+                Some((last_count, last_input_at)) if last_count == count => last_input_at,
+                _ => now,
+            };
+            self.last_input = Some((count, last_input_at));
+            Duration::from_secs(now.saturating_sub(last_input_at))
+        });
+
+        Ok(HostLoad {
+            cpu_load: cpu_load.clamp(0.0, 1.0),
+            memory_pressure,
+            user_idle,
+        })
+    }
+
+    fn read(&self, path: &str) -> Result<String, Error> {
+        let path = self.proc_root.join(path);
+        std::fs::read_to_string(&path)
+            .map_err(|e| Error::Resource(format!("Failed to read {}: {}", path.display(), e)))
+    }
+
+    fn read_cpu_counters(&self) -> Result<CpuCounters, Error> {
+        let stat = self.read("stat")?;
+        // user nice system idle iowait irq softirq steal; guest time is included in user
+        let ticks: Vec<u64> = stat.lines()
+            .find(|line| line.starts_with("cpu "))
+            .map(|line| line.split_whitespace().skip(1).take(8).filter_map(|field| field.parse().ok()).collect())
+            .unwrap_or_default();
+        if ticks.len() < 5 {
+            return Err(Error::Resource("Invalid /proc/stat".to_string()));
+        }
+
+        let total: u64 = ticks.iter().sum();
+        let idle = ticks[3] + ticks[4];
+        let own = parse_cpu_ticks(&self.read("self/stat")?)
+            .ok_or_else(|| Error::Resource("Invalid /proc/self/stat".to_string()))?;
+
+        Ok(CpuCounters {
+            busy: total - idle,
+            total,
+            own,
+        })
+    }
+
+    fn read_memory_pressure(&self) -> Result<f32, Error> {
+        let meminfo = self.read("meminfo")?;
+        let field = |name: &str| {
+            meminfo.lines()
+                .find(|line| line.split(':').next() == Some(name))
+                .and_then(|line| line.split_whitespace().nth(1)?.parse::<u64>().ok())
+        };
+
+        match (field("MemTotal"), field("MemAvailable")) {
+            (Some(total), Some(available)) if total > 0 => {
+                Ok(1.0 - available.min(total) as f32 / total as f32)
+            },
+            _ => Err(Error::Resource("Invalid /proc/meminfo".to_string())),
+        }
+    }
+
+    /// Sums the interrupt counts of the input sources, or returns None if there are none.
+    fn read_input_count(&self) -> Option<u64> {
+        let interrupts = self.read("interrupts").ok()?;
+        let counts: Vec<u64> = interrupts.lines()
+            .filter(|line| {
+                line.split_whitespace().any(|word| self.input_interrupts.iter().any(|name| name == word))
+            })
+            .map(|line| {
+                line.split_whitespace()
+                    .skip(1)
+                    .map_while(|field| field.parse::<u64>().ok())
+                    .sum()
+            })
+            .collect();
+
+        if counts.is_empty() {
+            None
+        } else {
+            Some(counts.iter().sum())
+        }
+    }
+}
+
+/// Adapts the scheduler and allocator budgets to the load of the host.
+pub struct AdaptiveThrottle {
+    settings: ThrottleSettings,
+    mode: ResourceMode,
+    probe: HostLoadProbe,
+    power: Option<PowerPolicy>,
+    clock: Arc<dyn Clock + Send + Sync>,
+    host_paused: bool,
+    host_budget: f32,
+    state: ThrottleState,
+    scheduler: Option<Arc<TaskScheduler>>,
+    allocator: Option<Arc<ResourceAllocator>>,
+}
+
+impl AdaptiveThrottle {
+    /// Creates a new AdaptiveThrottle reading `/proc`.
+    pub fn new(settings: ThrottleSettings) -> Self {
+        Self::new_with_proc_root(settings, "/proc")
+    }
+
+    /// Creates a new AdaptiveThrottle reading the given `/proc` directory.
+    pub fn new_with_proc_root(settings: ThrottleSettings, proc_root: impl AsRef<Path>) -> Self {
+        let mode = ResourceMode::Medium;
+        let probe = HostLoadProbe::new_with_proc_root(
+            proc_root.as_ref(),
+            settings.for_mode(mode).input_interrupts.clone(),
+        );
+        Self {
+            settings,
+            mode,
+            probe,
+            power: None,
+            clock: Arc::new(SystemClock),
+            host_paused: false,
+            host_budget: 1.0,
+            state: ThrottleState::default(),
+            scheduler: None,
+            allocator: None,
+        }
+    }
+
+    /// Sets the mode whose thresholds are used without an allocator; defaults to medium.
+    pub fn set_mode(&mut self, mode: ResourceMode) {
+        self.mode = mode;
+    }
+
+    /// Sets the scheduler to pause and limit.
+    pub fn set_scheduler(&mut self, scheduler: Arc<TaskScheduler>) {
+        self.scheduler = Some(scheduler);
+    }
+
+    /// Sets the allocator whose budget to scale.
+    ///
+    /// The thresholds of the allocator's active mode are used from then on.
+    pub fn set_allocator(&mut self, allocator: Arc<ResourceAllocator>) {
+        self.allocator = Some(allocator);
+    }
+
+    /// Sets the policy limiting the budget on thermal limits and on battery power.
+    pub fn set_power_policy(&mut self, policy: PowerPolicy) {
+        self.power = Some(policy);
+    }
+
+    /// Sets the clock used to measure user idle time.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock + Send + Sync>) {
+        self.clock = clock;
+    }
+
+    /// Returns the state decided for the last sample.
+    pub fn state(&self) -> ThrottleState {
+        self.state
+    }
+
+    /// Samples the host load and applies the resulting state.
+    pub fn sample(&mut self) -> Result<ThrottleState, Error> {
+        let input_interrupts = &self.active_config()?.input_interrupts;
+        if *input_interrupts != self.probe.input_interrupts {
+            let input_interrupts = input_interrupts.clone();
+            self.probe.set_input_interrupts(input_interrupts);
+        }
+        
+        let load = self.probe.sample(self.clock.now())?;
+        let power = self.power.as_mut().map(PowerPolicy::evaluate);
+        self.update(load, power)
+    }
+
+    /// Decides the state for the given host load and power state, and applies it.
+    ///
+    /// The budget shrinks at once as the load rises, but grows by at most
+    /// `budget_step` per update. Once the user has been idle for `idle_after`,
+    /// the full budget is used. The power state can only lower the budget.
+    pub fn update(&mut self, load: HostLoad, power: Option<PowerState>) -> Result<ThrottleState, Error> {
+        let config = self.active_config()?.clone();
+
+        let paused = if self.host_paused {
+            load.cpu_load > config.resume_cpu_load || load.memory_pressure > config.resume_memory_pressure
+        } else {
+            load.cpu_load >= config.pause_cpu_load || load.memory_pressure >= config.pause_memory_pressure
+        };
+
+        let user_idle = load.user_idle.is_some_and(|idle| idle >= config.idle_after);
+        let target = if user_idle {
+            1.0
+        } else if config.pause_cpu_load > 0.0 {
+            (1.0 - load.cpu_load / config.pause_cpu_load).clamp(config.min_budget, 1.0)
+        } else {
+            config.min_budget
+        };
+        let budget = if paused {
+            config.min_budget
+        } else if user_idle {
+            target
+        } else {
+            target.min(self.host_budget + config.budget_step)
+        };
+
+        if paused != self.host_paused {
+            if paused {
+                log::info!("Host is busy, pausing tasks (CPU load {:.2}, memory pressure {:.2})", load.cpu_load, load.memory_pressure);
+            } else {
+                log::info!("Host load is back to normal, resuming tasks");
+            }
+        }
+
+        self.host_paused = paused;
+        self.host_budget = budget;
+        self.state = ThrottleState {
+            paused: paused || power.is_some_and(|power| power.paused),
+            budget: power.map_or(budget, |power| budget.min(power.budget)),
+            load,
+            power,
+        };
+        self.apply()?;
+
+        Ok(self.state)
+    }
+
+    /// Spawns a background task sampling the host load every `period`.
+    ///
+    /// Sampling reads `/proc` and sysfs and may run the GPU driver tools, so it
+    /// runs on the blocking thread pool.
+    pub fn spawn(self, period: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            let mut throttle = self;
+            loop {
+                interval.tick().await;
+                let sampled = tokio::task::spawn_blocking(move || {
+                    let result = throttle.sample();
+                    (throttle, result)
+                }).await;
+
+                let result;
+                (throttle, result) = match sampled {
+                    Ok(sampled) => sampled,
+                    Err(e) => {
+                        log::warn!("Stopped sampling host load: {}", e);
+                        return;
+                    },
+                };
+                if let Err(e) = result {
+                    log::warn!("Failed to sample host load: {}", e);
+                }
+            }
+        })
+    }
+
+    /// Returns the thresholds of the allocator's active mode.
+    fn active_config(&self) -> Result<&ThrottleConfig, Error> {
+        let mode = match &self.allocator {
+            Some(allocator) => allocator.active_mode()?.0,
+            None => self.mode,
+        };
+
+        Ok(self.settings.for_mode(mode))
+    }
+
+    fn apply(&self) -> Result<(), Error> {
+        if let Some(scheduler) = &self.scheduler {
+            scheduler.set_paused(self.state.paused);
+            scheduler.set_concurrency_budget(self.state.budget);
+        }
+        if let Some(allocator) = &self.allocator {
+            allocator.set_budget(self.state.budget)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ResourceMode;
+    use crate::tasks::clock::ManualClock;
+
+    struct FakeProc {
+        root: tempfile::TempDir,
+        busy: u64,
+        idle: u64,
+        own: u64,
+    }
+
+    impl FakeProc {
+        fn new() -> Self {
+            let proc = Self {
+                root: tempfile::tempdir().unwrap(),
+                busy: 1000,
+                idle: 1000,
+                own: 0,
+            };
+            std::fs::create_dir(proc.root.path().join("self")).unwrap();
+            proc.set_memory(50);
+            proc.set_input(100);
+            proc.write_cpu();
+            proc
+        }
+
+        /// Advances the CPU counters by 100 ticks.
+        fn tick(&mut self, busy: u64, own: u64) {
+            self.busy += busy;
+            self.idle += 100 - busy;
+            self.own += own;
+            self.write_cpu();
+        }
+
+        fn write_cpu(&self) {
+            let stat = format!("cpu  {} 0 0 {} 0 0 0 0 0 0\ncpu0 0 0 0 0 0 0 0 0 0 0\n", self.busy, self.idle);
+            std::fs::write(self.root.path().join("stat"), stat).unwrap();
+            let own = format!("42 (catp2p) S 1 42 42 0 -1 0 0 0 0 0 {} 0 0 0 20 0 1 0 0", self.own);
+            std::fs::write(self.root.path().join("self/stat"), own).unwrap();
+        }
+
+        fn set_memory(&self, used_percent: u64) {
+            let meminfo = format!("MemTotal: 1000 kB\nMemFree: 0 kB\nMemAvailable: {} kB\n", 10 * (100 - used_percent));
+            std::fs::write(self.root.path().join("meminfo"), meminfo).unwrap();
+        }
+
+        fn set_input(&self, count: u64) {
+            let interrupts = format!("           CPU0\n  0:  50  IO-APIC  2-edge  timer\n  1:  {}  IO-APIC  1-edge  i8042\n", count);
+            std::fs::write(self.root.path().join("interrupts"), interrupts).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_pause_and_resume_with_hysteresis() {
+        let mut proc = FakeProc::new();
+        let clock = Arc::new(ManualClock::new(1_000));
+        let scheduler = Arc::new(TaskScheduler::new(4, Duration::from_secs(5)));
+        let mut throttle = AdaptiveThrottle::new_with_proc_root(ThrottleSettings::default(), proc.root.path());
+        throttle.set_clock(clock.clone());
+        throttle.set_scheduler(scheduler.clone());
+
+        assert!(!throttle.sample().unwrap().paused);
+
+        // 90% busy, but most of it is our own work
+        proc.tick(90, 80);
+        let state = throttle.sample().unwrap();
+        assert!((state.load.cpu_load - 0.1).abs() < 1e-3);
+        assert!((state.load.memory_pressure - 0.5).abs() < 1e-3);
+        assert!(!state.paused);
+
+        // Other processes keep the host busy
+        proc.tick(80, 0);
+        let state = throttle.sample().unwrap();
+        assert!(state.paused && scheduler.is_paused());
+        assert_eq!(scheduler.concurrency_limit(), 0);
+
+        // Below the pause threshold but above the resume threshold
+        proc.tick(60, 0);
+        assert!(throttle.sample().unwrap().paused);
+
+        // The budget grows back gradually once resumed
+        proc.tick(10, 0);
+        let state = throttle.sample().unwrap();
+        assert!(!state.paused && !scheduler.is_paused());
+        assert!((state.budget - 0.35).abs() < 1e-3);
+        assert_eq!(scheduler.concurrency_limit(), 2);
+
+        // Memory pressure pauses too
+        proc.set_memory(90);
+        proc.tick(10, 0);
+        assert!(throttle.sample().unwrap().paused);
+        proc.set_memory(50);
+
+        // The full budget is used once the user has been idle long enough
+        clock.advance(Duration::from_secs(300));
+        proc.tick(40, 0);
+        let state = throttle.sample().unwrap();
+        assert_eq!(state.load.user_idle, Some(Duration::from_secs(300)));
+        assert!(!state.paused);
+        assert_eq!(state.budget, 1.0);
+        assert_eq!(scheduler.concurrency_limit(), 4);
+
+        proc.set_input(101);
+        proc.tick(40, 0);
+        let state = throttle.sample().unwrap();
+        assert_eq!(state.load.user_idle, Some(Duration::ZERO));
+        assert!((state.budget - (1.0 - 0.4 / 0.7)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_thresholds_follow_the_active_mode() {
+        let resources = crate::tasks::testing::resources(4);
+        let allocator = Arc::new(ResourceAllocator::new(ResourceMode::Light, None, resources));
+        let mut throttle = AdaptiveThrottle::new(ThrottleSettings::default());
+        let busy = HostLoad {
+            cpu_load: 0.6,
+            ..HostLoad::default()
+        };
+
+        // Medium mode pauses at 70% load
+        assert!(!throttle.update(busy, None).unwrap().paused);
+
+        // Light mode pauses at 50% load, and resumes below 30%
+        throttle.set_allocator(allocator);
+        assert!(throttle.update(busy, None).unwrap().paused);
+        let quieter = HostLoad {
+            cpu_load: 0.4,
+            ..HostLoad::default()
+        };
+        assert!(throttle.update(quieter, None).unwrap().paused);
+    }
+}
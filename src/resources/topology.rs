@@ -0,0 +1,227 @@
+/* Copyright 2025 Joao Guimaraes, Catp2p Project
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Network interfaces and NUMA topology.
+//!
+//! NUMA nodes are read from `/sys/devices/system/node` and link speeds from
+//! `/sys/class/net`. Outside Linux no nodes are reported and link speeds are
+//! unknown. Threads can be pinned to the cores of a node so that their memory
+//! stays local.
+
+use crate::error::Error;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// The default sysfs mount point.
+pub const DEFAULT_SYSFS_ROOT: &str = "/sys";
+
+/// A network interface and its throughput since the previous sample.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkInterface {
+    /// The interface name.
+    pub name: String,
+    /// The link speed in Mbit/s, if known.
+    pub speed_mbps: Option<u64>,
+    /// Bytes received per second.
+    pub rx_bytes_per_sec: u64,
+    /// Bytes transmitted per second.
+    pub tx_bytes_per_sec: u64,
+}
+
+/// A NUMA node.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NumaNode {
+    /// The node ID.
+    pub id: u32,
+    /// The IDs of the CPUs of the node.
+    pub cpus: Vec<usize>,
+    /// Total memory of the node in bytes.
+    pub total_memory: u64,
+    /// Free memory of the node in bytes, when the topology was read.
+    pub free_memory: u64,
+}
+
+/// Gets the NUMA nodes of the system.
+pub fn numa_nodes() -> Vec<NumaNode> {
+    numa_nodes_with_sysfs_root(Path::new(DEFAULT_SYSFS_ROOT))
+}
+
+/// Gets the NUMA nodes described under the given sysfs directory.
+pub fn numa_nodes_with_sysfs_root(sysfs_root: &Path) -> Vec<NumaNode> {
+    let Ok(entries) = std::fs::read_dir(sysfs_root.join("devices/system/node")) else {
+        return Vec::new();
+    };
+
+    let mut nodes: Vec<NumaNode> = entries.filter_map(Result::ok)
+        .filter_map(|entry| {
+            let id = entry.file_name().to_str()?.strip_prefix("node")?.parse().ok()?;
+            let cpus = std::fs::read_to_string(entry.path().join("cpulist")).ok()
+                .map(|cpulist| parse_cpu_list(&cpulist))
+                .unwrap_or_default();
+            let meminfo = std::fs::read_to_string(entry.path().join("meminfo")).unwrap_or_default();
+
+            Some(NumaNode {
+                id,
+                cpus,
+                total_memory: parse_node_meminfo(&meminfo, "MemTotal:").unwrap_or(0),
+                free_memory: parse_node_meminfo(&meminfo, "MemFree:").unwrap_or(0),
+            })
+        })
+        .collect();
+    nodes.sort_by_key(|node| node.id);
+
+    nodes
+}
+
+/// Gets the link speed in Mbit/s of a network interface.
+///
+/// Returns None for interfaces that do not report one, such as loopback or
+/// disconnected links.
+pub fn link_speed(sysfs_root: &Path, interface: &str) -> Option<u64> {
+    let speed = std::fs::read_to_string(sysfs_root.join("class/net").join(interface).join("speed")).ok()?;
+    speed.trim().parse::<i64>().ok()
+        .filter(|speed| *speed > 0)
+        .map(|speed| speed as u64)
+}
+
+/// Pins the current thread to the given CPUs.
+#[cfg(target_os = "linux")]
+pub fn pin_current_thread(cpus: &[usize]) -> Result<(), Error> {
+    // SAFETY: cpu_set_t is plain data, and CPU_SET is only called with indices below CPU_SETSIZE.
+    let result = unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        for &cpu in cpus.iter().filter(|cpu| **cpu < libc::CPU_SETSIZE as usize) {
+            libc::CPU_SET(cpu, &mut set);
+        }
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set)
+    };
+
+    if result != 0 {
+        return Err(Error::Resource(format!(
+            "Failed to pin thread to CPUs {:?}: {}", cpus, std::io::Error::last_os_error(),
+        )));
+    }
+
+    Ok(())
+}
+
+/// Pins the current thread to the given CPUs.
+#[cfg(not(target_os = "linux"))]
+pub fn pin_current_thread(_cpus: &[usize]) -> Result<(), Error> {
+    Err(Error::Resource("Pinning threads to CPUs is only supported on Linux".to_string()))
+}
+
+/// Parses a CPU list such as `0-3,8,10-11`.
+fn parse_cpu_list(cpulist: &str) -> Vec<usize> {
+    cpulist.trim().split(',')
+        .filter(|range| !range.is_empty())
+        .filter_map(|range| match range.split_once('-') {
+            Some((start, end)) => Some(start.parse().ok()?..=end.parse().ok()?),
+            None => {
+                let cpu = range.parse().ok()?;
+                Some(cpu..=cpu)
+            },
+        })
+        .flatten()
+        .collect()
+}
+
+/// Parses a field in bytes from the contents of a node's `meminfo`, whose lines
+/// look like `Node 0 MemTotal:       16281020 kB`.
+fn parse_node_meminfo(meminfo: &str, field: &str) -> Option<u64> {
+    meminfo.lines()
+        .find_map(|line| {
+            let mut words = line.split_whitespace().skip(2);
+            (words.next()? == field).then(|| words.next()?.parse::<u64>().ok())?
+        })
+        .map(|kib| kib * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tasks::cpu::CpuTaskExecutor;
+    use crate::tasks::registry::TaskRegistry;
+    use crate::tasks::{Task, TaskExecutor, TaskPayload, TaskResourceType, TaskStatus};
+    use std::sync::Arc;
+
+    fn write(root: &Path, path: &str, value: &str) {
+        let path = root.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, value).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_numa_nodes_and_pinned_executor() {
+        let sysfs = tempfile::tempdir().unwrap();
+        let root = sysfs.path();
+        write(root, "devices/system/node/node1/cpulist", "4-5,7\n");
+        write(root, "devices/system/node/node1/meminfo", "Node 1 MemTotal:        2048 kB\nNode 1 MemFree:          512 kB\n");
+        write(root, "devices/system/node/node0/cpulist", "0\n");
+        write(root, "devices/system/node/node0/meminfo", "Node 0 MemTotal:        1024 kB\nNode 0 MemFree:         1000 kB\n");
+        write(root, "devices/system/node/possible", "0-1\n");
+        write(root, "class/net/eth0/speed", "1000\n");
+        write(root, "class/net/wlan0/speed", "-1\n");
+
+        let nodes = numa_nodes_with_sysfs_root(root);
+        assert_eq!(nodes, vec![
+            NumaNode { id: 0, cpus: vec![0], total_memory: 1024 * 1024, free_memory: 1000 * 1024 },
+            NumaNode { id: 1, cpus: vec![4, 5, 7], total_memory: 2048 * 1024, free_memory: 512 * 1024 },
+        ]);
+        assert_eq!(link_speed(root, "eth0"), Some(1000));
+        assert_eq!(link_speed(root, "wlan0"), None);
+        assert_eq!(link_speed(root, "lo"), None);
+
+        // Executor threads only run on the cores of their node, here one of the
+        // cores the test may run on
+        #[cfg(target_os = "linux")]
+        let cpu = {
+            // SAFETY: cpu_set_t is plain data, and CPU_ISSET is only called with indices below CPU_SETSIZE.
+            unsafe {
+                let mut set: libc::cpu_set_t = std::mem::zeroed();
+                assert_eq!(libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set), 0);
+                (0..libc::CPU_SETSIZE as usize).find(|cpu| libc::CPU_ISSET(*cpu, &set)).unwrap()
+            }
+        };
+        #[cfg(not(target_os = "linux"))]
+        let cpu = 0;
+        let node = NumaNode { id: 0, cpus: vec![cpu], total_memory: 0, free_memory: 0 };
+        let executor = CpuTaskExecutor::new_on_numa_node(&node, Arc::new(TaskRegistry::new())).unwrap();
+        assert_eq!(executor.cpu_cores(), 1);
+        executor.registry().register_typed("affinity", move |_: ()| {
+            #[cfg(target_os = "linux")]
+            {
+                // SAFETY: sched_getcpu has no preconditions.
+                assert_eq!(unsafe { libc::sched_getcpu() }, cpu as i32);
+            }
+            Ok(())
+        }).unwrap();
+        let task = Task {
+            id: "numa-1".to_string(),
+            resource_type: TaskResourceType::Cpu,
+            data: TaskPayload::typed("affinity", &()).unwrap().to_bytes().unwrap(),
+            status: TaskStatus::Pending,
+            created_at: 0,
+            completed_at: None,
+            checkpoint: None,
+            deterministic: false,
+        };
+        executor.execute(
+            &task,
+            &crate::tasks::events::ProgressReporter::disabled("test"),
+            &crate::tasks::cancel::CancellationToken::new(),
+        ).await.unwrap();
+    }
+}
@@ -18,6 +18,7 @@
 pub mod points;
 
 use crate::error::Error;
+use crate::resources::accounting::ResourceUsage;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
@@ -52,6 +53,9 @@ pub struct Contribution {
     pub network_bytes: u64,
     /// Total points earned.
     pub points: u64,
+    /// Number of results that disagreed with the verified result.
+    #[serde(default)]
+    pub verification_failures: u64,
 }
 
 impl Default for Contribution {
@@ -63,6 +67,7 @@ impl Default for Contribution {
             gpu_time_secs: 0.0,
             network_bytes: 0,
             points: 0,
+            verification_failures: 0,
         }
     }
 }
@@ -75,6 +80,7 @@ pub struct ScoringSystem {
     disk_points_per_gb_hour: u64,
     gpu_points_per_hour: u64,
     network_points_per_gb: u64,
+    verified_task_points: u64,
 }
 
 impl ScoringSystem {
@@ -87,6 +93,7 @@ impl ScoringSystem {
             disk_points_per_gb_hour: 20,
             gpu_points_per_hour: 200,
             network_points_per_gb: 10,
+            verified_task_points: 10,
         }
     }
     
@@ -105,9 +112,15 @@ impl ScoringSystem {
             disk_points_per_gb_hour,
             gpu_points_per_hour,
             network_points_per_gb,
+            verified_task_points: 10,
         }
     }
     
+    /// Sets the points awarded for each verified task run by a peer.
+    pub fn set_verified_task_points(&mut self, points: u64) {
+        self.verified_task_points = points;
+    }
+    
     /// Records a contribution from a peer.
     pub fn record_contribution(
         &self,
@@ -179,6 +192,44 @@ impl ScoringSystem {
         Ok(task_score)
     }
     
+    /// Records a task contribution from a peer using the resources measured for the task.
+    pub fn record_task_usage(&self, peer_id: &str, usage: &ResourceUsage) -> Result<u64, Error> {
+        self.record_task_contribution(
+            peer_id,
+            usage.cpu_time.as_secs_f64(),
+            usage.peak_memory,
+            usage.disk_written,
+            usage.gpu_time.as_secs_f64(),
+        )
+    }
+    
+    /// Records that a peer returned a verified result.
+    ///
+    /// The resources a peer reports for a task cannot be trusted, so a verified task
+    /// earns a fixed number of points. Returns the points awarded.
+    pub fn record_verified_task(&self, peer_id: &str) -> Result<u64, Error> {
+        let mut contributions = self.contributions.lock()
+            .map_err(|_| Error::Other("Failed to lock contributions".to_string()))?;
+        
+        let contribution = contributions.entry(peer_id.to_string()).or_default();
+        contribution.points += self.verified_task_points;
+        
+        Ok(self.verified_task_points)
+    }
+    
+    /// Records that a peer returned a result that failed verification.
+    ///
+    /// Returns the total number of verification failures recorded for the peer.
+    pub fn record_verification_failure(&self, peer_id: &str) -> Result<u64, Error> {
+        let mut contributions = self.contributions.lock()
+            .map_err(|_| Error::Other("Failed to lock contributions".to_string()))?;
+        
+        let contribution = contributions.entry(peer_id.to_string()).or_default();
+        contribution.verification_failures += 1;
+        
+        Ok(contribution.verification_failures)
+    }
+    
     /// Calculates the score for a task.
     fn calculate_task_score(
         &self,
@@ -16,12 +16,15 @@
 //! Database functionality for persisting data.
 
 use crate::error::Error;
+use crate::storage::quota::DiskQuota;
 use sled::Db;
 use std::path::Path;
+use std::sync::Arc;
 
 /// A key-value database wrapper.
 pub struct Database {
     db: Db,
+    quota: Option<Arc<DiskQuota>>,
 }
 
 impl Database {
@@ -32,15 +35,24 @@ impl Database {
         
         Ok(Self {
             db,
+            quota: None,
         })
     }
     
+    /// Sets the disk quota that writes are checked against.
+    ///
+    /// Only trees opened afterwards check their writes.
+    pub fn set_quota(&mut self, quota: Arc<DiskQuota>) {
+        self.quota = Some(quota);
+    }
+    
     /// Stores a key-value pair.
     pub fn put<K, V>(&self, key: K, value: V) -> Result<(), Error>
     where
         K: AsRef<[u8]>,
         V: AsRef<[u8]> + Into<sled::IVec>,
     {
+        check_quota(self.quota.as_deref(), key.as_ref().len() + value.as_ref().len())?;
         self.db.insert(key, value)
             .map_err(|e| Error::Storage(format!("Failed to store data: {}", e)))?;
         self.db.flush()
@@ -80,9 +92,15 @@ impl Database {
         
         Ok(Tree {
             tree,
+            quota: self.quota.clone(),
         })
     }
     
+    /// Returns the underlying sled database.
+    pub(crate) fn handle(&self) -> Db {
+        self.db.clone()
+    }
+    
     /// Closes the database.
     pub fn close(self) -> Result<(), Error> {
         self.db.flush()
@@ -94,6 +112,7 @@ impl Database {
 /// A tree (namespace) in the database.
 pub struct Tree {
     tree: sled::Tree,
+    quota: Option<Arc<DiskQuota>>,
 }
 
 impl Tree {
@@ -103,6 +122,7 @@ impl Tree {
         K: AsRef<[u8]>,
         V: AsRef<[u8]> + Into<sled::IVec>,
     {
+        check_quota(self.quota.as_deref(), key.as_ref().len() + value.as_ref().len())?;
         self.tree.insert(key, value)
             .map_err(|e| Error::Storage(format!("Failed to store data: {}", e)))?;
         self.tree.flush()
@@ -131,4 +151,54 @@ impl Tree {
             .map_err(|e| Error::Storage(format!("Failed to flush tree: {}", e)))?;
         Ok(())
     }
+    
+    /// Stores several key-value pairs atomically.
+    pub fn put_batch<K, V>(&self, entries: Vec<(K, V)>) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]> + Into<sled::IVec>,
+    {
+        let bytes = entries.iter().map(|(key, value)| key.as_ref().len() + value.as_ref().len()).sum();
+        check_quota(self.quota.as_deref(), bytes)?;
+        
+        let mut batch = sled::Batch::default();
+        for (key, value) in entries {
+            batch.insert(key.as_ref(), value);
+        }
+        
+        self.tree.apply_batch(batch)
+            .map_err(|e| Error::Storage(format!("Failed to store batch: {}", e)))?;
+        self.tree.flush()
+            .map_err(|e| Error::Storage(format!("Failed to flush tree: {}", e)))?;
+        Ok(())
+    }
+    
+    /// Iterates over all key-value pairs in the tree, in key order.
+    pub fn iter(&self) -> impl Iterator<Item = Result<(Vec<u8>, Vec<u8>), Error>> + '_ {
+        self.tree.iter().map(|entry| {
+            entry
+                .map(|(key, value)| (key.to_vec(), value.to_vec()))
+                .map_err(|e| Error::Storage(format!("Failed to iterate tree: {}", e)))
+        })
+    }
+    
+    /// Iterates over the key-value pairs whose key starts with the given prefix, in key order.
+    pub fn scan_prefix<P>(&self, prefix: P) -> impl Iterator<Item = Result<(Vec<u8>, Vec<u8>), Error>> + '_
+    where
+        P: AsRef<[u8]>,
+    {
+        self.tree.scan_prefix(prefix).map(|entry| {
+            entry
+                .map(|(key, value)| (key.to_vec(), value.to_vec()))
+                .map_err(|e| Error::Storage(format!("Failed to iterate tree: {}", e)))
+        })
+    }
+}
+
+/// Checks a write of `bytes` bytes against the quota, if any.
+fn check_quota(quota: Option<&DiskQuota>, bytes: usize) -> Result<(), Error> {
+    match quota {
+        Some(quota) => quota.check_write(bytes as u64),
+        None => Ok(()),
+    }
 }
@@ -16,6 +16,7 @@
 //! Storage functionality for persisting data.
 
 pub mod db;
+pub mod quota;
 
 use crate::error::Error;
 use sled::Db;
@@ -0,0 +1,358 @@
+/* Copyright 2025 Joao Guimaraes, Catp2p Project
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Disk quota enforcement.
+//!
+//! The quota covers the database, the blob directory and the scratch directories
+//! of subprocess tasks. Writes that would exceed it are rejected, after evicting
+//! caches to make room. Space freed in the database is only returned once sled
+//! reclaims its segments, so caches are also evicted ahead of time once usage
+//! passes the eviction threshold.
+//!
+//! Measuring usage walks the directories, so writes are checked against the last
+//! measurement plus the writes and evictions since. The usage is measured again
+//! once the measurement is older than the refresh interval, and by
+//! [`DiskQuota::enforce`].
+
+use crate::config::{Config, ResourceMode};
+use crate::error::Error;
+use crate::resources::accounting::directory_size;
+use crate::storage::db::Database;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Default share of the quota above which caches are evicted.
+const DEFAULT_EVICTION_THRESHOLD: f32 = 0.9;
+
+/// Default age after which the tracked usage is measured again.
+const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Disk usage in bytes, by consumer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiskUsage {
+    /// Size of the database on disk.
+    pub database: u64,
+    /// Size of the blob directory.
+    pub blobs: u64,
+    /// Size of the task scratch directories.
+    pub scratch: u64,
+}
+
+impl DiskUsage {
+    /// Returns the total usage.
+    pub fn total(&self) -> u64 {
+        self.database + self.blobs + self.scratch
+    }
+}
+
+/// A cache whose entries can be evicted to free disk space.
+pub trait Evictable: Send + Sync {
+    /// Evicts entries until at least `bytes` bytes are freed or the cache is empty.
+    ///
+    /// Returns the number of bytes freed.
+    fn evict_bytes(&self, bytes: u64) -> Result<u64, Error>;
+}
+
+/// The last measured usage, adjusted by the writes and evictions since.
+#[derive(Debug, Clone, Copy)]
+struct TrackedUsage {
+    total: u64,
+    measured_at: Instant,
+}
+
+/// Enforces a limit on the disk space used by the node.
+pub struct DiskQuota {
+    limit: u64,
+    eviction_threshold: f32,
+    refresh_interval: Duration,
+    tracked: Mutex<Option<TrackedUsage>>,
+    database: Option<sled::Db>,
+    blob_dir: Option<PathBuf>,
+    scratch_root: Option<PathBuf>,
+    caches: Vec<Arc<dyn Evictable>>,
+}
+
+impl DiskQuota {
+    /// Creates a new DiskQuota with the given limit in bytes.
+    pub fn new(limit: u64) -> Self {
+        Self {
+            limit,
+            eviction_threshold: DEFAULT_EVICTION_THRESHOLD,
+            refresh_interval: DEFAULT_REFRESH_INTERVAL,
+            tracked: Mutex::new(None),
+            database: None,
+            blob_dir: None,
+            scratch_root: None,
+            caches: Vec::new(),
+        }
+    }
+
+    /// Creates a new DiskQuota limited by `StorageConfig.max_size`, and by the
+    /// storage limit in custom mode.
+    pub fn from_config(config: &Config) -> Self {
+        let mut limit = config.storage.max_size;
+        if config.resource_mode == ResourceMode::Custom {
+            if let Some(limits) = &config.resource_limits {
+                limit = limit.min(limits.storage_limit);
+            }
+        }
+
+        Self::new(limit)
+    }
+
+    /// Sets the database to account for.
+    pub fn set_database(&mut self, database: &Database) {
+        self.database = Some(database.handle());
+    }
+
+    /// Sets the blob directory to account for.
+    pub fn set_blob_dir(&mut self, path: impl Into<PathBuf>) {
+        self.blob_dir = Some(path.into());
+    }
+
+    /// Sets the directory under which task scratch directories are created.
+    pub fn set_scratch_root(&mut self, path: impl Into<PathBuf>) {
+        self.scratch_root = Some(path.into());
+    }
+
+    /// Adds a cache to evict when usage nears the limit.
+    ///
+    /// Caches are evicted in the order they were added.
+    pub fn add_cache(&mut self, cache: Arc<dyn Evictable>) {
+        self.caches.push(cache);
+    }
+
+    /// Sets the share of the quota (0.0 - 1.0) above which caches are evicted.
+    pub fn set_eviction_threshold(&mut self, threshold: f32) {
+        self.eviction_threshold = threshold.clamp(0.0, 1.0);
+    }
+
+    /// Sets how old the tracked usage may get before writes measure it again.
+    pub fn set_refresh_interval(&mut self, interval: Duration) {
+        self.refresh_interval = interval;
+    }
+
+    /// Returns the limit in bytes.
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    /// Measures the current disk usage, and tracks usage from there.
+    pub fn usage(&self) -> Result<DiskUsage, Error> {
+        let usage = self.measure()?;
+        *self.lock_tracked()? = Some(TrackedUsage {
+            total: usage.total(),
+            measured_at: Instant::now(),
+        });
+
+        Ok(usage)
+    }
+
+    /// Returns the total usage in bytes as tracked since the last measurement,
+    /// measuring it again if the measurement is older than the refresh interval.
+    pub fn tracked_usage(&self) -> Result<u64, Error> {
+        if let Some(tracked) = *self.lock_tracked()? {
+            if tracked.measured_at.elapsed() < self.refresh_interval {
+                return Ok(tracked.total);
+            }
+        }
+
+        Ok(self.usage()?.total())
+    }
+
+    /// Returns how many more bytes can be written before the limit is reached,
+    /// going by the tracked usage.
+    pub fn remaining(&self) -> Result<u64, Error> {
+        Ok(self.limit.saturating_sub(self.tracked_usage()?))
+    }
+
+    /// Checks that `bytes` more bytes can be written, evicting caches if needed.
+    ///
+    /// The bytes are charged against the tracked usage.
+    pub fn check_write(&self, bytes: u64) -> Result<(), Error> {
+        let used = self.tracked_usage()?;
+        let needed = used.saturating_add(bytes);
+        let threshold = self.threshold();
+        if needed > threshold {
+            let freed = self.evict(needed - threshold)?;
+            self.adjust_tracked(|total| total.saturating_sub(freed))?;
+        }
+
+        let mut tracked = self.lock_tracked()?;
+        let tracked = tracked.get_or_insert(TrackedUsage {
+            total: used,
+            measured_at: Instant::now(),
+        });
+        if tracked.total.saturating_add(bytes) > self.limit {
+            return Err(Error::Storage(format!(
+                "Disk quota exceeded: writing {} bytes with {} of {} bytes in use",
+                bytes, tracked.total, self.limit,
+            )));
+        }
+        tracked.total += bytes;
+
+        Ok(())
+    }
+
+    /// Measures the usage and evicts caches if it is above the eviction threshold,
+    /// returning the usage afterwards.
+    pub fn enforce(&self) -> Result<DiskUsage, Error> {
+        let usage = self.usage()?;
+        let threshold = self.threshold();
+        if usage.total() > threshold && self.evict(usage.total() - threshold)? > 0 {
+            return self.usage();
+        }
+
+        Ok(usage)
+    }
+
+    /// Measures the current disk usage without tracking it.
+    fn measure(&self) -> Result<DiskUsage, Error> {
+        let database = match &self.database {
+            Some(database) => database.size_on_disk()
+                .map_err(|e| Error::Storage(format!("Failed to get database size: {}", e)))?,
+            None => 0,
+        };
+
+        Ok(DiskUsage {
+            database,
+            blobs: self.blob_dir.as_deref().map_or(0, directory_size),
+            scratch: self.scratch_root.as_deref().map_or(0, directory_size),
+        })
+    }
+
+    /// Returns the usage in bytes above which caches are evicted.
+    fn threshold(&self) -> u64 {
+        (self.limit as f64 * self.eviction_threshold as f64) as u64
+    }
+
+    /// Applies a change to the tracked usage, if any.
+    fn adjust_tracked(&self, change: impl FnOnce(u64) -> u64) -> Result<(), Error> {
+        if let Some(tracked) = self.lock_tracked()?.as_mut() {
+            tracked.total = change(tracked.total);
+        }
+        Ok(())
+    }
+
+    fn lock_tracked(&self) -> Result<std::sync::MutexGuard<'_, Option<TrackedUsage>>, Error> {
+        self.tracked.lock()
+            .map_err(|_| Error::Storage("Failed to lock tracked disk usage".to_string()))
+    }
+
+    /// Evicts caches until `bytes` bytes are freed, returning how many were.
+    fn evict(&self, bytes: u64) -> Result<u64, Error> {
+        let mut freed = 0;
+        for cache in &self.caches {
+            if freed >= bytes {
+                break;
+            }
+            freed += cache.evict_bytes(bytes - freed)?;
+        }
+
+        if freed > 0 {
+            if let Some(database) = &self.database {
+                database.flush()
+                    .map_err(|e| Error::Storage(format!("Failed to flush database: {}", e)))?;
+            }
+            log::debug!("Evicted {} bytes of cached data to stay within the disk quota", freed);
+        }
+
+        Ok(freed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    /// A cache of files in a directory, evicted in name order.
+    struct FileCache {
+        dir: PathBuf,
+    }
+
+    impl Evictable for FileCache {
+        fn evict_bytes(&self, bytes: u64) -> Result<u64, Error> {
+            let mut files: Vec<PathBuf> = std::fs::read_dir(&self.dir)?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .collect();
+            files.sort();
+
+            let mut freed = 0;
+            for file in files {
+                if freed >= bytes {
+                    break;
+                }
+                freed += std::fs::metadata(&file)?.len();
+                std::fs::remove_file(&file)?;
+            }
+            Ok(freed)
+        }
+    }
+
+    fn write(path: &Path, bytes: usize) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, vec![0u8; bytes]).unwrap();
+    }
+
+    #[test]
+    fn test_writes_are_limited_and_caches_evicted() {
+        let dir = tempfile::tempdir().unwrap();
+        let blobs = dir.path().join("blobs");
+        let scratch = dir.path().join("scratch");
+        write(&blobs.join("a"), 400);
+        write(&blobs.join("b"), 400);
+        write(&scratch.join("task-1/output"), 200);
+
+        let mut database = Database::open(dir.path().join("db")).unwrap();
+        let mut quota = DiskQuota::new(0);
+        quota.set_database(&database);
+        quota.set_blob_dir(&blobs);
+        quota.set_scratch_root(&scratch);
+        let usage = quota.usage().unwrap();
+        assert_eq!((usage.blobs, usage.scratch), (800, 200));
+
+        // Without caches to evict, writes beyond the quota are rejected
+        quota.limit = usage.total() + 500;
+        assert!(quota.check_write(450).is_ok());
+        assert_eq!(quota.tracked_usage().unwrap(), usage.total() + 450);
+        assert!(quota.check_write(100).is_err());
+
+        // Changes made outside the quota are only seen once usage is measured again
+        write(&scratch.join("task-2/output"), 100);
+        assert_eq!(quota.tracked_usage().unwrap(), usage.total() + 450);
+        // The database size changes as sled flushes, so only the files are compared
+        let enforced = quota.enforce().unwrap();
+        assert_eq!((enforced.blobs, enforced.scratch), (800, 300));
+
+        // Evicting caches makes room, and the freed bytes are credited at once
+        quota.limit = enforced.total() + 400;
+        quota.set_eviction_threshold(1.0);
+        quota.add_cache(Arc::new(FileCache { dir: blobs.clone() }));
+        assert!(quota.check_write(600).is_ok());
+        assert_eq!(quota.tracked_usage().unwrap(), enforced.total() + 200);
+        assert_eq!(quota.usage().unwrap().blobs, 400);
+
+        // Database writes are checked against the quota
+        let quota = Arc::new(quota);
+        database.set_quota(quota.clone());
+        let tree = database.open_tree("data").unwrap();
+        assert!(tree.put("small", vec![0u8; 16]).is_ok());
+        assert!(matches!(tree.put("large", vec![0u8; 100_000]), Err(Error::Storage(_))));
+        assert!(tree.get("large").unwrap().is_none());
+    }
+}
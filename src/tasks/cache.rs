@@ -0,0 +1,536 @@
+/* Copyright 2025 Joao Guimaraes, Catp2p Project
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Result caching for deterministic tasks.
+//!
+//! Results of tasks marked as deterministic are kept in a persistent cache keyed by
+//! the hash of what the task computes. Entries expire after a TTL, and the least
+//! recently used entries are evicted once the cache outgrows its share of the
+//! storage budget. Peers caching a result announce it in a [`CacheDirectory`], so
+//! other peers can fetch the result instead of computing it again. A fetched result
+//! is only used once enough providers (two by default) agree on it, and is never
+//! announced again, so a forged result is only used if that many providers collude.
+
+use crate::config::{Config, StorageConfig};
+use crate::error::Error;
+use crate::network::protocol::{Message, MessageHandler, MessageSender};
+use crate::storage::db::{Database, Tree};
+use crate::storage::quota::Evictable;
+use crate::tasks::{content_hash, current_timestamp, ExitReason, Task, TaskResult};
+use async_trait::async_trait;
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Message type for asking a peer for a cached result.
+pub const TASK_CACHE_LOOKUP_MESSAGE: &str = "task-cache-lookup";
+
+/// Name of the database tree holding cached results.
+const RESULT_CACHE_TREE: &str = "result-cache";
+
+/// Default time a cached result stays valid.
+const DEFAULT_RESULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Share of `StorageConfig.max_size` the cache may use by default.
+const DEFAULT_STORAGE_SHARE: u64 = 4;
+
+/// Default number of providers that must return the same result before it is used.
+const DEFAULT_PROVIDER_QUORUM: usize = 2;
+
+/// Computes the cache key of a task from its resource type and data.
+///
+/// The data carries the function kind and its input, so two tasks share a key
+/// exactly when they compute the same thing.
+pub fn cache_key(task: &Task) -> Result<String, Error> {
+    let identity = serde_json::to_vec(&(task.resource_type, &task.data)).map_err(Error::Serialization)?;
+    Ok(content_hash(&identity))
+}
+
+/// Configuration of the result cache.
+#[derive(Debug, Clone)]
+pub struct ResultCacheConfig {
+    /// How long a cached result stays valid.
+    pub ttl: Duration,
+    /// Maximum total size of the cached results in bytes.
+    pub max_size: u64,
+}
+
+impl ResultCacheConfig {
+    /// Creates a configuration using a quarter of the storage budget, leaving the
+    /// rest to checkpoints and other data.
+    pub fn from_storage_config(storage: &StorageConfig) -> Self {
+        Self {
+            ttl: DEFAULT_RESULT_TTL,
+            max_size: storage.max_size / DEFAULT_STORAGE_SHARE,
+        }
+    }
+}
+
+impl Default for ResultCacheConfig {
+    fn default() -> Self {
+        Self::from_storage_config(&Config::default().storage)
+    }
+}
+
+/// A cached task result.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedResult {
+    /// The cache key of the task.
+    pub key: String,
+    /// The task result.
+    pub result: TaskResult,
+    /// When the result was cached (seconds since the Unix epoch).
+    pub created_at: u64,
+    /// Logical time the result was cached, increasing with every insertion.
+    ///
+    /// Later uses are only tracked in memory, so reads do not rewrite the entry.
+    pub last_used: u64,
+}
+
+impl CachedResult {
+    /// Serializes the cached result to bytes.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        serde_json::to_vec(self).map_err(Error::Serialization)
+    }
+
+    /// Deserializes a cached result from bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        serde_json::from_slice(bytes).map_err(Error::Serialization)
+    }
+}
+
+/// A request for a result cached by a peer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheLookupRequest {
+    /// The cache key of the task.
+    pub key: String,
+}
+
+/// A peer's reply to a [`CacheLookupRequest`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheLookupReply {
+    /// The cached result, if the peer has it.
+    pub result: Option<TaskResult>,
+}
+
+/// A directory of which peers cache which results, usually backed by DHT provider records.
+#[async_trait]
+pub trait CacheDirectory {
+    /// Announces that the local peer caches the result with the given key.
+    async fn announce(&self, key: &str) -> Result<(), Error>;
+
+    /// Finds the peers that announced caching the result with the given key.
+    async fn find_providers(&self, key: &str) -> Result<Vec<PeerId>, Error>;
+}
+
+/// A persistent cache of task results.
+pub struct ResultCache {
+    tree: Tree,
+    config: ResultCacheConfig,
+    size: Mutex<u64>,
+    clock: AtomicU64,
+    last_used: Mutex<HashMap<String, u64>>,
+    provider_quorum: usize,
+    directory: Option<Arc<dyn CacheDirectory + Send + Sync>>,
+    sender: Option<Arc<dyn MessageSender + Send + Sync>>,
+}
+
+impl ResultCache {
+    /// Opens the result cache in the given database, dropping expired entries.
+    pub fn new(database: &Database, config: ResultCacheConfig) -> Result<Self, Error> {
+        let cache = Self {
+            tree: database.open_tree(RESULT_CACHE_TREE)?,
+            config,
+            size: Mutex::new(0),
+            clock: AtomicU64::new(0),
+            last_used: Mutex::new(HashMap::new()),
+            provider_quorum: DEFAULT_PROVIDER_QUORUM,
+            directory: None,
+            sender: None,
+        };
+
+        let mut size = 0;
+        let mut clock = 0;
+        for entry in cache.tree.iter() {
+            let (key, value) = entry?;
+            size += (key.len() + value.len()) as u64;
+            let cached = CachedResult::from_bytes(&value)?;
+            clock = clock.max(cached.last_used);
+            cache.lock_last_used()?.insert(cached.key, cached.last_used);
+        }
+        *cache.lock_size()? = size;
+        cache.clock.store(clock, Ordering::SeqCst);
+        cache.purge_expired()?;
+
+        Ok(cache)
+    }
+
+    /// Sets the directory cached results are announced in, and the sender used to
+    /// fetch results from the peers found there.
+    pub fn set_directory(
+        &mut self,
+        directory: Arc<dyn CacheDirectory + Send + Sync>,
+        sender: Arc<dyn MessageSender + Send + Sync>,
+    ) {
+        self.directory = Some(directory);
+        self.sender = Some(sender);
+    }
+
+    /// Sets how many providers must return the same result before a fetched result is used.
+    pub fn set_provider_quorum(&mut self, quorum: usize) {
+        self.provider_quorum = quorum.max(1);
+    }
+
+    /// Returns the total size of the cached results in bytes.
+    pub fn size(&self) -> Result<u64, Error> {
+        Ok(*self.lock_size()?)
+    }
+
+    /// Gets a locally cached result, if it has not expired.
+    pub fn get(&self, key: &str) -> Result<Option<TaskResult>, Error> {
+        let cached = match self.tree.get(key)? {
+            Some(bytes) => CachedResult::from_bytes(&bytes)?,
+            None => return Ok(None),
+        };
+
+        if self.is_expired(&cached) {
+            self.remove(key)?;
+            return Ok(None);
+        }
+
+        let tick = self.tick();
+        self.lock_last_used()?.insert(cached.key, tick);
+
+        Ok(Some(cached.result))
+    }
+
+    /// Gets a cached result, asking the peers that announced it if it is not cached locally.
+    ///
+    /// A result from peers is only used once the provider quorum returned the same
+    /// output. It is then cached locally, but not announced.
+    pub async fn lookup(&self, key: &str) -> Result<Option<TaskResult>, Error> {
+        if let Some(result) = self.get(key)? {
+            return Ok(Some(result));
+        }
+
+        let (directory, sender) = match (&self.directory, &self.sender) {
+            (Some(directory), Some(sender)) => (directory, sender),
+            _ => return Ok(None),
+        };
+
+        let message = Message::new(
+            TASK_CACHE_LOOKUP_MESSAGE.to_string(),
+            serde_json::to_vec(&CacheLookupRequest { key: key.to_string() }).map_err(Error::Serialization)?,
+        ).to_bytes()?;
+
+        // Results agreeing on the output, by the hash of the output
+        let mut agreeing: HashMap<String, (TaskResult, usize)> = HashMap::new();
+        for peer_id in directory.find_providers(key).await? {
+            // Provider records may outlive the entries they announce, so misses are expected
+            let reply = sender.send_message(&peer_id, &message).await
+                .and_then(|bytes| serde_json::from_slice::<CacheLookupReply>(&bytes).map_err(Error::Serialization));
+
+            match reply {
+                Ok(CacheLookupReply { result: Some(result) }) if result.exit_reason == ExitReason::Completed => {
+                    let (result, count) = agreeing.entry(content_hash(&result.output)).or_insert((result, 0));
+                    *count += 1;
+                    if *count >= self.provider_quorum {
+                        let result = result.clone();
+                        self.store(key, &result)?;
+                        return Ok(Some(result));
+                    }
+                },
+                Ok(_) => {},
+                Err(e) => log::debug!("Failed to look up cached result {} on peer {}: {}", key, peer_id, e),
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Caches the result of a task and announces it in the directory.
+    ///
+    /// Only completed results are cached; results larger than the whole cache are skipped.
+    pub async fn insert(&self, key: &str, result: &TaskResult) -> Result<(), Error> {
+        if !self.store(key, result)? {
+            return Ok(());
+        }
+
+        if let Some(directory) = &self.directory {
+            directory.announce(key).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Caches the result of a task without announcing it, returning whether it was cached.
+    fn store(&self, key: &str, result: &TaskResult) -> Result<bool, Error> {
+        if result.exit_reason != ExitReason::Completed {
+            return Ok(false);
+        }
+
+        let cached = CachedResult {
+            key: key.to_string(),
+            result: result.clone(),
+            created_at: current_timestamp(),
+            last_used: self.tick(),
+        };
+        if (key.len() + cached.to_bytes()?.len()) as u64 > self.config.max_size {
+            return Ok(false);
+        }
+
+        self.write(&cached)?;
+        self.lock_last_used()?.insert(cached.key, cached.last_used);
+        self.evict()?;
+
+        Ok(true)
+    }
+
+    /// Removes a cached result.
+    pub fn remove(&self, key: &str) -> Result<(), Error> {
+        if let Some(bytes) = self.tree.get(key)? {
+            self.tree.remove(key)?;
+            self.lock_last_used()?.remove(key);
+            let mut size = self.lock_size()?;
+            *size = size.saturating_sub((key.len() + bytes.len()) as u64);
+        }
+
+        Ok(())
+    }
+
+    /// Removes all expired results, returning how many were removed.
+    pub fn purge_expired(&self) -> Result<usize, Error> {
+        let expired: Vec<String> = self.entries()?.into_iter()
+            .filter(|cached| self.is_expired(cached))
+            .map(|cached| cached.key)
+            .collect();
+
+        for key in &expired {
+            self.remove(key)?;
+        }
+
+        Ok(expired.len())
+    }
+
+    /// Evicts the least recently used results until the cache fits within its maximum size.
+    fn evict(&self) -> Result<(), Error> {
+        if self.size()? <= self.config.max_size {
+            return Ok(());
+        }
+
+        for key in self.least_recently_used()? {
+            if self.size()? <= self.config.max_size {
+                break;
+            }
+            self.remove(&key)?;
+        }
+
+        Ok(())
+    }
+
+    fn entries(&self) -> Result<Vec<CachedResult>, Error> {
+        self.tree.iter()
+            .map(|entry| entry.and_then(|(_, value)| CachedResult::from_bytes(&value)))
+            .collect()
+    }
+
+    /// Returns the keys of the cached results, least recently used first.
+    fn least_recently_used(&self) -> Result<Vec<String>, Error> {
+        let mut keys: Vec<(String, u64)> = self.lock_last_used()?.iter()
+            .map(|(key, last_used)| (key.clone(), *last_used))
+            .collect();
+        keys.sort_by_key(|(_, last_used)| *last_used);
+        Ok(keys.into_iter().map(|(key, _)| key).collect())
+    }
+
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    fn is_expired(&self, cached: &CachedResult) -> bool {
+        cached.created_at.saturating_add(self.config.ttl.as_secs()) <= current_timestamp()
+    }
+
+    /// Writes an entry, keeping track of the cache size.
+    fn write(&self, cached: &CachedResult) -> Result<(), Error> {
+        let bytes = cached.to_bytes()?;
+        let previous = self.tree.get(&cached.key)?.map_or(0, |previous| previous.len());
+        self.tree.put(&cached.key, bytes.clone())?;
+
+        let mut size = self.lock_size()?;
+        *size = size.saturating_sub(previous as u64) + bytes.len() as u64;
+        if previous == 0 {
+            *size += cached.key.len() as u64;
+        }
+
+        Ok(())
+    }
+
+    fn lock_size(&self) -> Result<std::sync::MutexGuard<'_, u64>, Error> {
+        self.size.lock()
+            .map_err(|_| Error::Task("Failed to lock result cache size".to_string()))
+    }
+
+    fn lock_last_used(&self) -> Result<std::sync::MutexGuard<'_, HashMap<String, u64>>, Error> {
+        self.last_used.lock()
+            .map_err(|_| Error::Task("Failed to lock result cache usage".to_string()))
+    }
+}
+
+impl Evictable for ResultCache {
+    /// Evicts the least recently used results.
+    fn evict_bytes(&self, bytes: u64) -> Result<u64, Error> {
+        let start = self.size()?;
+        for key in self.least_recently_used()? {
+            if start.saturating_sub(self.size()?) >= bytes {
+                break;
+            }
+            self.remove(&key)?;
+        }
+
+        Ok(start.saturating_sub(self.size()?))
+    }
+}
+
+#[async_trait]
+impl MessageHandler for ResultCache {
+    async fn handle_message(&self, _peer_id: &PeerId, message: &[u8]) -> Result<Vec<u8>, Error> {
+        let message = Message::from_bytes(message)?;
+
+        if message.message_type != TASK_CACHE_LOOKUP_MESSAGE {
+            return Err(Error::Network(format!("Unexpected message type: {}", message.message_type)));
+        }
+
+        let request: CacheLookupRequest = serde_json::from_slice(&message.payload).map_err(Error::Serialization)?;
+        let reply = CacheLookupReply {
+            result: self.get(&request.key)?,
+        };
+
+        serde_json::to_vec(&reply).map_err(Error::Serialization)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tasks::{TaskResourceType, TaskStatus};
+    use std::collections::HashMap;
+
+    /// An in-memory directory shared by all peers, delivering lookups directly.
+    #[derive(Default)]
+    struct SharedDirectory {
+        providers: Mutex<HashMap<String, Vec<PeerId>>>,
+        caches: Mutex<HashMap<PeerId, Arc<ResultCache>>>,
+    }
+
+    struct Provider {
+        peer_id: PeerId,
+        shared: Arc<SharedDirectory>,
+    }
+
+    #[async_trait]
+    impl CacheDirectory for Provider {
+        async fn announce(&self, key: &str) -> Result<(), Error> {
+            self.shared.providers.lock().unwrap().entry(key.to_string()).or_default().push(self.peer_id);
+            Ok(())
+        }
+
+        async fn find_providers(&self, key: &str) -> Result<Vec<PeerId>, Error> {
+            Ok(self.shared.providers.lock().unwrap().get(key).cloned().unwrap_or_default())
+        }
+    }
+
+    #[async_trait]
+    impl MessageSender for Provider {
+        async fn send_message(&self, peer_id: &PeerId, message: &[u8]) -> Result<Vec<u8>, Error> {
+            let cache = self.shared.caches.lock().unwrap().get(peer_id).cloned()
+                .ok_or_else(|| Error::Network(format!("Unknown peer {}", peer_id)))?;
+            cache.handle_message(&self.peer_id, message).await
+        }
+    }
+
+    fn task(data: Vec<u8>) -> Task {
+        Task {
+            id: "cached".to_string(),
+            resource_type: TaskResourceType::Cpu,
+            data,
+            status: TaskStatus::Pending,
+            created_at: 0,
+            completed_at: None,
+            checkpoint: None,
+            deterministic: true,
+        }
+    }
+
+    fn open(dir: &tempfile::TempDir, shared: &Arc<SharedDirectory>, max_size: u64, quorum: usize) -> Arc<ResultCache> {
+        let database = Database::open(dir.path()).unwrap();
+        let mut cache = ResultCache::new(&database, ResultCacheConfig {
+            ttl: Duration::from_secs(60),
+            max_size,
+        }).unwrap();
+
+        let peer_id = PeerId::random();
+        let provider = Arc::new(Provider { peer_id, shared: shared.clone() });
+        cache.set_directory(provider.clone(), provider);
+        cache.set_provider_quorum(quorum);
+
+        let cache = Arc::new(cache);
+        shared.caches.lock().unwrap().insert(peer_id, cache.clone());
+        cache
+    }
+
+    #[tokio::test]
+    async fn test_results_are_evicted_and_fetched_from_peers() {
+        let shared = Arc::new(SharedDirectory::default());
+        let (dir_a, dir_b) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let peer_a = open(&dir_a, &shared, 1024, 1);
+        let peer_b = open(&dir_b, &shared, 1024, 1);
+
+        let key = cache_key(&task(vec![1, 2, 3])).unwrap();
+        assert_ne!(key, cache_key(&task(vec![1, 2, 4])).unwrap());
+        assert!(peer_a.lookup(&key).await.unwrap().is_none());
+
+        peer_a.insert(&key, &TaskResult::completed(vec![6], Duration::ZERO, 0)).await.unwrap();
+        peer_a.insert("failed", &TaskResult::cancelled(Duration::ZERO)).await.unwrap();
+        assert!(peer_a.get("failed").unwrap().is_none());
+
+        // Peer B has not computed the result, but finds it through the directory
+        let fetched = peer_b.lookup(&key).await.unwrap().unwrap();
+        assert_eq!(fetched.output, vec![6]);
+        assert!(peer_b.get(&key).unwrap().is_some());
+        assert_eq!(shared.providers.lock().unwrap()[&key].len(), 1);
+
+        // A forged result is not used until enough providers agree on it
+        let (dir_c, dir_d, dir_e) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let forger = open(&dir_c, &shared, 1024, 1);
+        forger.insert(&key, &TaskResult::completed(vec![7], Duration::ZERO, 0)).await.unwrap();
+        let peer_d = open(&dir_d, &shared, 1024, DEFAULT_PROVIDER_QUORUM);
+        assert!(peer_d.lookup(&key).await.unwrap().is_none());
+        let honest = open(&dir_e, &shared, 1024, 1);
+        honest.insert(&key, &TaskResult::completed(vec![6], Duration::ZERO, 0)).await.unwrap();
+        assert_eq!(peer_d.lookup(&key).await.unwrap().unwrap().output, vec![6]);
+
+        // Filling the cache evicts the least recently used result
+        for i in 0..20u8 {
+            peer_a.insert(&format!("filler-{}", i), &TaskResult::completed(vec![i; 32], Duration::ZERO, 0)).await.unwrap();
+        }
+        assert!(peer_a.size().unwrap() <= 1024);
+        assert!(peer_a.get(&key).unwrap().is_none());
+        assert!(peer_a.get("filler-19").unwrap().is_some());
+    }
+}
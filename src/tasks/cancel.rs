@@ -0,0 +1,186 @@
+/* Copyright 2025 Joao Guimaraes, Catp2p Project
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Task cancellation functionality.
+//!
+//! A [`CancellationToken`] is passed to every executor. Executors that can stop
+//! work early (e.g. by killing a subprocess) do so as soon as the token is
+//! cancelled; work running on rayon or blocking threads is cancelled on a best-effort
+//! basis, with handlers expected to poll the token and their result discarded.
+
+use crate::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+#[derive(Debug, Default)]
+struct CancellationState {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+/// A token signalling that a task should stop.
+///
+/// Cloning a token yields another handle to the same cancellation state.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    state: Arc<CancellationState>,
+}
+
+impl CancellationToken {
+    /// Creates a new, uncancelled token.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the token, waking everything waiting on it.
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::SeqCst);
+        self.state.notify.notify_waiters();
+    }
+
+    /// Returns whether the token has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Returns an error if the token has been cancelled, for use with `?` in handlers.
+    pub fn check(&self) -> Result<(), Error> {
+        if self.is_cancelled() {
+            return Err(Error::Task("Task was cancelled".to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Waits until the token is cancelled.
+    pub async fn cancelled(&self) {
+        let notified = self.state.notify.notified();
+        tokio::pin!(notified);
+
+        // Register interest before checking the flag so a concurrent cancel is not missed
+        notified.as_mut().enable();
+        if self.is_cancelled() {
+            return;
+        }
+
+        notified.await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ResourceMode;
+    use crate::resources::allocation::ResourceAllocator;
+    use crate::tasks::cpu::CpuTaskExecutor;
+    use crate::tasks::scheduler::TaskScheduler;
+    use crate::tasks::testing::resources;
+    use crate::tasks::{ExitReason, Task, TaskManager, TaskPayload, TaskResourceType, TaskStatus};
+    use std::sync::atomic::AtomicUsize;
+    use std::time::Duration;
+
+    fn spin_task(id: &str) -> Task {
+        Task {
+            id: id.to_string(),
+            resource_type: TaskResourceType::Cpu,
+            data: TaskPayload::new("spin", Vec::new()).to_bytes().unwrap(),
+            status: TaskStatus::Pending,
+            created_at: 0,
+            completed_at: None,
+            checkpoint: None,
+            deterministic: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cancel_running_task() {
+        let executor = CpuTaskExecutor::new(1).unwrap();
+        // Spins until cancelled
+        executor.registry().register_with_context("spin", |context, _| {
+            while !context.is_cancelled() {
+                std::thread::sleep(Duration::from_millis(5));
+            }
+            context.check_cancelled()?;
+            Ok(Vec::new())
+        }).unwrap();
+
+        let allocator = Arc::new(ResourceAllocator::new(ResourceMode::HighPerformance, None, resources(4)));
+        let mut scheduler = TaskScheduler::new(1, Duration::from_secs(30));
+        scheduler.set_cpu_executor(Arc::new(executor));
+        scheduler.set_allocator(allocator.clone());
+        let scheduler = Arc::new(scheduler);
+        let manager = TaskManager::new_with_scheduler(scheduler.clone());
+
+        let task = spin_task("spin-1");
+
+        let running = {
+            let scheduler = scheduler.clone();
+            let task = task.clone();
+            tokio::spawn(async move { scheduler.execute_task(&task).await })
+        };
+        while scheduler.running_task_count().await == 0 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        assert_eq!(allocator.reserved().unwrap().cpu_cores, 1);
+        manager.cancel_task(&task.id).await.unwrap();
+        assert_eq!(scheduler.running_task_count().await, 0);
+        assert_eq!(allocator.reserved().unwrap().cpu_cores, 0);
+        assert_eq!(manager.get_task_status(&task.id).await.unwrap(), TaskStatus::Cancelled);
+
+        let result = running.await.unwrap().unwrap();
+        assert_eq!(result.exit_reason, ExitReason::Cancelled);
+
+        let finished = scheduler.get_finished_task(&task.id).await.unwrap();
+        assert_eq!(finished.status, TaskStatus::Cancelled);
+        assert!(finished.completed_at.is_some());
+        assert!(manager.cancel_task(&task.id).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_timeout_cancels_running_task() {
+        let stopped = Arc::new(AtomicUsize::new(0));
+        let executor = CpuTaskExecutor::new(1).unwrap();
+        {
+            let stopped = stopped.clone();
+            executor.registry().register_with_context("spin", move |context, _| {
+                while !context.is_cancelled() {
+                    std::thread::sleep(Duration::from_millis(5));
+                }
+                stopped.fetch_add(1, Ordering::SeqCst);
+                context.check_cancelled()?;
+                Ok(Vec::new())
+            }).unwrap();
+        }
+
+        let mut scheduler = TaskScheduler::new(1, Duration::from_millis(50));
+        scheduler.set_cpu_executor(Arc::new(executor));
+
+        let task = spin_task("spin-2");
+        let result = scheduler.execute_task(&task).await.unwrap();
+        assert_eq!(result.exit_reason, ExitReason::TimedOut);
+        assert_eq!(scheduler.get_finished_task(&task.id).await.unwrap().status, TaskStatus::Failed);
+
+        // The handler is told to stop instead of running on after the timeout
+        for _ in 0..100 {
+            if stopped.load(Ordering::SeqCst) == 1 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(stopped.load(Ordering::SeqCst), 1);
+    }
+}
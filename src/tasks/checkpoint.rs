@@ -0,0 +1,228 @@
+/* Copyright 2025 Joao Guimaraes, Catp2p Project
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Task checkpointing functionality.
+//!
+//! Long-running handlers can emit opaque checkpoint blobs through their
+//! [`TaskContext`](crate::tasks::TaskContext). Only the latest checkpoint of each
+//! task is kept; a restarted or migrated task is handed that checkpoint so it can
+//! resume instead of starting from zero.
+
+use crate::error::Error;
+use crate::storage::db::{Database, Tree};
+use crate::tasks::{content_hash, current_timestamp};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Name of the database tree holding checkpoints.
+const CHECKPOINT_TREE: &str = "checkpoints";
+
+/// Metadata describing a task checkpoint.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CheckpointMetadata {
+    /// Sequence number, increasing with every checkpoint of the task.
+    pub sequence: u64,
+    /// Size of the checkpoint data in bytes.
+    pub size: u64,
+    /// Content hash of the checkpoint data.
+    pub hash: String,
+    /// When the checkpoint was taken (seconds since the Unix epoch).
+    pub created_at: u64,
+}
+
+/// A task checkpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// The task ID.
+    pub task_id: String,
+    /// The checkpoint metadata.
+    pub metadata: CheckpointMetadata,
+    /// The opaque checkpoint data emitted by the handler.
+    pub data: Vec<u8>,
+}
+
+impl Checkpoint {
+    /// Serializes the checkpoint to bytes.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        serde_json::to_vec(self).map_err(Error::Serialization)
+    }
+
+    /// Deserializes a checkpoint from bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        serde_json::from_slice(bytes).map_err(Error::Serialization)
+    }
+}
+
+/// A callback invoked whenever a new checkpoint is saved for a task.
+pub type CheckpointListener = Arc<dyn Fn(&Checkpoint) + Send + Sync>;
+
+/// Persists the latest checkpoint of each task.
+pub struct CheckpointStore {
+    tree: Tree,
+    listeners: Mutex<HashMap<String, CheckpointListener>>,
+}
+
+impl CheckpointStore {
+    /// Creates a new CheckpointStore in the given database.
+    pub fn new(database: &Database) -> Result<Self, Error> {
+        Ok(Self {
+            tree: database.open_tree(CHECKPOINT_TREE)?,
+            listeners: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Saves a new checkpoint for a task and notifies its listener, if any.
+    pub fn save(&self, task_id: &str, data: Vec<u8>) -> Result<CheckpointMetadata, Error> {
+        let sequence = self.metadata(task_id)?.map_or(1, |metadata| metadata.sequence + 1);
+
+        let checkpoint = Checkpoint {
+            task_id: task_id.to_string(),
+            metadata: CheckpointMetadata {
+                sequence,
+                size: data.len() as u64,
+                hash: content_hash(&data),
+                created_at: current_timestamp(),
+            },
+            data,
+        };
+        self.tree.put(task_id, checkpoint.to_bytes()?)?;
+
+        let listener = self.listeners.lock()
+            .map_err(|_| Error::Task("Failed to lock checkpoint listeners".to_string()))?
+            .get(task_id)
+            .cloned();
+        if let Some(listener) = listener {
+            listener(&checkpoint);
+        }
+
+        Ok(checkpoint.metadata)
+    }
+
+    /// Stores a checkpoint received from another node.
+    ///
+    /// The checkpoint is ignored unless it is newer than the one already stored.
+    /// Returns whether it was stored.
+    pub fn store(&self, checkpoint: &Checkpoint) -> Result<bool, Error> {
+        if content_hash(&checkpoint.data) != checkpoint.metadata.hash {
+            return Err(Error::Task(format!(
+                "Checkpoint {} of task {} does not match its hash",
+                checkpoint.metadata.sequence, checkpoint.task_id
+            )));
+        }
+
+        if let Some(existing) = self.metadata(&checkpoint.task_id)? {
+            if existing.sequence >= checkpoint.metadata.sequence {
+                return Ok(false);
+            }
+        }
+
+        self.tree.put(&checkpoint.task_id, checkpoint.to_bytes()?)?;
+
+        Ok(true)
+    }
+
+    /// Gets the latest checkpoint for a task.
+    pub fn latest(&self, task_id: &str) -> Result<Option<Checkpoint>, Error> {
+        self.tree.get(task_id)?
+            .map(|bytes| Checkpoint::from_bytes(&bytes))
+            .transpose()
+    }
+
+    /// Gets the metadata of the latest checkpoint for a task.
+    pub fn metadata(&self, task_id: &str) -> Result<Option<CheckpointMetadata>, Error> {
+        Ok(self.latest(task_id)?.map(|checkpoint| checkpoint.metadata))
+    }
+
+    /// Removes the checkpoints of a task, e.g. once it has completed.
+    pub fn remove(&self, task_id: &str) -> Result<(), Error> {
+        self.tree.remove(task_id)
+    }
+
+    /// Sets the listener notified when a checkpoint is saved for a task.
+    pub fn set_listener(&self, task_id: &str, listener: CheckpointListener) -> Result<(), Error> {
+        self.listeners.lock()
+            .map_err(|_| Error::Task("Failed to lock checkpoint listeners".to_string()))?
+            .insert(task_id.to_string(), listener);
+
+        Ok(())
+    }
+
+    /// Removes the listener for a task.
+    pub fn remove_listener(&self, task_id: &str) -> Result<(), Error> {
+        self.listeners.lock()
+            .map_err(|_| Error::Task("Failed to lock checkpoint listeners".to_string()))?
+            .remove(task_id);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tasks::cpu::CpuTaskExecutor;
+    use crate::tasks::cancel::CancellationToken;
+    use crate::tasks::events::ProgressReporter;
+    use crate::tasks::{Task, TaskExecutor, TaskPayload, TaskResourceType, TaskStatus};
+
+    #[tokio::test]
+    async fn test_task_resumes_from_latest_checkpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::open(dir.path()).unwrap();
+        let checkpoints = Arc::new(CheckpointStore::new(&database).unwrap());
+
+        let mut executor = CpuTaskExecutor::new(1).unwrap();
+        executor.set_checkpoint_store(checkpoints.clone());
+
+        // Counts to 10, checkpointing after each step and failing once at step 5
+        executor.registry().register_with_context("count", |context, _| {
+            let start = context.resume_checkpoint()
+                .map(|data| data[0])
+                .unwrap_or(0);
+            for step in start..10 {
+                if step == 5 && start == 0 {
+                    return Err(Error::Task("Node restarted".to_string()));
+                }
+                context.checkpoint(vec![step + 1])?;
+            }
+            Ok(vec![start])
+        }).unwrap();
+
+        let task = Task {
+            id: "count-1".to_string(),
+            resource_type: TaskResourceType::Cpu,
+            data: TaskPayload::new("count", Vec::new()).to_bytes().unwrap(),
+            status: TaskStatus::Pending,
+            created_at: 0,
+            completed_at: None,
+            checkpoint: None,
+            deterministic: false,
+        };
+
+        assert!(executor.execute(&task, &ProgressReporter::disabled("test"), &CancellationToken::new()).await.is_err());
+        let metadata = checkpoints.metadata(&task.id).unwrap().unwrap();
+        assert_eq!(metadata.sequence, 5);
+
+        // Stale checkpoints from other nodes are ignored
+        let mut stale = checkpoints.latest(&task.id).unwrap().unwrap();
+        stale.metadata.sequence = 2;
+        assert!(!checkpoints.store(&stale).unwrap());
+
+        let result = executor.execute(&task, &ProgressReporter::disabled("test"), &CancellationToken::new()).await.unwrap();
+        assert_eq!(result.output, vec![5]);
+        assert!(checkpoints.latest(&task.id).unwrap().is_none());
+    }
+}
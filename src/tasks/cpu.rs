@@ -16,68 +16,189 @@
 //! CPU task execution functionality.
 
 use crate::error::Error;
-use crate::tasks::{Task, TaskExecutor}; 
+use crate::resources::accounting::ThreadUsageMeter;
+use crate::resources::topology::{pin_current_thread, NumaNode};
+use crate::tasks::checkpoint::CheckpointStore;
+use crate::tasks::cancel::CancellationToken;
+use crate::tasks::events::ProgressReporter;
+use crate::tasks::registry::TaskRegistry;
+use crate::tasks::{Task, TaskContext, TaskExecutor, TaskPayload, TaskResult};
 use async_trait::async_trait;
-// Remove unused import
-// use rayon::prelude::*;
-use std::time::Instant;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::oneshot;
 
 /// A CPU task executor.
+///
+/// Tasks carry a [`TaskPayload`] whose kind is looked up in the executor's
+/// [`TaskRegistry`]; the handler then runs on a rayon pool sized by `cpu_cores`.
 pub struct CpuTaskExecutor {
     // Number of CPU cores to use
     cpu_cores: usize,
+    pool: Arc<rayon::ThreadPool>,
+    registry: Arc<TaskRegistry>,
+    checkpoints: Option<Arc<CheckpointStore>>,
 }
 
 impl CpuTaskExecutor {
-    /// Creates a new CpuTaskExecutor with the given number of CPU cores.
-    pub fn new(cpu_cores: usize) -> Self {
+    /// Creates a new CpuTaskExecutor with the given number of CPU cores and an empty registry.
+    pub fn new(cpu_cores: usize) -> Result<Self, Error> {
+        Self::new_with_registry(cpu_cores, Arc::new(TaskRegistry::new()))
+    }
+    
+    /// Creates a new CpuTaskExecutor using all available CPU cores and an empty registry.
+    pub fn new_with_all_cores() -> Result<Self, Error> {
+        Self::new(rayon::current_num_threads())
+    }
+    
+    /// Creates a new CpuTaskExecutor with the given number of CPU cores and task registry.
+    pub fn new_with_registry(cpu_cores: usize, registry: Arc<TaskRegistry>) -> Result<Self, Error> {
         let available_cores = rayon::current_num_threads();
-        let cores_to_use = cpu_cores.min(available_cores);
+        let cores_to_use = cpu_cores.clamp(1, available_cores.max(1));
         
-        Self {
-            cpu_cores: cores_to_use,
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(cores_to_use)
+            .thread_name(|i| format!("catp2p-cpu-{}", i))
+            .build()
+            .map_err(|e| Error::Task(format!("Failed to create thread pool: {}", e)))?;
+        
+        Ok(Self::new_with_pool(cores_to_use, pool, registry))
+    }
+    
+    /// Creates a new CpuTaskExecutor whose threads are pinned to the cores of a NUMA node,
+    /// one thread per core.
+    ///
+    /// Threads that cannot be pinned run unpinned.
+    pub fn new_on_numa_node(node: &NumaNode, registry: Arc<TaskRegistry>) -> Result<Self, Error> {
+        if node.cpus.is_empty() {
+            return Err(Error::Task(format!("NUMA node {} has no CPUs", node.id)));
         }
+        
+        let node_id = node.id;
+        let cpus = node.cpus.clone();
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(cpus.len())
+            .thread_name(move |i| format!("catp2p-cpu-{}-{}", node_id, i))
+            .start_handler(move |_| {
+                if let Err(e) = pin_current_thread(&cpus) {
+                    log::warn!("{}", e);
+                }
+            })
+            .build()
+            .map_err(|e| Error::Task(format!("Failed to create thread pool: {}", e)))?;
+        
+        Ok(Self::new_with_pool(node.cpus.len(), pool, registry))
     }
     
-    /// Creates a new CpuTaskExecutor using all available CPU cores.
-    pub fn new_with_all_cores() -> Self {
+    fn new_with_pool(cpu_cores: usize, pool: rayon::ThreadPool, registry: Arc<TaskRegistry>) -> Self {
         Self {
-            cpu_cores: rayon::current_num_threads(),
+            cpu_cores,
+            pool: Arc::new(pool),
+            registry,
+            checkpoints: None,
         }
     }
+    
+    /// Returns the number of CPU cores used by this executor.
+    pub fn cpu_cores(&self) -> usize {
+        self.cpu_cores
+    }
+    
+    /// Returns the task registry used to look up handlers.
+    pub fn registry(&self) -> &Arc<TaskRegistry> {
+        &self.registry
+    }
+    
+    /// Sets the store used to persist checkpoints emitted by handlers.
+    ///
+    /// Tasks that already have a checkpoint in the store resume from it, and a
+    /// task's checkpoints are removed once it completes.
+    pub fn set_checkpoint_store(&mut self, checkpoints: Arc<CheckpointStore>) {
+        self.checkpoints = Some(checkpoints);
+    }
 }
 
 #[async_trait]
 impl TaskExecutor for CpuTaskExecutor {
-    async fn execute(&self, _task: &Task) -> Result<String, Error> {
-        // This is a placeholder implementation
-        // In a real implementation, we would parse the task data and execute the actual computation
+    async fn execute(
+        &self,
+        task: &Task,
+        progress: &ProgressReporter,
+        cancel: &CancellationToken,
+    ) -> Result<TaskResult, Error> {
+        let payload = TaskPayload::from_bytes(&task.data)?;
+        let handler = self.registry.get(&payload.kind)?;
+        let mut context = match &self.checkpoints {
+            Some(checkpoints) => TaskContext::new_with_checkpoints(&task.id, checkpoints.clone())?,
+            None => TaskContext::new(&task.id),
+        };
+        context.set_progress_reporter(progress.clone());
+        context.set_cancellation_token(cancel.clone());
         
-        let start_time = Instant::now();
-        let cpu_cores = self.cpu_cores; // Clone the value to move into the closure
-        
-        // Simulate CPU-intensive work
-        let result = tokio::task::spawn_blocking(move || {
-            // Create a parallel iterator with the specified number of threads
-            let pool = rayon::ThreadPoolBuilder::new()
-                .num_threads(cpu_cores)
-                .build()
-                .map_err(|e| Error::Task(format!("Failed to create thread pool: {}", e)))?;
+        let (tx, rx) = oneshot::channel();
+        self.pool.spawn(move || {
+            let meter = ThreadUsageMeter::start();
             
-            let result = pool.install(|| {
-                // Simulate some CPU-intensive work
-                let mut sum = 0;
-                for i in 0..1_000_000 {
-                    sum += i;
-                }
-                Ok::<_, Error>(sum.to_string())
-            });
+            // A panic inside a rayon job would abort the process, so contain it here
+            let output = panic::catch_unwind(AssertUnwindSafe(|| handler(&context, &payload.input)));
             
-            result
-        }).await.map_err(|e| Error::Task(format!("Task execution failed: {}", e)))??;
+            let _ = tx.send((output, meter.cpu_time()));
+        });
         
-        let elapsed = start_time.elapsed();
+        let (output, cpu_time) = tokio::select! {
+            received = rx => received
+                .map_err(|_| Error::Task(format!("Task {} was dropped by the thread pool", task.id)))?,
+            // Rayon jobs cannot be interrupted; the handler stops when it next checks the
+            // context, and whatever it returns is discarded
+            _ = cancel.cancelled() => return Ok(TaskResult::cancelled(Duration::ZERO)),
+        };
+        if cancel.is_cancelled() {
+            return Ok(TaskResult::cancelled(cpu_time));
+        }
+        let output = output
+            .map_err(|_| Error::Task(format!("Task {} panicked", task.id)))??;
+        
+        if let Some(checkpoints) = &self.checkpoints {
+            checkpoints.remove(&task.id)?;
+        }
         
-        Ok(format!("Result: {}, Time: {:?}", result, elapsed))
+        // The handler shares the process's memory with every other task, so none is attributed to it
+        Ok(TaskResult::completed(output, cpu_time, 0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tasks::{ExitReason, TaskResourceType, TaskStatus};
+
+    #[tokio::test]
+    async fn test_execute_runs_registered_handler() {
+        let executor = CpuTaskExecutor::new(2).expect("Failed to create executor");
+        executor.registry()
+            .register_typed("sum", |values: Vec<u64>| Ok(values.iter().sum::<u64>()))
+            .unwrap();
+
+        let task = Task {
+            id: "sum-1".to_string(),
+            resource_type: TaskResourceType::Cpu,
+            data: TaskPayload::typed("sum", &vec![1u64, 2, 3]).unwrap().to_bytes().unwrap(),
+            status: TaskStatus::Pending,
+            created_at: 0,
+            completed_at: None,
+            checkpoint: None,
+            deterministic: false,
+        };
+
+        let result = executor.execute(&task, &ProgressReporter::disabled("test"), &CancellationToken::new()).await.expect("Task should succeed");
+        assert_eq!(result.output, b"6");
+        assert_eq!(result.exit_reason, ExitReason::Completed);
+
+        let unknown = Task {
+            data: TaskPayload::new("missing", Vec::new()).to_bytes().unwrap(),
+            ..task
+        };
+        assert!(executor.execute(&unknown, &ProgressReporter::disabled("test"), &CancellationToken::new()).await.is_err());
     }
 }
@@ -0,0 +1,274 @@
+/* Copyright 2025 Joao Guimaraes, Catp2p Project
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Task lifecycle events and progress reporting.
+
+use crate::tasks::current_timestamp;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tokio::sync::broadcast;
+
+/// Default number of events buffered for slow subscribers.
+const DEFAULT_EVENT_CAPACITY: usize = 1024;
+
+/// Progress reported by a running task.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskProgress {
+    /// Fraction of the work done (0.0 - 1.0).
+    pub fraction: f32,
+    /// Human-readable description of the current step.
+    pub message: Option<String>,
+    /// Custom metrics reported by the task.
+    pub metrics: HashMap<String, f64>,
+}
+
+/// What happened to a task.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TaskEventKind {
+    /// The task was submitted.
+    Submitted,
+    /// The task was queued for execution.
+    Scheduled,
+    /// The task started running.
+    Started,
+    /// The task reported progress.
+    Progress(TaskProgress),
+    /// The task is being retried after a failed attempt.
+    Retrying {
+        /// The attempt about to start, counting from 1.
+        attempt: u32,
+        /// Why the previous attempt failed.
+        reason: String,
+    },
+    /// The task completed successfully.
+    Completed,
+    /// The task failed.
+    Failed(String),
+    /// The task was cancelled.
+    Cancelled,
+}
+
+impl TaskEventKind {
+    /// Returns whether no further events follow for the task.
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, Self::Completed | Self::Failed(_) | Self::Cancelled)
+    }
+}
+
+/// A task lifecycle event.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskEvent {
+    /// The task ID.
+    pub task_id: String,
+    /// What happened.
+    pub kind: TaskEventKind,
+    /// The peer the event was forwarded from, or None if it happened locally.
+    pub peer_id: Option<String>,
+    /// When the event happened (seconds since the Unix epoch).
+    pub timestamp: u64,
+}
+
+impl TaskEvent {
+    /// Creates a new local TaskEvent.
+    pub fn new(task_id: &str, kind: TaskEventKind) -> Self {
+        Self {
+            task_id: task_id.to_string(),
+            kind,
+            peer_id: None,
+            timestamp: current_timestamp(),
+        }
+    }
+}
+
+/// A broadcast channel of task events.
+///
+/// Cloning a `TaskEvents` yields another handle to the same channel. Events are
+/// dropped if nobody is subscribed, and slow subscribers skip the oldest events.
+#[derive(Debug, Clone)]
+pub struct TaskEvents {
+    sender: broadcast::Sender<TaskEvent>,
+}
+
+impl TaskEvents {
+    /// Creates a new TaskEvents channel with the default capacity.
+    pub fn new() -> Self {
+        Self::new_with_capacity(DEFAULT_EVENT_CAPACITY)
+    }
+
+    /// Creates a new TaskEvents channel buffering up to `capacity` events per subscriber.
+    pub fn new_with_capacity(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity.max(1));
+        Self { sender }
+    }
+
+    /// Subscribes to all events emitted from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<TaskEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Emits an event to all subscribers.
+    pub fn emit(&self, event: TaskEvent) {
+        // Sending only fails when there are no subscribers
+        let _ = self.sender.send(event);
+    }
+
+    /// Emits a local event for a task.
+    pub fn emit_kind(&self, task_id: &str, kind: TaskEventKind) {
+        self.emit(TaskEvent::new(task_id, kind));
+    }
+}
+
+impl Default for TaskEvents {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A handle passed to executors for reporting the progress of a task.
+#[derive(Debug, Clone)]
+pub struct ProgressReporter {
+    task_id: String,
+    events: Option<TaskEvents>,
+}
+
+impl ProgressReporter {
+    /// Creates a new ProgressReporter emitting progress events for the given task.
+    pub fn new(task_id: &str, events: TaskEvents) -> Self {
+        Self {
+            task_id: task_id.to_string(),
+            events: Some(events),
+        }
+    }
+
+    /// Creates a ProgressReporter that discards all progress.
+    pub fn disabled(task_id: &str) -> Self {
+        Self {
+            task_id: task_id.to_string(),
+            events: None,
+        }
+    }
+
+    /// Returns the ID of the task being reported on.
+    pub fn task_id(&self) -> &str {
+        &self.task_id
+    }
+
+    /// Reports the fraction of work done together with a message.
+    pub fn report(&self, fraction: f32, message: &str) {
+        self.report_progress(TaskProgress {
+            fraction,
+            message: Some(message.to_string()),
+            metrics: HashMap::new(),
+        });
+    }
+
+    /// Reports progress including custom metrics.
+    pub fn report_progress(&self, mut progress: TaskProgress) {
+        if let Some(events) = &self.events {
+            progress.fraction = progress.fraction.clamp(0.0, 1.0);
+            events.emit_kind(&self.task_id, TaskEventKind::Progress(progress));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::db::Database;
+    use crate::tasks::cpu::CpuTaskExecutor;
+    use crate::tasks::scheduler::TaskScheduler;
+    use crate::tasks::store::TaskStore;
+    use crate::tasks::{Task, TaskManager, TaskPayload, TaskResourceType, TaskStatus};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn task(kind: &str) -> Task {
+        Task {
+            id: String::new(),
+            resource_type: TaskResourceType::Cpu,
+            data: TaskPayload::typed(kind, &()).unwrap().to_bytes().unwrap(),
+            status: TaskStatus::Pending,
+            created_at: 0,
+            completed_at: None,
+            checkpoint: None,
+            deterministic: false,
+        }
+    }
+
+    /// Receives the events of a task up to and including its terminal event.
+    async fn events_of(events: &mut broadcast::Receiver<TaskEvent>, task_id: &str) -> Vec<TaskEventKind> {
+        let mut kinds = Vec::new();
+        loop {
+            let event = events.recv().await.unwrap();
+            if event.task_id != task_id {
+                continue;
+            }
+            let terminal = event.kind.is_terminal();
+            kinds.push(event.kind);
+            if terminal {
+                return kinds;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_submitted_tasks_emit_lifecycle_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::open(dir.path()).unwrap();
+
+        let executor = CpuTaskExecutor::new(2).unwrap();
+        executor.registry().register_with_context("halves", |context, _| {
+            context.report_progress(0.5, "halfway");
+            Ok(Vec::new())
+        }).unwrap();
+        executor.registry().register_with_context("forever", |context, _| {
+            context.report_progress(0.0, "started");
+            while !context.is_cancelled() {
+                std::thread::sleep(Duration::from_millis(5));
+            }
+            Ok(Vec::new())
+        }).unwrap();
+        let mut scheduler = TaskScheduler::new(2, Duration::from_secs(5));
+        scheduler.set_cpu_executor(Arc::new(executor));
+        scheduler.set_task_store(Arc::new(TaskStore::new(&database).unwrap()));
+
+        let manager = TaskManager::new_with_scheduler(Arc::new(scheduler));
+        let mut events = manager.subscribe();
+
+        let task_id = manager.submit_task(task("halves")).await.unwrap();
+        let kinds = events_of(&mut events, &task_id).await;
+        assert_eq!(kinds[..3], [TaskEventKind::Submitted, TaskEventKind::Scheduled, TaskEventKind::Started]);
+        match &kinds[3] {
+            TaskEventKind::Progress(progress) => {
+                assert_eq!(progress.fraction, 0.5);
+                assert_eq!(progress.message.as_deref(), Some("halfway"));
+            },
+            kind => panic!("Expected progress, got {:?}", kind),
+        }
+        assert_eq!(kinds[4..], [TaskEventKind::Completed]);
+
+        // A running task is cancelled once it has reported progress
+        let task_id = manager.submit_task(task("forever")).await.unwrap();
+        loop {
+            let event = events.recv().await.unwrap();
+            if event.task_id == task_id && matches!(event.kind, TaskEventKind::Progress(_)) {
+                break;
+            }
+        }
+        manager.cancel_task(&task_id).await.unwrap();
+        assert_eq!(events_of(&mut events, &task_id).await, [TaskEventKind::Cancelled]);
+        assert_eq!(manager.get_task_status(&task_id).await.unwrap(), TaskStatus::Cancelled);
+    }
+}
@@ -14,31 +14,166 @@
  */
 
 //! GPU task execution functionality.
+//!
+//! GPU tasks carry a WGSL compute shader together with the buffers it binds. The
+//! executor uploads the input buffers, dispatches the shader and reads the output
+//! buffers back into a [`GpuTaskOutput`].
 
 use crate::error::Error;
-use crate::tasks::{Task, TaskExecutor}; // Removed unused TaskStatus import
+use crate::tasks::cancel::CancellationToken;
+use crate::tasks::events::ProgressReporter;
+use crate::tasks::{Task, TaskExecutor, TaskResult};
 use async_trait::async_trait;
-// Remove the following line:
-// use std::time::Instant; // Only import when GPU feature is enabled
+use serde::{Deserialize, Serialize};
 
 #[cfg(feature = "gpu")]
-use wgpu;
+use std::collections::HashSet;
 #[cfg(feature = "gpu")]
-use std::time::Instant;
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "gpu")]
+use std::time::{Duration, Instant};
+#[cfg(feature = "gpu")]
+use wgpu::util::DeviceExt;
+
+/// How a buffer is bound and used by a GPU task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GpuBufferUsage {
+    /// Read-only storage buffer initialized from the task.
+    Input,
+    /// Read-write storage buffer that is read back after the dispatch.
+    Output,
+    /// Read-write storage buffer initialized from the task and read back after the dispatch.
+    InputOutput,
+    /// Uniform buffer initialized from the task.
+    Uniform,
+}
+
+/// Describes a buffer bound to the compute shader in bind group 0.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GpuBufferDescriptor {
+    /// Binding index within bind group 0.
+    pub binding: u32,
+    /// How the buffer is used.
+    pub usage: GpuBufferUsage,
+    /// Initial contents; ignored for `Output` buffers.
+    pub contents: Vec<u8>,
+    /// Size in bytes for `Output` buffers; other buffers use the length of `contents`.
+    pub size: u64,
+}
+
+impl GpuBufferDescriptor {
+    /// Creates a read-only input buffer.
+    pub fn input(binding: u32, contents: Vec<u8>) -> Self {
+        Self { binding, usage: GpuBufferUsage::Input, contents, size: 0 }
+    }
+
+    /// Creates an output buffer of the given size.
+    pub fn output(binding: u32, size: u64) -> Self {
+        Self { binding, usage: GpuBufferUsage::Output, contents: Vec::new(), size }
+    }
+
+    /// Creates a uniform buffer.
+    pub fn uniform(binding: u32, contents: Vec<u8>) -> Self {
+        Self { binding, usage: GpuBufferUsage::Uniform, contents, size: 0 }
+    }
+
+    /// Returns the size of the buffer in bytes.
+    pub fn buffer_size(&self) -> u64 {
+        match self.usage {
+            GpuBufferUsage::Output => self.size,
+            _ => self.contents.len() as u64,
+        }
+    }
+
+    /// Returns whether the buffer is read back after the dispatch.
+    pub fn is_read_back(&self) -> bool {
+        matches!(self.usage, GpuBufferUsage::Output | GpuBufferUsage::InputOutput)
+    }
+}
+
+/// The contents of `Task.data` for a GPU task.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GpuTask {
+    /// WGSL source of the compute shader.
+    pub shader: String,
+    /// Name of the compute entry point.
+    pub entry_point: String,
+    /// Number of workgroups to dispatch in each dimension.
+    pub workgroups: [u32; 3],
+    /// Buffers bound in bind group 0.
+    pub buffers: Vec<GpuBufferDescriptor>,
+}
+
+impl GpuTask {
+    /// Returns the GPU memory the task's buffers take up, including the staging
+    /// buffers that output buffers are read back through.
+    pub fn gpu_memory(&self) -> u64 {
+        self.buffers.iter()
+            .map(|buffer| if buffer.is_read_back() { buffer.buffer_size() * 2 } else { buffer.buffer_size() })
+            .sum()
+    }
+
+    /// Serializes the task to bytes suitable for `Task.data`.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        serde_json::to_vec(self).map_err(Error::Serialization)
+    }
+
+    /// Deserializes a task from `Task.data`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        serde_json::from_slice(bytes).map_err(Error::Serialization)
+    }
+}
+
+/// A buffer read back from the GPU.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GpuBufferOutput {
+    /// Binding index of the buffer.
+    pub binding: u32,
+    /// Buffer contents after the dispatch.
+    pub data: Vec<u8>,
+}
+
+/// The output of a GPU task, stored in `TaskResult.output`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GpuTaskOutput {
+    /// Read-back buffers, ordered by binding.
+    pub buffers: Vec<GpuBufferOutput>,
+}
+
+impl GpuTaskOutput {
+    /// Serializes the output to bytes.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        serde_json::to_vec(self).map_err(Error::Serialization)
+    }
+
+    /// Deserializes the output from `TaskResult.output`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        serde_json::from_slice(bytes).map_err(Error::Serialization)
+    }
+}
 
 /// A GPU task executor.
 #[allow(dead_code)]
 pub struct GpuTaskExecutor {
     #[cfg(feature = "gpu")]
-    device: wgpu::Device,
+    device: Arc<wgpu::Device>,
     #[cfg(feature = "gpu")]
-    queue: wgpu::Queue,
+    queue: Arc<wgpu::Queue>,
+    // Error scopes belong to the device, so dispatches must not interleave
+    #[cfg(feature = "gpu")]
+    dispatch_lock: Arc<Mutex<()>>,
 }
 
 impl GpuTaskExecutor {
-    /// Creates a new GpuTaskExecutor.
+    /// Creates a new GpuTaskExecutor on the default high-performance adapter.
     #[cfg(feature = "gpu")]
     pub async fn new() -> Result<Self, Error> {
+        Self::new_with_options(false).await
+    }
+    
+    /// Creates a new GpuTaskExecutor, optionally forcing wgpu's software fallback adapter.
+    #[cfg(feature = "gpu")]
+    pub async fn new_with_options(force_fallback_adapter: bool) -> Result<Self, Error> {
         // Initialize wgpu
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
             backends: wgpu::Backends::all(),
@@ -50,7 +185,7 @@ impl GpuTaskExecutor {
             &wgpu::RequestAdapterOptions {
                 power_preference: wgpu::PowerPreference::HighPerformance,
                 compatible_surface: None,
-                force_fallback_adapter: false,
+                force_fallback_adapter,
             },
         ).await.ok_or_else(|| Error::Task("No GPU adapter found".to_string()))?;
         
@@ -58,15 +193,16 @@ impl GpuTaskExecutor {
         let (device, queue) = adapter.request_device(
             &wgpu::DeviceDescriptor {
                 features: wgpu::Features::empty(),
-                limits: wgpu::Limits::default(),
-                label: None,
+                limits: wgpu::Limits::downlevel_defaults().using_resolution(adapter.limits()),
+                label: Some("catp2p GPU task device"),
             },
             None,
         ).await.map_err(|e| Error::Task(format!("Failed to create GPU device: {}", e)))?;
         
         Ok(Self {
-            device,
-            queue,
+            device: Arc::new(device),
+            queue: Arc::new(queue),
+            dispatch_lock: Arc::new(Mutex::new(())),
         })
     }
     
@@ -76,6 +212,12 @@ impl GpuTaskExecutor {
         Err(Error::Task("GPU support is not enabled".to_string()))
     }
     
+    /// Creates a new GpuTaskExecutor, optionally forcing wgpu's software fallback adapter.
+    #[cfg(not(feature = "gpu"))]
+    pub async fn new_with_options(_force_fallback_adapter: bool) -> Result<Self, Error> {
+        Err(Error::Task("GPU support is not enabled".to_string()))
+    }
+    
     /// Checks if GPU is available.
     pub fn is_gpu_available() -> bool {
         #[cfg(feature = "gpu")]
@@ -93,25 +235,316 @@ impl GpuTaskExecutor {
 
 #[async_trait]
 impl TaskExecutor for GpuTaskExecutor {
-    async fn execute(&self, _task: &Task) -> Result<String, Error> {
+    async fn execute(
+        &self,
+        task: &Task,
+        _progress: &ProgressReporter,
+        cancel: &CancellationToken,
+    ) -> Result<TaskResult, Error> {
         #[cfg(feature = "gpu")]
         {
-            // This is a placeholder implementation
-            // In a real implementation, we would parse the task data and execute the actual computation
+            let gpu_task = GpuTask::from_bytes(&task.data)?;
+            let device = self.device.clone();
+            let queue = self.queue.clone();
+            let dispatch_lock = self.dispatch_lock.clone();
             
             let start_time = Instant::now();
             
-            // Simulate GPU-intensive work
-            // In a real implementation, we would create a compute shader and execute it
+            // Waiting for the device blocks, so keep it off the async runtime threads
+            let dispatch = tokio::task::spawn_blocking(move || run_compute(&device, &queue, &dispatch_lock, &gpu_task));
             
-            let elapsed = start_time.elapsed();
-            
-            Ok(format!("GPU task completed in {:?}", elapsed))
+            tokio::select! {
+                joined = dispatch => joined
+                    .map_err(|e| Error::Task(format!("GPU task {} failed to join: {}", task.id, e)))?,
+                // A submitted dispatch cannot be aborted, so it finishes in the background and its output is discarded
+                _ = cancel.cancelled() => {
+                    let mut result = TaskResult::cancelled(Duration::ZERO);
+                    result.gpu_time = start_time.elapsed();
+                    Ok(result)
+                },
+            }
         }
         
         #[cfg(not(feature = "gpu"))]
         {
+            let _ = (task, cancel);
             Err(Error::Task("GPU support is not enabled".to_string()))
         }
     }
 }
+
+/// Uploads the task buffers, dispatches the shader and reads the output buffers back.
+#[cfg(feature = "gpu")]
+fn run_compute(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    dispatch_lock: &Mutex<()>,
+    gpu_task: &GpuTask,
+) -> Result<TaskResult, Error> {
+    validate_task(device, gpu_task)?;
+    
+    let start_time = Instant::now();
+    
+    // Capture validation errors (e.g. invalid WGSL) instead of letting wgpu panic.
+    // The scope is held until it is popped so that no other dispatch's errors land in it.
+    let scope_guard = dispatch_lock.lock()
+        .map_err(|_| Error::Task("Failed to lock GPU device".to_string()))?;
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Task Shader"),
+        source: wgpu::ShaderSource::Wgsl(gpu_task.shader.as_str().into()),
+    });
+    
+    let buffers: Vec<wgpu::Buffer> = gpu_task.buffers.iter()
+        .map(|descriptor| create_buffer(device, descriptor))
+        .collect();
+    
+    let layout_entries: Vec<wgpu::BindGroupLayoutEntry> = gpu_task.buffers.iter()
+        .map(|descriptor| wgpu::BindGroupLayoutEntry {
+            binding: descriptor.binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: match descriptor.usage {
+                    GpuBufferUsage::Input => wgpu::BufferBindingType::Storage { read_only: true },
+                    GpuBufferUsage::Output | GpuBufferUsage::InputOutput => wgpu::BufferBindingType::Storage { read_only: false },
+                    GpuBufferUsage::Uniform => wgpu::BufferBindingType::Uniform,
+                },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        })
+        .collect();
+    
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Task Bind Group Layout"),
+        entries: &layout_entries,
+    });
+    
+    let bind_group_entries: Vec<wgpu::BindGroupEntry> = gpu_task.buffers.iter()
+        .zip(&buffers)
+        .map(|(descriptor, buffer)| wgpu::BindGroupEntry {
+            binding: descriptor.binding,
+            resource: buffer.as_entire_binding(),
+        })
+        .collect();
+    
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Task Bind Group"),
+        layout: &bind_group_layout,
+        entries: &bind_group_entries,
+    });
+    
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Task Pipeline Layout"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("Task Compute Pipeline"),
+        layout: Some(&pipeline_layout),
+        module: &shader,
+        entry_point: &gpu_task.entry_point,
+    });
+    
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Task Encoder"),
+    });
+    
+    {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Task Compute Pass"),
+        });
+        compute_pass.set_pipeline(&pipeline);
+        compute_pass.set_bind_group(0, &bind_group, &[]);
+        let [x, y, z] = gpu_task.workgroups;
+        compute_pass.dispatch_workgroups(x, y, z);
+    }
+    
+    // Copy every output buffer into a mappable staging buffer
+    let mut staging = Vec::new();
+    for (descriptor, buffer) in gpu_task.buffers.iter().zip(&buffers) {
+        if !descriptor.is_read_back() {
+            continue;
+        }
+        let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Task Staging Buffer"),
+            size: descriptor.buffer_size(),
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        encoder.copy_buffer_to_buffer(buffer, 0, &staging_buffer, 0, descriptor.buffer_size());
+        staging.push((descriptor.binding, staging_buffer));
+    }
+    
+    queue.submit(std::iter::once(encoder.finish()));
+    
+    let error = pollster::block_on(device.pop_error_scope());
+    drop(scope_guard);
+    if let Some(error) = error {
+        return Err(Error::Task(format!("GPU task rejected: {}", error)));
+    }
+    
+    let mut outputs = Vec::with_capacity(staging.len());
+    for (binding, staging_buffer) in staging {
+        let slice = staging_buffer.slice(..);
+        let (tx, rx) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = tx.send(result);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        
+        rx.recv()
+            .map_err(|_| Error::Task("GPU buffer mapping was abandoned".to_string()))?
+            .map_err(|e| Error::Task(format!("Failed to map GPU buffer: {}", e)))?;
+        
+        let data = slice.get_mapped_range().to_vec();
+        staging_buffer.unmap();
+        outputs.push(GpuBufferOutput { binding, data });
+    }
+    outputs.sort_by_key(|output| output.binding);
+    
+    let gpu_memory: u64 = gpu_task.buffers.iter().map(GpuBufferDescriptor::buffer_size).sum();
+    let output = GpuTaskOutput { buffers: outputs }.to_bytes()?;
+    
+    // GPU time is reported as the wall-clock time of the dispatch and read-back,
+    // and peak memory as the GPU memory allocated for the task buffers
+    let mut result = TaskResult::completed(output, Duration::ZERO, gpu_memory);
+    result.gpu_time = start_time.elapsed();
+    Ok(result)
+}
+
+#[cfg(feature = "gpu")]
+fn create_buffer(device: &wgpu::Device, descriptor: &GpuBufferDescriptor) -> wgpu::Buffer {
+    let usage = match descriptor.usage {
+        GpuBufferUsage::Input => wgpu::BufferUsages::STORAGE,
+        GpuBufferUsage::Output | GpuBufferUsage::InputOutput => wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        GpuBufferUsage::Uniform => wgpu::BufferUsages::UNIFORM,
+    };
+    
+    match descriptor.usage {
+        GpuBufferUsage::Output => device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Task Output Buffer"),
+            size: descriptor.size,
+            usage,
+            mapped_at_creation: false,
+        }),
+        _ => device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Task Input Buffer"),
+            contents: &descriptor.contents,
+            usage,
+        }),
+    }
+}
+
+/// Checks the task against the device limits before touching the GPU.
+#[cfg(feature = "gpu")]
+fn validate_task(device: &wgpu::Device, gpu_task: &GpuTask) -> Result<(), Error> {
+    let limits = device.limits();
+    
+    if gpu_task.workgroups.iter().any(|&count| count == 0 || count > limits.max_compute_workgroups_per_dimension) {
+        return Err(Error::Task(format!(
+            "Workgroup counts {:?} must be between 1 and {}",
+            gpu_task.workgroups, limits.max_compute_workgroups_per_dimension
+        )));
+    }
+    
+    let mut bindings = HashSet::new();
+    for descriptor in &gpu_task.buffers {
+        if !bindings.insert(descriptor.binding) {
+            return Err(Error::Task(format!("Duplicate buffer binding: {}", descriptor.binding)));
+        }
+        
+        let size = descriptor.buffer_size();
+        let max_size = match descriptor.usage {
+            GpuBufferUsage::Uniform => limits.max_uniform_buffer_binding_size,
+            _ => limits.max_storage_buffer_binding_size,
+        };
+        // Buffer copies must be a multiple of 4 bytes
+        if size == 0 || size % 4 != 0 || size > max_size as u64 {
+            return Err(Error::Task(format!(
+                "Buffer {} has invalid size {} (must be a non-zero multiple of 4, at most {})",
+                descriptor.binding, size, max_size
+            )));
+        }
+    }
+    
+    Ok(())
+}
+
+#[cfg(all(test, feature = "gpu"))]
+mod tests {
+    use super::*;
+    use crate::tasks::{TaskResourceType, TaskStatus};
+
+    const DOUBLE_SHADER: &str = r#"
+        @group(0) @binding(0) var<storage, read> input: array<f32>;
+        @group(0) @binding(1) var<storage, read_write> output: array<f32>;
+
+        @compute @workgroup_size(64)
+        fn double(@builtin(global_invocation_id) id: vec3<u32>) {
+            if (id.x < arrayLength(&input)) {
+                output[id.x] = input[id.x] * 2.0;
+            }
+        }
+    "#;
+
+    #[tokio::test]
+    async fn test_dispatch_on_fallback_adapter() {
+        // Not every machine has a software adapter, in which case there is nothing to test
+        let executor = match GpuTaskExecutor::new_with_options(true).await {
+            Ok(executor) => executor,
+            Err(e) => {
+                log::info!("Skipping GPU task test: {}", e);
+                return;
+            },
+        };
+
+        let input: Vec<f32> = (0..128).map(|i| i as f32).collect();
+        let gpu_task = GpuTask {
+            shader: DOUBLE_SHADER.to_string(),
+            entry_point: "double".to_string(),
+            workgroups: [2, 1, 1],
+            buffers: vec![
+                GpuBufferDescriptor::input(0, bytemuck::cast_slice(&input).to_vec()),
+                GpuBufferDescriptor::output(1, (input.len() * 4) as u64),
+            ],
+        };
+        let task = Task {
+            id: "gpu-1".to_string(),
+            resource_type: TaskResourceType::Gpu,
+            data: gpu_task.to_bytes().unwrap(),
+            status: TaskStatus::Pending,
+            created_at: 0,
+            completed_at: None,
+            checkpoint: None,
+            deterministic: false,
+        };
+
+        // An invalid shader dispatched concurrently does not fail the valid task
+        let mut invalid = task.clone();
+        invalid.id = "gpu-invalid".to_string();
+        invalid.data = GpuTask { shader: "not wgsl".to_string(), ..gpu_task.clone() }.to_bytes().unwrap();
+        let progress = ProgressReporter::disabled("test");
+        let cancel = CancellationToken::new();
+        let (result, rejected) = tokio::join!(
+            executor.execute(&task, &progress, &cancel),
+            executor.execute(&invalid, &progress, &cancel),
+        );
+        assert!(rejected.is_err());
+        let output = GpuTaskOutput::from_bytes(&result.expect("GPU task should succeed").output).unwrap();
+        let doubled: &[f32] = bytemuck::cast_slice(&output.buffers[0].data);
+        assert_eq!(doubled[5], 10.0);
+        assert_eq!(doubled[127], 254.0);
+
+        // Uniform buffers are held to the smaller uniform binding limit
+        let max_uniform = executor.device.limits().max_uniform_buffer_binding_size as usize;
+        let oversized = GpuTask {
+            buffers: vec![GpuBufferDescriptor::uniform(0, vec![0; max_uniform + 4])],
+            ..gpu_task
+        };
+        assert!(validate_task(&executor.device, &oversized).is_err());
+    }
+}
@@ -0,0 +1,619 @@
+/* Copyright 2025 Joao Guimaraes, Catp2p Project
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Map-reduce job functionality built on top of the task scheduler.
+//!
+//! Each input chunk becomes a map task carrying a [`TaskPayload`] for the map function.
+//! Map outputs are streamed into the reducer as they complete: the reduce function
+//! receives a JSON-encoded `Vec<Vec<u8>>` of values (which may include an earlier
+//! partial result), so it must be associative and commutative.
+
+use crate::error::Error;
+use crate::tasks::cancel::CancellationToken;
+use crate::tasks::scheduler::TaskScheduler;
+use crate::tasks::{current_timestamp, Task, TaskPayload, TaskResourceType, TaskResult, TaskStatus};
+use futures::FutureExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+use tokio::time::Interval;
+
+/// Configuration for speculative re-execution of straggling map tasks.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpeculationConfig {
+    /// Whether speculative re-execution is enabled.
+    pub enabled: bool,
+    /// Fraction of map tasks (0.0 - 1.0) that must complete before speculating.
+    pub min_completed_fraction: f64,
+    /// A task is a straggler once it runs this many times longer than the median map task.
+    pub slowdown_factor: f64,
+    /// How often to check for stragglers.
+    pub check_interval: Duration,
+}
+
+impl Default for SpeculationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_completed_fraction: 0.75,
+            slowdown_factor: 1.5,
+            check_interval: Duration::from_millis(100),
+        }
+    }
+}
+
+impl SpeculationConfig {
+    /// Checks that the settings are usable when speculation is enabled.
+    pub fn validate(&self) -> Result<(), Error> {
+        if !self.enabled {
+            return Ok(());
+        }
+        if !(0.0..=1.0).contains(&self.min_completed_fraction) {
+            return Err(Error::Task(format!(
+                "Speculation min_completed_fraction must be between 0.0 and 1.0, got {}", self.min_completed_fraction
+            )));
+        }
+        if !self.slowdown_factor.is_finite() || self.slowdown_factor < 1.0 {
+            return Err(Error::Task(format!(
+                "Speculation slowdown_factor must be a finite number of at least 1.0, got {}", self.slowdown_factor
+            )));
+        }
+        if self.check_interval.is_zero() {
+            return Err(Error::Task("Speculation check_interval must be greater than zero".to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+/// A map-reduce job description.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MapReduceJob {
+    /// Job ID, used as a prefix for the generated task IDs.
+    pub id: String,
+    /// The input, split into chunks. One map task is created per chunk.
+    pub chunks: Vec<Vec<u8>>,
+    /// Identifier of the map function.
+    pub map_kind: String,
+    /// Identifier of the reduce function.
+    pub reduce_kind: String,
+    /// Resource type of the generated tasks.
+    pub resource_type: TaskResourceType,
+    /// Straggler handling.
+    pub speculation: SpeculationConfig,
+}
+
+impl MapReduceJob {
+    /// Creates a new CPU map-reduce job with the default speculation settings.
+    pub fn new(id: &str, chunks: Vec<Vec<u8>>, map_kind: &str, reduce_kind: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            chunks,
+            map_kind: map_kind.to_string(),
+            reduce_kind: reduce_kind.to_string(),
+            resource_type: TaskResourceType::Cpu,
+            speculation: SpeculationConfig::default(),
+        }
+    }
+}
+
+/// Statistics for a single input chunk.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkStats {
+    /// Index of the chunk in the job input.
+    pub chunk_index: usize,
+    /// Number of map attempts launched for the chunk.
+    pub attempts: u32,
+    /// Whether the accepted result came from a speculative attempt.
+    pub speculative: bool,
+    /// Wall-clock time of the accepted attempt.
+    pub duration: Duration,
+    /// Size of the chunk in bytes.
+    pub input_bytes: usize,
+    /// Size of the map output in bytes.
+    pub output_bytes: usize,
+}
+
+/// The result of a map-reduce job.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MapReduceResult {
+    /// Job ID.
+    pub job_id: String,
+    /// The final reduced output.
+    pub output: Vec<u8>,
+    /// Per-chunk statistics, ordered by chunk index.
+    pub chunk_stats: Vec<ChunkStats>,
+    /// Number of reduce tasks executed.
+    pub reduce_steps: usize,
+    /// Total wall-clock time of the job.
+    pub duration: Duration,
+}
+
+enum Outcome {
+    Map {
+        chunk_index: usize,
+        attempt: u32,
+        elapsed: Duration,
+        result: Result<Vec<u8>, Error>,
+    },
+    Reduce {
+        result: Result<Vec<u8>, Error>,
+    },
+}
+
+/// A task launched on the scheduler that has not been joined yet.
+struct Launched {
+    task_id: String,
+    cancel: CancellationToken,
+}
+
+struct Attempt {
+    attempt: u32,
+    started: Instant,
+    launched: Launched,
+}
+
+/// Runs map-reduce jobs through a task scheduler.
+pub struct MapReduceRunner {
+    scheduler: Arc<TaskScheduler>,
+}
+
+impl MapReduceRunner {
+    /// Creates a new MapReduceRunner that executes tasks through the given scheduler.
+    pub fn new(scheduler: Arc<TaskScheduler>) -> Self {
+        Self {
+            scheduler,
+        }
+    }
+
+    /// Runs a job to completion and returns the reduced output with per-chunk statistics.
+    pub async fn run(&self, job: MapReduceJob) -> Result<MapReduceResult, Error> {
+        if job.chunks.is_empty() {
+            return Err(Error::Task(format!("Map-reduce job {} has no input chunks", job.id)));
+        }
+        job.speculation.validate()?;
+
+        let start_time = Instant::now();
+        let chunk_count = job.chunks.len();
+        let mut tasks = JoinSet::new();
+
+        let mut in_flight: HashMap<usize, Vec<Attempt>> = HashMap::new();
+        let mut attempts = vec![0u32; chunk_count];
+        let mut stats: Vec<Option<ChunkStats>> = vec![None; chunk_count];
+        let mut completed_durations: Vec<Duration> = Vec::new();
+
+        let mut pending_values: Vec<Vec<u8>> = Vec::new();
+        let mut reducing: Option<Launched> = None;
+        let mut reduce_steps = 0;
+        let mut straggler_check = job.speculation.enabled
+            .then(|| tokio::time::interval(job.speculation.check_interval));
+
+        for chunk_index in 0..chunk_count {
+            let attempt = self.spawn_map(&mut tasks, &job, chunk_index, &mut attempts)?;
+            in_flight.entry(chunk_index).or_default().push(attempt);
+        }
+
+        loop {
+            let maps_done = completed_durations.len() == chunk_count;
+
+            if reducing.is_none() {
+                let final_reduce = maps_done && (pending_values.len() > 1 || reduce_steps == 0);
+                if pending_values.len() > 1 || final_reduce {
+                    let values = std::mem::take(&mut pending_values);
+                    reducing = Some(self.spawn_reduce(&mut tasks, &job, reduce_steps, values)?);
+                    reduce_steps += 1;
+                } else if maps_done {
+                    break;
+                }
+            }
+
+            let outcome = tokio::select! {
+                joined = tasks.join_next() => match joined {
+                    Some(Ok(outcome)) => outcome,
+                    Some(Err(e)) => return Err(Error::Task(format!("Map-reduce task failed to join: {}", e))),
+                    None => return Err(Error::Task(format!("Map-reduce job {} stalled", job.id))),
+                },
+                _ = tick(&mut straggler_check) => {
+                    for chunk_index in find_stragglers(&job.speculation, &in_flight, &completed_durations, chunk_count) {
+                        log::debug!("Speculatively re-executing chunk {} of job {}", chunk_index, job.id);
+                        let attempt = self.spawn_map(&mut tasks, &job, chunk_index, &mut attempts)?;
+                        in_flight.entry(chunk_index).or_default().push(attempt);
+                    }
+                    continue;
+                },
+            };
+
+            match outcome {
+                Outcome::Map { chunk_index, attempt, elapsed, result } => {
+                    if stats[chunk_index].is_some() {
+                        continue;
+                    }
+
+                    match result {
+                        Ok(output) => {
+                            for other in in_flight.remove(&chunk_index).unwrap_or_default() {
+                                if other.attempt != attempt {
+                                    self.cancel(&other.launched).await;
+                                }
+                            }
+                            stats[chunk_index] = Some(ChunkStats {
+                                chunk_index,
+                                attempts: attempts[chunk_index],
+                                speculative: attempt > 1,
+                                duration: elapsed,
+                                input_bytes: job.chunks[chunk_index].len(),
+                                output_bytes: output.len(),
+                            });
+                            completed_durations.push(elapsed);
+                            pending_values.push(output);
+                        },
+                        Err(e) => {
+                            let remaining = in_flight.get_mut(&chunk_index)
+                                .map(|running| {
+                                    running.retain(|other| other.attempt != attempt);
+                                    running.len()
+                                })
+                                .unwrap_or(0);
+                            if remaining == 0 {
+                                self.cancel_all(&mut tasks, &in_flight, reducing.as_ref()).await;
+                                return Err(Error::Task(format!(
+                                    "Map task for chunk {} of job {} failed: {}", chunk_index, job.id, e
+                                )));
+                            }
+                        },
+                    }
+                },
+                Outcome::Reduce { result } => {
+                    reducing = None;
+                    match result {
+                        Ok(output) => pending_values.insert(0, output),
+                        Err(e) => {
+                            self.cancel_all(&mut tasks, &in_flight, None).await;
+                            return Err(Error::Task(format!("Reduce task of job {} failed: {}", job.id, e)));
+                        },
+                    }
+                },
+            }
+        }
+
+        // Speculative attempts that lost were cancelled, and still finish through the scheduler
+        while tasks.join_next().await.is_some() {}
+
+        let output = pending_values.pop().unwrap_or_default();
+
+        Ok(MapReduceResult {
+            job_id: job.id,
+            output,
+            chunk_stats: stats.into_iter().flatten().collect(),
+            reduce_steps,
+            duration: start_time.elapsed(),
+        })
+    }
+
+    fn spawn_map(
+        &self,
+        tasks: &mut JoinSet<Outcome>,
+        job: &MapReduceJob,
+        chunk_index: usize,
+        attempts: &mut [u32],
+    ) -> Result<Attempt, Error> {
+        attempts[chunk_index] += 1;
+        let attempt = attempts[chunk_index];
+
+        let payload = TaskPayload::new(&job.map_kind, job.chunks[chunk_index].clone());
+        let task = new_task(
+            format!("{}-map-{}-{}", job.id, chunk_index, attempt),
+            job.resource_type,
+            payload.to_bytes()?,
+        );
+
+        let launched = Launched {
+            task_id: task.id.clone(),
+            cancel: CancellationToken::new(),
+        };
+        let scheduler = self.scheduler.clone();
+        let cancel = launched.cancel.clone();
+        let started = Instant::now();
+        tasks.spawn(async move {
+            let result = execute(&scheduler, &task, cancel).await;
+            Outcome::Map {
+                chunk_index,
+                attempt,
+                elapsed: started.elapsed(),
+                result,
+            }
+        });
+
+        Ok(Attempt {
+            attempt,
+            started,
+            launched,
+        })
+    }
+
+    fn spawn_reduce(
+        &self,
+        tasks: &mut JoinSet<Outcome>,
+        job: &MapReduceJob,
+        step: usize,
+        values: Vec<Vec<u8>>,
+    ) -> Result<Launched, Error> {
+        let input = serde_json::to_vec(&values).map_err(Error::Serialization)?;
+        let payload = TaskPayload::new(&job.reduce_kind, input);
+        let task = new_task(format!("{}-reduce-{}", job.id, step), job.resource_type, payload.to_bytes()?);
+
+        let launched = Launched {
+            task_id: task.id.clone(),
+            cancel: CancellationToken::new(),
+        };
+        let scheduler = self.scheduler.clone();
+        let cancel = launched.cancel.clone();
+        tasks.spawn(async move {
+            Outcome::Reduce {
+                result: execute(&scheduler, &task, cancel).await,
+            }
+        });
+
+        Ok(launched)
+    }
+
+    /// Cancels a launched task, whether it is queued, running or not yet polled.
+    async fn cancel(&self, launched: &Launched) {
+        launched.cancel.cancel();
+        if let Err(e) = self.scheduler.cancel_task(&launched.task_id).await {
+            log::warn!("Failed to cancel task {}: {}", launched.task_id, e);
+        }
+    }
+
+    /// Cancels every task still in flight and waits for them to finish, so that
+    /// their handlers stop and their final status is recorded.
+    async fn cancel_all(
+        &self,
+        tasks: &mut JoinSet<Outcome>,
+        in_flight: &HashMap<usize, Vec<Attempt>>,
+        reducing: Option<&Launched>,
+    ) {
+        for launched in in_flight.values().flatten().map(|attempt| &attempt.launched).chain(reducing) {
+            self.cancel(launched).await;
+        }
+        while tasks.join_next().await.is_some() {}
+    }
+}
+
+/// Returns the chunks whose only running attempt has exceeded the straggler threshold.
+fn find_stragglers(
+    config: &SpeculationConfig,
+    in_flight: &HashMap<usize, Vec<Attempt>>,
+    completed_durations: &[Duration],
+    chunk_count: usize,
+) -> Vec<usize> {
+    let completed_fraction = completed_durations.len() as f64 / chunk_count as f64;
+    if completed_durations.is_empty() || completed_fraction < config.min_completed_fraction {
+        return Vec::new();
+    }
+
+    let mut sorted = completed_durations.to_vec();
+    sorted.sort();
+    let threshold = sorted[sorted.len() / 2].mul_f64(config.slowdown_factor);
+
+    in_flight.iter()
+        .filter(|(_, running)| running.len() == 1 && running[0].started.elapsed() > threshold)
+        .map(|(chunk_index, _)| *chunk_index)
+        .collect()
+}
+
+/// Waits for the next tick of an interval, or forever if there is none.
+async fn tick(interval: &mut Option<Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        },
+        None => std::future::pending().await,
+    }
+}
+
+/// Executes a task once the scheduler has room for it, so large jobs wait instead of failing.
+async fn execute(scheduler: &TaskScheduler, task: &Task, cancel: CancellationToken) -> Result<Vec<u8>, Error> {
+    AssertUnwindSafe(scheduler.execute_queued(task, cancel))
+        .catch_unwind()
+        .await
+        .unwrap_or_else(|_| Err(Error::Task(format!("Task {} panicked", task.id))))
+        .and_then(TaskResult::into_output)
+}
+
+fn new_task(id: String, resource_type: TaskResourceType, data: Vec<u8>) -> Task {
+    Task {
+        id,
+        resource_type,
+        data,
+        status: TaskStatus::Pending,
+        created_at: current_timestamp(),
+        completed_at: None,
+        checkpoint: None,
+        deterministic: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ResourceMode;
+    use crate::resources::allocation::ResourceAllocator;
+    use crate::tasks::events::{ProgressReporter, TaskEventKind};
+    use crate::tasks::testing::resources;
+    use crate::tasks::{TaskExecutor, TaskResult};
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Maps a chunk to the sum of its bytes and reduces by summing partial sums.
+    struct SumExecutor;
+
+    #[async_trait]
+    impl TaskExecutor for SumExecutor {
+        async fn execute(&self, task: &Task, _progress: &ProgressReporter, cancel: &CancellationToken) -> Result<TaskResult, Error> {
+            // The first attempt of the first chunk runs until cancelled in these jobs
+            if task.id == "straggler-map-0-1" || task.id == "failing-map-0-1" {
+                cancel.cancelled().await;
+                return Ok(TaskResult::cancelled(Duration::ZERO));
+            }
+            if task.id == "failing-map-1-1" {
+                return Err(Error::Task("Chunk cannot be mapped".to_string()));
+            }
+            let payload = TaskPayload::from_bytes(&task.data)?;
+            let sum: u64 = match payload.kind.as_str() {
+                "sum-bytes" => payload.input.iter().map(|&b| b as u64).sum(),
+                "sum-values" => {
+                    let values: Vec<Vec<u8>> = serde_json::from_slice(&payload.input)?;
+                    values.iter()
+                        .map(|value| String::from_utf8_lossy(value).parse::<u64>().unwrap_or(0))
+                        .sum()
+                },
+                other => return Err(Error::Task(format!("Unknown kind: {}", other))),
+            };
+            Ok(TaskResult::completed(sum.to_string().into_bytes(), Duration::ZERO, 0))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_sums_chunks() {
+        let mut scheduler = TaskScheduler::new(4, Duration::from_secs(5));
+        scheduler.set_cpu_executor(Arc::new(SumExecutor));
+        let runner = MapReduceRunner::new(Arc::new(scheduler));
+
+        let chunks = vec![vec![1, 2, 3], vec![4, 5], vec![6], vec![7, 8, 9, 10]];
+        let result = runner.run(MapReduceJob::new("sum", chunks, "sum-bytes", "sum-values"))
+            .await
+            .expect("job should succeed");
+
+        assert_eq!(String::from_utf8(result.output).unwrap(), "55");
+        assert_eq!(result.chunk_stats.len(), 4);
+        assert!(result.reduce_steps >= 1);
+    }
+
+    /// Runs tasks on a [`SumExecutor`], recording how many run at the same time.
+    #[derive(Default)]
+    struct ConcurrencyExecutor {
+        running: AtomicUsize,
+        peak: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl TaskExecutor for ConcurrencyExecutor {
+        async fn execute(&self, task: &Task, progress: &ProgressReporter, cancel: &CancellationToken) -> Result<TaskResult, Error> {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(running, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            let result = SumExecutor.execute(task, progress, cancel).await;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            result
+        }
+    }
+
+    #[tokio::test]
+    async fn test_large_jobs_wait_for_the_scheduler() {
+        let executor = Arc::new(ConcurrencyExecutor::default());
+        // Three cores are available in high performance mode
+        let allocator = Arc::new(ResourceAllocator::new(ResourceMode::HighPerformance, None, resources(4)));
+        let mut scheduler = TaskScheduler::new(8, Duration::from_secs(5));
+        scheduler.set_cpu_executor(executor.clone());
+        scheduler.set_allocator(allocator.clone());
+        let runner = MapReduceRunner::new(Arc::new(scheduler));
+
+        let chunks: Vec<Vec<u8>> = (1..=40u8).map(|value| vec![value]).collect();
+        let mut job = MapReduceJob::new("fan-out", chunks, "sum-bytes", "sum-values");
+        job.speculation.enabled = false;
+        let result = runner.run(job).await.expect("job should succeed");
+
+        assert_eq!(String::from_utf8(result.output).unwrap(), "820");
+        assert!(result.chunk_stats.iter().all(|stats| stats.attempts == 1));
+        assert!(executor.peak.load(Ordering::SeqCst) <= 3);
+        assert_eq!(allocator.reserved().unwrap().cpu_cores, 0);
+    }
+
+    #[tokio::test]
+    async fn test_straggler_is_re_executed_speculatively() {
+        let mut scheduler = TaskScheduler::new(4, Duration::from_secs(3600));
+        scheduler.set_cpu_executor(Arc::new(SumExecutor));
+        let scheduler = Arc::new(scheduler);
+        let runner = MapReduceRunner::new(scheduler.clone());
+
+        let chunks = vec![vec![1, 2, 3], vec![4, 5], vec![6], vec![7, 8, 9, 10]];
+        let mut job = MapReduceJob::new("straggler", chunks, "sum-bytes", "sum-values");
+        // Only the stalled chunk is still running once three quarters have completed
+        job.speculation = SpeculationConfig {
+            enabled: true,
+            min_completed_fraction: 0.75,
+            slowdown_factor: 1.5,
+            check_interval: Duration::from_millis(10),
+        };
+        let result = tokio::time::timeout(Duration::from_secs(10), runner.run(job.clone()))
+            .await
+            .expect("the straggler should be re-executed")
+            .expect("job should succeed");
+
+        assert_eq!(String::from_utf8(result.output).unwrap(), "55");
+        let straggler = &result.chunk_stats[0];
+        assert_eq!(straggler.attempts, 2);
+        assert!(straggler.speculative);
+        assert!(result.chunk_stats[1..].iter().all(|stats| stats.attempts == 1 && !stats.speculative));
+        // The losing attempt is cancelled on the scheduler rather than left running
+        let loser = scheduler.get_finished_task("straggler-map-0-1").await.unwrap();
+        assert_eq!(loser.status, TaskStatus::Cancelled);
+        assert_eq!(scheduler.running_task_count().await, 0);
+
+        // Settings that would panic are rejected, unless speculation is disabled
+        job.speculation.slowdown_factor = f64::NAN;
+        assert!(runner.run(job.clone()).await.is_err());
+        job.speculation.slowdown_factor = 1.5;
+        job.speculation.check_interval = Duration::ZERO;
+        assert!(runner.run(job.clone()).await.is_err());
+        job.speculation.enabled = false;
+        job.id = "unspeculated".to_string();
+        assert!(runner.run(job).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_failed_job_cancels_running_tasks() {
+        let mut scheduler = TaskScheduler::new(4, Duration::from_secs(3600));
+        scheduler.set_cpu_executor(Arc::new(SumExecutor));
+        let scheduler = Arc::new(scheduler);
+        let runner = MapReduceRunner::new(scheduler.clone());
+        let mut events = scheduler.events().subscribe();
+
+        let mut job = MapReduceJob::new("failing", vec![vec![1], vec![2]], "sum-bytes", "sum-values");
+        job.speculation.enabled = false;
+        let result = tokio::time::timeout(Duration::from_secs(10), runner.run(job))
+            .await
+            .expect("running tasks should be cancelled");
+        assert!(result.is_err());
+
+        assert_eq!(scheduler.get_finished_task("failing-map-0-1").await.unwrap().status, TaskStatus::Cancelled);
+        assert_eq!(scheduler.running_task_count().await, 0);
+        // Every started task has a terminal event
+        let mut started = 0;
+        let mut terminal = 0;
+        while let Ok(event) = events.try_recv() {
+            match event.kind {
+                TaskEventKind::Started => started += 1,
+                kind if kind.is_terminal() => terminal += 1,
+                _ => {},
+            }
+        }
+        assert_eq!((started, terminal), (2, 2));
+    }
+}
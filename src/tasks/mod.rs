@@ -15,15 +15,45 @@
 
 //! Task management functionality for distributing and executing tasks.
 
+pub mod cache;
+pub mod cancel;
+pub mod checkpoint;
+pub mod clock;
 pub mod cpu;
+pub mod events;
 pub mod gpu;
+pub mod mapreduce;
+#[cfg(unix)]
+pub mod process;
+pub mod recurring;
+pub mod registry;
+pub mod remote;
 pub mod scheduler;
+pub mod stealing;
+pub mod store;
+#[cfg(test)]
+pub(crate) mod testing;
+pub mod verification;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod workflow;
 
 use crate::error::Error;
+use crate::resources::accounting::ResourceUsage;
+use crate::tasks::cancel::CancellationToken;
+use crate::tasks::checkpoint::{Checkpoint, CheckpointMetadata, CheckpointStore};
+use crate::tasks::events::{ProgressReporter, TaskEventKind, TaskEvents};
+use crate::tasks::scheduler::{TaskCompletion, TaskScheduler};
 use async_trait::async_trait;
+use futures::stream::{BoxStream, StreamExt};
 use serde::{Deserialize, Serialize};
-// Remove the following line:
-// use std::time::Instant; // Removed unused Duration import
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::Semaphore;
+
+/// Number of results buffered for the stream returned by `TaskManager::results`.
+const RESULTS_BUFFER: usize = 256;
 
 /// Task resource type.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -70,47 +100,524 @@ pub struct Task {
     pub created_at: u64,
     /// Task completion time, if completed.
     pub completed_at: Option<u64>,
+    /// Metadata of the latest checkpoint, if the task has been checkpointed.
+    #[serde(default)]
+    pub checkpoint: Option<CheckpointMetadata>,
+    /// Whether the task always produces the same result for the same data,
+    /// allowing its result to be cached and reused.
+    #[serde(default)]
+    pub deterministic: bool,
+}
+
+/// A function invocation carried in `Task.data`.
+///
+/// The `kind` identifies the function to run and `input` is passed to it unchanged.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskPayload {
+    /// Function identifier.
+    pub kind: String,
+    /// Function input.
+    pub input: Vec<u8>,
+}
+
+impl TaskPayload {
+    /// Creates a new TaskPayload.
+    pub fn new(kind: &str, input: Vec<u8>) -> Self {
+        Self {
+            kind: kind.to_string(),
+            input,
+        }
+    }
+
+    /// Serializes the payload to bytes suitable for `Task.data`.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        serde_json::to_vec(self).map_err(Error::Serialization)
+    }
+
+    /// Deserializes a payload from `Task.data`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        serde_json::from_slice(bytes).map_err(Error::Serialization)
+    }
+
+    /// Creates a payload by serializing a typed input as JSON.
+    pub fn typed<T: Serialize>(kind: &str, input: &T) -> Result<Self, Error> {
+        Ok(Self::new(kind, serde_json::to_vec(input).map_err(Error::Serialization)?))
+    }
+}
+
+/// Why a task stopped executing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExitReason {
+    /// The task ran to completion.
+    Completed,
+    /// The task reported a failure.
+    Failed(String),
+    /// The task exceeded its time limit.
+    TimedOut,
+    /// The task was cancelled.
+    Cancelled,
+}
+
+/// The result of executing a task.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskResult {
+    /// Output bytes produced by the task.
+    pub output: Vec<u8>,
+    /// CPU time consumed by the task.
+    pub cpu_time: Duration,
+    /// Peak resident memory observed while running the task, in bytes.
+    ///
+    /// Tasks run in-process on the CPU executor report 0.
+    pub peak_memory: u64,
+    /// GPU time consumed by the task.
+    #[serde(default)]
+    pub gpu_time: Duration,
+    /// Bytes written to disk by the task.
+    #[serde(default)]
+    pub disk_written: u64,
+    /// Why the task stopped.
+    pub exit_reason: ExitReason,
+}
+
+impl TaskResult {
+    /// Creates a result for a task that was cancelled before it finished.
+    pub fn cancelled(cpu_time: Duration) -> Self {
+        Self {
+            output: Vec::new(),
+            cpu_time,
+            peak_memory: 0,
+            gpu_time: Duration::ZERO,
+            disk_written: 0,
+            exit_reason: ExitReason::Cancelled,
+        }
+    }
+    
+    /// Creates a result for a task that completed with the given output.
+    pub fn completed(output: Vec<u8>, cpu_time: Duration, peak_memory: u64) -> Self {
+        Self {
+            output,
+            cpu_time,
+            peak_memory,
+            gpu_time: Duration::ZERO,
+            disk_written: 0,
+            exit_reason: ExitReason::Completed,
+        }
+    }
+
+    /// Returns the resources used by the task.
+    pub fn usage(&self) -> ResourceUsage {
+        ResourceUsage {
+            cpu_time: self.cpu_time,
+            gpu_time: self.gpu_time,
+            peak_memory: self.peak_memory,
+            disk_written: self.disk_written,
+        }
+    }
+
+    /// Sets the resources used by the task.
+    pub fn set_usage(&mut self, usage: &ResourceUsage) {
+        self.cpu_time = usage.cpu_time;
+        self.gpu_time = usage.gpu_time;
+        self.peak_memory = usage.peak_memory;
+        self.disk_written = usage.disk_written;
+    }
+
+    /// Returns the output if the task completed, or an error describing why it did not.
+    pub fn into_output(self) -> Result<Vec<u8>, Error> {
+        match self.exit_reason {
+            ExitReason::Completed => Ok(self.output),
+            ExitReason::Failed(reason) => Err(Error::Task(format!("Task failed: {}", reason))),
+            ExitReason::TimedOut => Err(Error::Task("Task timed out".to_string())),
+            ExitReason::Cancelled => Err(Error::Task("Task was cancelled".to_string())),
+        }
+    }
+}
+
+/// Context passed to task handlers while they run.
+#[derive(Clone)]
+pub struct TaskContext {
+    task_id: String,
+    checkpoints: Option<Arc<CheckpointStore>>,
+    resume_from: Option<Checkpoint>,
+    progress: ProgressReporter,
+    cancel: CancellationToken,
+}
+
+impl TaskContext {
+    /// Creates a new TaskContext without checkpoint support.
+    pub fn new(task_id: &str) -> Self {
+        Self {
+            task_id: task_id.to_string(),
+            checkpoints: None,
+            resume_from: None,
+            progress: ProgressReporter::disabled(task_id),
+            cancel: CancellationToken::new(),
+        }
+    }
+    
+    /// Creates a new TaskContext that saves checkpoints to the given store and
+    /// resumes from the latest checkpoint already stored for the task.
+    pub fn new_with_checkpoints(task_id: &str, checkpoints: Arc<CheckpointStore>) -> Result<Self, Error> {
+        let resume_from = checkpoints.latest(task_id)?;
+        
+        Ok(Self {
+            task_id: task_id.to_string(),
+            checkpoints: Some(checkpoints),
+            resume_from,
+            progress: ProgressReporter::disabled(task_id),
+            cancel: CancellationToken::new(),
+        })
+    }
+    
+    /// Returns the ID of the running task.
+    pub fn task_id(&self) -> &str {
+        &self.task_id
+    }
+    
+    /// Sets the reporter used for progress updates.
+    pub fn set_progress_reporter(&mut self, progress: ProgressReporter) {
+        self.progress = progress;
+    }
+    
+    /// Returns the reporter used for progress updates.
+    pub fn progress(&self) -> &ProgressReporter {
+        &self.progress
+    }
+    
+    /// Reports the fraction of work done together with a message.
+    pub fn report_progress(&self, fraction: f32, message: &str) {
+        self.progress.report(fraction, message);
+    }
+    
+    /// Sets the token used to signal cancellation.
+    pub fn set_cancellation_token(&mut self, cancel: CancellationToken) {
+        self.cancel = cancel;
+    }
+    
+    /// Returns whether the task has been cancelled.
+    ///
+    /// Long-running handlers should check this periodically and stop early; the
+    /// result of a cancelled task is discarded.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+    
+    /// Returns an error if the task has been cancelled, for use with `?`.
+    pub fn check_cancelled(&self) -> Result<(), Error> {
+        self.cancel.check()
+    }
+    
+    /// Returns the checkpoint data to resume from, if the task was checkpointed before.
+    pub fn resume_checkpoint(&self) -> Option<&[u8]> {
+        self.resume_from.as_ref().map(|checkpoint| checkpoint.data.as_slice())
+    }
+    
+    /// Emits a checkpoint from which the task can later be resumed.
+    ///
+    /// This is a no-op if the executor has no checkpoint store.
+    pub fn checkpoint(&self, data: Vec<u8>) -> Result<(), Error> {
+        if let Some(checkpoints) = &self.checkpoints {
+            checkpoints.save(&self.task_id, data)?;
+        }
+        
+        Ok(())
+    }
 }
 
 /// Task executor trait.
 #[async_trait]
 pub trait TaskExecutor {
-    /// Executes a task.
-    async fn execute(&self, task: &Task) -> Result<String, Error>;
+    /// Executes a task, reporting progress through the given handle.
+    ///
+    /// Once `cancel` is cancelled the executor should stop as soon as it can and
+    /// return a result with [`ExitReason::Cancelled`].
+    async fn execute(
+        &self,
+        task: &Task,
+        progress: &ProgressReporter,
+        cancel: &CancellationToken,
+    ) -> Result<TaskResult, Error>;
 }
 
 /// Task manager for distributing and executing tasks.
 pub struct TaskManager {
-    // Will add more fields as we implement the task management functionality
+    events: TaskEvents,
+    scheduler: Option<Arc<TaskScheduler>>,
+    result_cache: Option<Arc<cache::ResultCache>>,
+    slots: Arc<Semaphore>,
 }
 
 impl TaskManager {
     /// Creates a new TaskManager.
     pub fn new() -> Self {
-        Self {}
+        Self::new_with_events(TaskEvents::new())
+    }
+    
+    /// Creates a new TaskManager publishing to an existing event channel,
+    /// e.g. the one returned by `TaskScheduler::events`.
+    pub fn new_with_events(events: TaskEvents) -> Self {
+        Self {
+            events,
+            scheduler: None,
+            result_cache: None,
+            slots: Arc::new(Semaphore::new(1)),
+        }
+    }
+    
+    /// Creates a new TaskManager that runs and cancels tasks through a scheduler.
+    ///
+    /// Submitted tasks run at most `max_concurrent_tasks` at a time.
+    pub fn new_with_scheduler(scheduler: Arc<TaskScheduler>) -> Self {
+        Self {
+            events: scheduler.events().clone(),
+            // Shared with tasks queued on the scheduler directly, e.g. by workflows
+            slots: scheduler.slots().clone(),
+            scheduler: Some(scheduler),
+            result_cache: None,
+        }
+    }
+    
+    /// Sets the cache that results of deterministic tasks are reused from.
+    pub fn set_result_cache(&mut self, result_cache: Arc<cache::ResultCache>) {
+        self.result_cache = Some(result_cache);
+    }
+    
+    /// Subscribes to the lifecycle events of all tasks.
+    pub fn subscribe(&self) -> tokio::sync::broadcast::Receiver<events::TaskEvent> {
+        self.events.subscribe()
+    }
+    
+    /// Returns the event channel used by this manager.
+    pub fn events(&self) -> &TaskEvents {
+        &self.events
+    }
+    
+    /// Submits a task for execution, returning its assigned ID.
+    pub async fn submit_task(&self, task: Task) -> Result<String, Error> {
+        self.submit_batch(vec![task]).await?
+            .pop()
+            .ok_or_else(|| Error::Task("No ID assigned to submitted task".to_string()))
+    }
+    
+    /// Submits a batch of tasks for execution, returning their assigned IDs.
+    ///
+    /// The whole batch is persisted in one transaction before any task is queued.
+    /// Results are published to the stream returned by [`TaskManager::results`].
+    pub async fn submit_batch(&self, tasks: Vec<Task>) -> Result<Vec<String>, Error> {
+        let scheduler = self.require_scheduler()?;
+        let store = scheduler.task_store()
+            .ok_or_else(|| Error::Task("No task store to persist tasks in".to_string()))?;
+        
+        let tasks = store.insert_batch(tasks)?;
+        let task_ids = tasks.iter().map(|task| task.id.clone()).collect();
+        for task in tasks {
+            self.events.emit_kind(&task.id, TaskEventKind::Submitted);
+            scheduler.schedule_submitted(task).await?;
+        }
+        
+        self.dispatch(scheduler);
+        Ok(task_ids)
+    }
+    
+    /// Queues the persisted tasks that did not finish, e.g. after a restart.
+    ///
+    /// Returns the number of queued tasks.
+    pub async fn resubmit_unfinished(&self) -> Result<usize, Error> {
+        let scheduler = self.require_scheduler()?;
+        let store = scheduler.task_store()
+            .ok_or_else(|| Error::Task("No task store to resubmit tasks from".to_string()))?;
+        
+        let tasks = store.unfinished()?;
+        let count = tasks.len();
+        for mut task in tasks {
+            task.status = TaskStatus::Pending;
+            scheduler.schedule_submitted(task).await?;
+        }
+        
+        self.dispatch(scheduler);
+        Ok(count)
     }
     
-    /// Submits a task for execution.
-    pub fn submit_task(&self, _task: Task) -> Result<String, Error> {
-        // Implementation will be added later
-        Ok("task-id".to_string())
+    /// Queues the delayed tasks and recurring runs that are due, and runs them
+    /// together with any tasks still waiting for the scheduler to resume.
+    ///
+    /// Returns the number of queued tasks.
+    pub async fn tick(&self) -> Result<usize, Error> {
+        let scheduler = self.require_scheduler()?;
+        
+        let queued = scheduler.tick().await?;
+        if queued > 0 || scheduler.pending_task_count().await > 0 {
+            self.dispatch(scheduler);
+        }
+        
+        Ok(queued)
     }
     
-    /// Cancels a task.
-    pub fn cancel_task(&self, _task_id: &str) -> Result<(), Error> {
-        // Implementation will be added later
+    /// Spawns a background task calling [`TaskManager::tick`] every `period`.
+    pub fn spawn_ticker(self: Arc<Self>, period: Duration) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                if let Err(e) = self.tick().await {
+                    log::warn!("Failed to queue scheduled tasks: {}", e);
+                }
+            }
+        })
+    }
+    
+    /// Returns a stream of the results of submitted tasks in completion order.
+    ///
+    /// Subscribe before submitting tasks, since earlier results are not replayed.
+    /// Every stream receives every result. Once a stream falls `RESULTS_BUFFER`
+    /// results behind, no further tasks are dispatched until it catches up or is
+    /// dropped.
+    pub async fn results(&self) -> Result<BoxStream<'static, TaskCompletion>, Error> {
+        let receiver = self.require_scheduler()?.subscribe_results(RESULTS_BUFFER).await;
+        
+        Ok(futures::stream::unfold(receiver, |mut receiver| async move {
+            receiver.recv().await.map(|completion| (completion, receiver))
+        }).boxed())
+    }
+    
+    /// Executes a task through the scheduler and waits for its result.
+    ///
+    /// Deterministic tasks are looked up in the result cache first, locally and then
+    /// on peers, and are only scheduled if no cached result is found.
+    pub async fn execute_task(&self, task: Task) -> Result<TaskResult, Error> {
+        let scheduler = self.require_scheduler()?;
+        self.events.emit_kind(&task.id, TaskEventKind::Submitted);
+        
+        // Tasks executed directly are not persisted
+        run_task(scheduler, self.result_cache.as_deref(), &task).await
+    }
+    
+    /// Cancels a pending, running or offloaded task.
+    ///
+    /// Running tasks are signalled through their cancellation token and their
+    /// resources are released immediately; the task is recorded as cancelled.
+    pub async fn cancel_task(&self, task_id: &str) -> Result<(), Error> {
+        let scheduler = self.require_scheduler()?;
+
+        if !scheduler.cancel_task(task_id).await? {
+            return Err(Error::Task(format!("Task {} is not pending or running", task_id)));
+        }
+
         Ok(())
     }
     
     /// Gets the status of a task.
-    pub fn get_task_status(&self, _task_id: &str) -> Result<TaskStatus, Error> {
-        // Implementation will be added later
-        Ok(TaskStatus::Pending)
+    pub async fn get_task_status(&self, task_id: &str) -> Result<TaskStatus, Error> {
+        let scheduler = self.require_scheduler()?;
+        if let Some(status) = scheduler.get_task_status(task_id).await {
+            return Ok(status);
+        }
+        
+        if let Some(store) = scheduler.task_store() {
+            if let Some(task) = store.get(task_id)? {
+                return Ok(task.status);
+            }
+        }
+        
+        Err(Error::Task(format!("Unknown task {}", task_id)))
+    }
+    
+    /// Runs queued tasks, at most `max_concurrent_tasks` at a time, until the queue is empty.
+    fn dispatch(&self, scheduler: &Arc<TaskScheduler>) {
+        let scheduler = scheduler.clone();
+        let cache = self.result_cache.clone();
+        let slots = self.slots.clone();
+        
+        tokio::spawn(async move {
+            loop {
+                let permit = match slots.clone().acquire_owned().await {
+                    Ok(permit) => permit,
+                    Err(_) => break,
+                };
+                // The next tick resumes dispatching once throttling allows it
+                if !scheduler.can_start_task().await {
+                    break;
+                }
+                // Tasks may have been stolen or taken by another dispatcher meanwhile
+                let task = match scheduler.pop_pending().await {
+                    Some(task) => task,
+                    None => break,
+                };
+                
+                let scheduler = scheduler.clone();
+                let cache = cache.clone();
+                tokio::spawn(async move {
+                    let _permit = permit;
+                    // The outcome is persisted and published by the scheduler
+                    let _ = run_task(&scheduler, cache.as_deref(), &task).await;
+                });
+            }
+        });
+    }
+    
+    fn require_scheduler(&self) -> Result<&Arc<TaskScheduler>, Error> {
+        self.scheduler.as_ref()
+            .ok_or_else(|| Error::Task("No scheduler to run tasks on".to_string()))
     }
 }
 
+/// Runs a task through the scheduler, reusing the cached result of a deterministic task.
+async fn run_task(
+    scheduler: &TaskScheduler,
+    cache: Option<&cache::ResultCache>,
+    task: &Task,
+) -> Result<TaskResult, Error> {
+    let cache = match cache {
+        Some(cache) if task.deterministic => Some((cache, cache::cache_key(task)?)),
+        _ => None,
+    };
+    
+    let mut cached = None;
+    if let Some((cache, key)) = &cache {
+        // A failed lookup only means the task runs again
+        match cache.lookup(key).await {
+            Ok(result) => cached = result,
+            Err(e) => log::warn!("Failed to look up cached result of task {}: {}", task.id, e),
+        }
+    }
+    
+    let was_cached = cached.is_some();
+    let result = match cached {
+        Some(result) => {
+            let result = Ok(result);
+            scheduler.finish(task, &result).await;
+            result
+        },
+        None => scheduler.execute_task(task).await,
+    };
+    
+    if let (Some((cache, key)), Ok(result), false) = (&cache, &result, was_cached) {
+        // A result that cannot be cached is still a valid result
+        if let Err(e) = cache.insert(key, result).await {
+            log::warn!("Failed to cache result of task {}: {}", task.id, e);
+        }
+    }
+    
+    result
+}
+
 impl Default for TaskManager {
     fn default() -> Self {
         Self::new()
     }
 }
+
+/// Gets the current timestamp in seconds.
+pub(crate) fn current_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+/// Computes the hex-encoded SHA-256 hash of the given data.
+pub fn content_hash(data: &[u8]) -> String {
+    Sha256::digest(data).iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
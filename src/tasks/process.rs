@@ -0,0 +1,617 @@
+/* Copyright 2025 Joao Guimaraes, Catp2p Project
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Subprocess task execution functionality.
+//!
+//! Runs whitelisted commands in their own process group and a scratch working
+//! directory that is removed afterwards. On Linux, CPU time and address-space
+//! limits are applied with `setrlimit` before the command starts, the command
+//! joins the workers' cgroup if one is enforced, and the CPU time and peak memory
+//! of the command are read from `/proc`. With a disk quota, the command is killed
+//! once its scratch directory grows beyond the space left in the quota.
+
+use crate::error::Error;
+use crate::resources::accounting::{directory_size, ProcessUsageTracker};
+use crate::resources::cgroup::CgroupManager;
+use crate::storage::quota::DiskQuota;
+use crate::tasks::cancel::CancellationToken;
+use crate::tasks::events::ProgressReporter;
+use crate::tasks::{ExitReason, Task, TaskExecutor, TaskResult};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::process::{Child, Command};
+
+/// Environment variable holding the path of the input file in `File` input mode.
+pub const INPUT_FILE_ENV: &str = "CATP2P_INPUT_FILE";
+
+/// Placeholder in command arguments that is replaced by the input file path.
+pub const INPUT_FILE_PLACEHOLDER: &str = "{input}";
+
+/// How often the size of a scratch directory is checked against the disk quota.
+const SCRATCH_CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How task data is handed to the command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InputMode {
+    /// Task data is written to the command's stdin.
+    Stdin,
+    /// Task data is written to a file in the scratch directory.
+    File,
+}
+
+/// The contents of `Task.data` for a subprocess task.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessTask {
+    /// Name of a whitelisted command.
+    pub command: String,
+    /// Arguments passed to the command.
+    pub args: Vec<String>,
+    /// Task input.
+    pub input: Vec<u8>,
+    /// How the input is handed to the command.
+    pub input_mode: InputMode,
+}
+
+impl ProcessTask {
+    /// Serializes the task to bytes suitable for `Task.data`.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        serde_json::to_vec(self).map_err(Error::Serialization)
+    }
+
+    /// Deserializes a task from `Task.data`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        serde_json::from_slice(bytes).map_err(Error::Serialization)
+    }
+}
+
+/// The output of a subprocess task, stored in `TaskResult.output`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessOutput {
+    /// Exit code, if the process exited normally.
+    pub exit_code: Option<i32>,
+    /// Signal that terminated the process, if any.
+    pub signal: Option<i32>,
+    /// Captured stdout.
+    pub stdout: Vec<u8>,
+    /// Captured stderr.
+    pub stderr: Vec<u8>,
+}
+
+impl ProcessOutput {
+    /// Serializes the output to bytes.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        serde_json::to_vec(self).map_err(Error::Serialization)
+    }
+
+    /// Deserializes the output from `TaskResult.output`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        serde_json::from_slice(bytes).map_err(Error::Serialization)
+    }
+}
+
+/// Limits and settings for subprocess tasks.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessConfig {
+    /// Directory under which per-task scratch directories are created.
+    pub scratch_root: PathBuf,
+    /// Wall-clock timeout per task.
+    pub timeout: Duration,
+    /// CPU time limit per task (Linux only).
+    pub cpu_time_limit: Option<Duration>,
+    /// Address-space limit per task in bytes (Linux only).
+    pub memory_limit: Option<u64>,
+    /// Maximum number of bytes captured from stdout and from stderr.
+    pub max_output_bytes: usize,
+}
+
+impl Default for ProcessConfig {
+    fn default() -> Self {
+        Self {
+            scratch_root: std::env::temp_dir().join("catp2p-scratch"),
+            timeout: Duration::from_secs(3600),
+            cpu_time_limit: None,
+            memory_limit: None,
+            max_output_bytes: 16 * 1024 * 1024, // 16 MB
+        }
+    }
+}
+
+/// A task executor that runs whitelisted commands as subprocesses.
+pub struct ProcessTaskExecutor {
+    config: ProcessConfig,
+    allowed_commands: HashMap<String, PathBuf>,
+    cgroup: Option<Arc<CgroupManager>>,
+    quota: Option<Arc<DiskQuota>>,
+}
+
+impl ProcessTaskExecutor {
+    /// Creates a new ProcessTaskExecutor with no whitelisted commands.
+    pub fn new(config: ProcessConfig) -> Self {
+        Self {
+            config,
+            allowed_commands: HashMap::new(),
+            cgroup: None,
+            quota: None,
+        }
+    }
+    
+    /// Sets the disk quota that input files and scratch directories must fit in.
+    pub fn set_quota(&mut self, quota: Arc<DiskQuota>) {
+        self.quota = Some(quota);
+    }
+    
+    /// Sets the cgroup that commands run in.
+    ///
+    /// Commands run outside of any cgroup while it is not enforced.
+    pub fn set_cgroup(&mut self, cgroup: Arc<CgroupManager>) {
+        self.cgroup = Some(cgroup);
+    }
+
+    /// Whitelists a command under the given name.
+    pub fn allow_command<P: Into<PathBuf>>(&mut self, name: &str, program: P) {
+        self.allowed_commands.insert(name.to_string(), program.into());
+    }
+
+    /// Returns the scratch root directory.
+    pub fn scratch_root(&self) -> &Path {
+        &self.config.scratch_root
+    }
+}
+
+#[async_trait]
+impl TaskExecutor for ProcessTaskExecutor {
+    async fn execute(
+        &self,
+        task: &Task,
+        _progress: &ProgressReporter,
+        cancel: &CancellationToken,
+    ) -> Result<TaskResult, Error> {
+        let process_task = ProcessTask::from_bytes(&task.data)?;
+        let program = self.allowed_commands.get(&process_task.command)
+            .ok_or_else(|| Error::Task(format!("Command is not whitelisted: {}", process_task.command)))?;
+
+        let input_len = process_task.input.len() as u64;
+        // Only input files take up disk space; the scratch directory may grow by what is left
+        let scratch_limit = match &self.quota {
+            Some(quota) if process_task.input_mode == InputMode::File => {
+                quota.check_write(input_len)?;
+                Some(quota.remaining()?.saturating_add(input_len))
+            },
+            Some(quota) => Some(quota.remaining()?),
+            None => None,
+        };
+        let scratch = ScratchDir::create(&self.config.scratch_root, &task.id)?;
+
+        let mut args = process_task.args.clone();
+        let mut command = Command::new(program);
+        command
+            .current_dir(scratch.path())
+            .env_clear()
+            .env("PATH", "/usr/bin:/bin")
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .process_group(0);
+
+        match process_task.input_mode {
+            InputMode::Stdin => {
+                command.stdin(Stdio::piped());
+            },
+            InputMode::File => {
+                let input_path = scratch.path().join("input");
+                tokio::fs::write(&input_path, &process_task.input).await?;
+                let input_path = input_path.to_string_lossy().into_owned();
+                for arg in args.iter_mut() {
+                    *arg = arg.replace(INPUT_FILE_PLACEHOLDER, &input_path);
+                }
+                command.env(INPUT_FILE_ENV, &input_path).stdin(Stdio::null());
+            },
+        }
+        command.args(&args);
+
+        #[cfg(target_os = "linux")]
+        apply_rlimits(&mut command, self.config.cpu_time_limit, self.config.memory_limit, scratch_limit);
+        // Kept open until the command has started
+        #[cfg(target_os = "linux")]
+        let _cgroup_procs = match self.cgroup.as_deref().filter(|cgroup| cgroup.is_enforced()) {
+            Some(cgroup) => {
+                let procs = cgroup.open_procs()?;
+                join_cgroup(&mut command, &procs);
+                Some(procs)
+            },
+            None => None,
+        };
+
+        let mut child = command.spawn()
+            .map_err(|e| Error::Task(format!("Failed to start {}: {}", process_task.command, e)))?;
+        // Kills the whole process group if this future is dropped (e.g. the task is cancelled)
+        let mut group = ProcessGroupGuard::new(&child);
+        let pid = child.id()
+            .ok_or_else(|| Error::Task(format!("{} exited before it could be tracked", process_task.command)))?;
+        let tracker = ProcessUsageTracker::start(pid);
+        // The exited process is left unreaped so that its CPU time can still be read
+        let mut exited = tokio::task::spawn_blocking(move || wait_for_exit(pid));
+
+        if process_task.input_mode == InputMode::Stdin {
+            if let Some(mut stdin) = child.stdin.take() {
+                let input = process_task.input;
+                tokio::spawn(async move {
+                    // The command may exit without reading all of its input
+                    let _ = stdin.write_all(&input).await;
+                });
+            }
+        }
+
+        let limit = self.config.max_output_bytes;
+        let stdout = tokio::spawn(read_capped(child.stdout.take(), limit));
+        let stderr = tokio::spawn(read_capped(child.stderr.take(), limit));
+
+        let waited = tokio::select! {
+            waited = tokio::time::timeout(self.config.timeout, &mut exited) => match waited {
+                Ok(_) => Waited::Exited,
+                Err(_) => Waited::TimedOut,
+            },
+            _ = cancel.cancelled() => Waited::Cancelled,
+            _ = scratch_exceeded(scratch.path(), scratch_limit) => Waited::QuotaExceeded,
+        };
+        if waited != Waited::Exited {
+            group.kill();
+            let _ = exited.await;
+        }
+        let stats = tracker.finish();
+
+        let (status, exit_reason) = match waited {
+            Waited::Exited => {
+                let status = child.wait().await?;
+                let exit_reason = match (status.code(), status.signal()) {
+                    (Some(0), _) => ExitReason::Completed,
+                    (Some(code), _) => ExitReason::Failed(format!("exited with code {}", code)),
+                    (None, Some(signal)) => ExitReason::Failed(format!("terminated by signal {}", signal)),
+                    (None, None) => ExitReason::Failed("terminated abnormally".to_string()),
+                };
+                (Some(status), exit_reason)
+            },
+            Waited::TimedOut => {
+                let _ = child.wait().await;
+                (None, ExitReason::TimedOut)
+            },
+            Waited::Cancelled => {
+                let _ = child.wait().await;
+                (None, ExitReason::Cancelled)
+            },
+            Waited::QuotaExceeded => {
+                let _ = child.wait().await;
+                let reason = format!("scratch directory exceeded the disk quota of {} bytes", scratch_limit.unwrap_or_default());
+                (None, ExitReason::Failed(reason))
+            },
+        };
+        // Reap any processes the command left behind in its group
+        group.kill();
+
+        let mut disk_written = directory_size(scratch.path());
+        if process_task.input_mode == InputMode::File {
+            disk_written = disk_written.saturating_sub(input_len);
+        }
+
+        let output = ProcessOutput {
+            exit_code: status.and_then(|status| status.code()),
+            signal: status.and_then(|status| status.signal()),
+            stdout: stdout.await.map_err(|e| Error::Task(format!("Failed to read stdout: {}", e)))?,
+            stderr: stderr.await.map_err(|e| Error::Task(format!("Failed to read stderr: {}", e)))?,
+        };
+
+        Ok(TaskResult {
+            output: output.to_bytes()?,
+            cpu_time: stats.cpu_time,
+            peak_memory: stats.peak_memory,
+            gpu_time: Duration::ZERO,
+            disk_written,
+            exit_reason,
+        })
+    }
+}
+
+/// Why waiting for a command ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Waited {
+    Exited,
+    TimedOut,
+    Cancelled,
+    QuotaExceeded,
+}
+
+/// Completes once the scratch directory grows beyond `limit` bytes, or never without a limit.
+async fn scratch_exceeded(scratch: &Path, limit: Option<u64>) {
+    let Some(limit) = limit else {
+        return std::future::pending().await;
+    };
+
+    let mut interval = tokio::time::interval(SCRATCH_CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        let scratch = scratch.to_path_buf();
+        // Walking the directory blocks
+        let size = tokio::task::spawn_blocking(move || directory_size(&scratch)).await.unwrap_or(0);
+        if size > limit {
+            return;
+        }
+    }
+}
+
+/// Waits until a child process exits, without reaping it.
+fn wait_for_exit(pid: u32) -> std::io::Result<()> {
+    loop {
+        // SAFETY: siginfo_t is a plain C struct, for which all zeroes is a valid value.
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        // SAFETY: `info` is a valid siginfo_t for the duration of the call.
+        let waited = unsafe {
+            libc::waitid(libc::P_PID, pid as libc::id_t, &mut info, libc::WEXITED | libc::WNOWAIT)
+        };
+        if waited == 0 {
+            return Ok(());
+        }
+
+        let error = std::io::Error::last_os_error();
+        if error.kind() != std::io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+}
+
+/// Reads up to `limit` bytes from a pipe and discards the rest.
+async fn read_capped<R: AsyncRead + Unpin>(reader: Option<R>, limit: usize) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    if let Some(mut reader) = reader {
+        let _ = (&mut reader).take(limit as u64).read_to_end(&mut buffer).await;
+        let _ = tokio::io::copy(&mut reader, &mut tokio::io::sink()).await;
+    }
+    buffer
+}
+
+/// Limits the CPU time, address space and size of each written file of the command.
+#[cfg(target_os = "linux")]
+fn apply_rlimits(command: &mut Command, cpu_time_limit: Option<Duration>, memory_limit: Option<u64>, file_size_limit: Option<u64>) {
+    if cpu_time_limit.is_none() && memory_limit.is_none() && file_size_limit.is_none() {
+        return;
+    }
+
+    // SAFETY: the closure only calls setrlimit, which is async-signal-safe.
+    unsafe {
+        command.pre_exec(move || {
+            if let Some(limit) = cpu_time_limit {
+                let seconds = limit.as_secs().max(1) as libc::rlim_t;
+                set_rlimit(libc::RLIMIT_CPU, seconds)?;
+            }
+            if let Some(limit) = memory_limit {
+                set_rlimit(libc::RLIMIT_AS, limit as libc::rlim_t)?;
+            }
+            if let Some(limit) = file_size_limit {
+                set_rlimit(libc::RLIMIT_FSIZE, limit as libc::rlim_t)?;
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Moves the command into a cgroup before it starts, through an open `cgroup.procs`.
+#[cfg(target_os = "linux")]
+fn join_cgroup(command: &mut Command, procs: &std::fs::File) {
+    use std::os::fd::AsRawFd;
+
+    let fd = procs.as_raw_fd();
+    // SAFETY: the closure only calls write, which is async-signal-safe, on a
+    // descriptor that stays open until the command has started.
+    unsafe {
+        command.pre_exec(move || {
+            // Writing 0 moves the writing process
+            if libc::write(fd, b"0".as_ptr().cast(), 1) < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+type RlimitResource = libc::__rlimit_resource_t;
+#[cfg(all(target_os = "linux", not(target_env = "gnu")))]
+type RlimitResource = libc::c_int;
+
+#[cfg(target_os = "linux")]
+fn set_rlimit(resource: RlimitResource, value: libc::rlim_t) -> std::io::Result<()> {
+    let limit = libc::rlimit {
+        rlim_cur: value,
+        rlim_max: value,
+    };
+    // SAFETY: `limit` is a valid rlimit for the duration of the call.
+    if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Kills a child's process group when dropped, unless it has already been killed.
+struct ProcessGroupGuard {
+    pgid: Option<i32>,
+}
+
+impl ProcessGroupGuard {
+    fn new(child: &Child) -> Self {
+        Self {
+            pgid: child.id().map(|id| id as i32),
+        }
+    }
+
+    fn kill(&mut self) {
+        if let Some(pgid) = self.pgid.take() {
+            // SAFETY: kill has no memory-safety preconditions; a negative pid targets the group.
+            unsafe {
+                libc::kill(-pgid, libc::SIGKILL);
+            }
+        }
+    }
+}
+
+impl Drop for ProcessGroupGuard {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
+
+/// A per-task scratch directory that is removed when dropped.
+struct ScratchDir {
+    path: PathBuf,
+}
+
+impl ScratchDir {
+    fn create(root: &Path, task_id: &str) -> Result<Self, Error> {
+        let sanitized: String = task_id.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+        let path = root.join(format!("{}-{:016x}", sanitized, rand::random::<u64>()));
+        std::fs::create_dir_all(&path)?;
+        Ok(Self {
+            path,
+        })
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.path) {
+            log::warn!("Failed to remove scratch directory {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tasks::{TaskResourceType, TaskStatus};
+    use std::time::Instant;
+
+    fn executor(config: ProcessConfig) -> ProcessTaskExecutor {
+        let mut executor = ProcessTaskExecutor::new(config);
+        executor.allow_command("sh", "/bin/sh");
+        executor
+    }
+
+    fn shell_task(script: &str, input: &[u8], input_mode: InputMode) -> Task {
+        let process_task = ProcessTask {
+            command: "sh".to_string(),
+            args: vec!["-c".to_string(), script.to_string()],
+            input: input.to_vec(),
+            input_mode,
+        };
+        Task {
+            id: "process-1".to_string(),
+            resource_type: TaskResourceType::Cpu,
+            data: process_task.to_bytes().unwrap(),
+            status: TaskStatus::Pending,
+            created_at: 0,
+            completed_at: None,
+            checkpoint: None,
+            deterministic: false,
+        }
+    }
+
+    fn test_config() -> ProcessConfig {
+        ProcessConfig {
+            scratch_root: std::env::temp_dir().join(format!("catp2p-process-test-{:016x}", rand::random::<u64>())),
+            timeout: Duration::from_secs(10),
+            ..ProcessConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_collects_output_and_exit_code() {
+        let config = test_config();
+        let scratch_root = config.scratch_root.clone();
+        let executor = executor(config);
+
+        let task = shell_task("tr a-z A-Z; echo oops >&2; exit 3", b"hello", InputMode::Stdin);
+        let result = executor.execute(&task, &ProgressReporter::disabled("test"), &CancellationToken::new()).await.unwrap();
+        let output = ProcessOutput::from_bytes(&result.output).unwrap();
+
+        assert_eq!(output.stdout, b"HELLO");
+        assert_eq!(output.stderr, b"oops\n");
+        assert_eq!(output.exit_code, Some(3));
+        assert!(matches!(result.exit_reason, ExitReason::Failed(_)));
+        assert_eq!(std::fs::read_dir(&scratch_root).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_input_file_mode() {
+        let executor = executor(test_config());
+
+        let task = shell_task("cat \"$CATP2P_INPUT_FILE\" {input}", b"data", InputMode::File);
+        let result = executor.execute(&task, &ProgressReporter::disabled("test"), &CancellationToken::new()).await.unwrap();
+
+        assert_eq!(ProcessOutput::from_bytes(&result.output).unwrap().stdout, b"datadata");
+        assert_eq!(result.exit_reason, ExitReason::Completed);
+    }
+
+    #[tokio::test]
+    async fn test_timeout_kills_process_group() {
+        let executor = executor(ProcessConfig {
+            timeout: Duration::from_millis(200),
+            ..test_config()
+        });
+
+        let start = Instant::now();
+        let task = shell_task("sleep 30 & sleep 30", b"", InputMode::Stdin);
+        let result = executor.execute(&task, &ProgressReporter::disabled("test"), &CancellationToken::new()).await.unwrap();
+
+        assert_eq!(result.exit_reason, ExitReason::TimedOut);
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[tokio::test]
+    async fn test_rejects_unlisted_command() {
+        let executor = ProcessTaskExecutor::new(test_config());
+        assert!(executor.execute(&shell_task("true", b"", InputMode::Stdin), &ProgressReporter::disabled("test"), &CancellationToken::new()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_scratch_directory_is_limited_by_quota() {
+        let mut executor = executor(test_config());
+        executor.set_quota(Arc::new(DiskQuota::new(100_000)));
+
+        // Input passed on stdin takes up no disk space
+        let task = shell_task("wc -c", &[0; 200_000], InputMode::Stdin);
+        let result = executor.execute(&task, &ProgressReporter::disabled("test"), &CancellationToken::new()).await.unwrap();
+        assert_eq!(result.exit_reason, ExitReason::Completed);
+
+        // Files that fit individually but not together exceed the quota
+        let script = "for i in 1 2 3 4; do head -c 40000 /dev/zero > out$i; done; sleep 30";
+        let start = Instant::now();
+        let result = executor.execute(&shell_task(script, b"", InputMode::Stdin), &ProgressReporter::disabled("test"), &CancellationToken::new()).await.unwrap();
+        assert!(matches!(result.exit_reason, ExitReason::Failed(reason) if reason.contains("disk quota")));
+        assert!(start.elapsed() < Duration::from_secs(10));
+
+        // Input files are charged against the quota
+        assert!(executor.execute(&shell_task("true", &[0; 200_000], InputMode::File), &ProgressReporter::disabled("test"), &CancellationToken::new()).await.is_err());
+    }
+}
@@ -0,0 +1,479 @@
+/* Copyright 2025 Joao Guimaraes, Catp2p Project
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Recurring task schedules.
+//!
+//! A [`RecurringSchedule`] queues a copy of a template task whenever its
+//! [`Recurrence`] fires, either at a fixed interval or according to a cron
+//! expression. Runs missed while the node was down are handled according to the
+//! schedule's [`MissedRunPolicy`].
+
+use crate::error::Error;
+use crate::storage::db::{Database, Tree};
+use crate::tasks::{Task, TaskStatus};
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, Timelike};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Name of the database tree holding recurring schedules.
+const RECURRING_TREE: &str = "recurring-schedules";
+
+/// Maximum number of missed runs queued at once when catching up.
+const MAX_CATCH_UP_RUNS: usize = 100;
+
+/// How far ahead a cron expression is searched for its next match.
+const CRON_SEARCH_DAYS: i64 = 5 * 366;
+
+/// Default lateness after which a run counts as missed.
+const DEFAULT_MISFIRE_GRACE: Duration = Duration::from_secs(60);
+
+/// A cron expression with the five standard fields, evaluated in UTC.
+///
+/// Each field accepts `*`, single values, ranges (`1-5`), lists (`1,15`) and steps
+/// (`*/15`, `0-30/10`). Days of the week count from 0 (Sunday) to 6, with 7 also
+/// meaning Sunday. As in classic cron, if both the day of the month and the day of
+/// the week are restricted, a day matching either of them matches.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct CronSchedule {
+    expression: String,
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    day_of_month_restricted: bool,
+    day_of_week_restricted: bool,
+}
+
+impl CronSchedule {
+    /// Parses a cron expression such as `0 3 * * *` (every day at 03:00 UTC).
+    pub fn parse(expression: &str) -> Result<Self, Error> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(Error::Task(format!(
+                "Cron expression '{}' must have 5 fields, found {}", expression, fields.len()
+            )));
+        }
+
+        let mut days_of_week = parse_cron_field(fields[4], 0, 7)?;
+        // Both 0 and 7 mean Sunday
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week = (days_of_week & !(1 << 7)) | 1;
+        }
+
+        Ok(Self {
+            expression: fields.join(" "),
+            minutes: parse_cron_field(fields[0], 0, 59)?,
+            hours: parse_cron_field(fields[1], 0, 23)?,
+            days_of_month: parse_cron_field(fields[2], 1, 31)?,
+            months: parse_cron_field(fields[3], 1, 12)?,
+            days_of_week,
+            day_of_month_restricted: !fields[2].starts_with('*'),
+            day_of_week_restricted: !fields[4].starts_with('*'),
+        })
+    }
+
+    /// Returns the cron expression.
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+
+    /// Returns the first matching minute strictly after the given time, or None if
+    /// the expression never matches (e.g. `0 0 31 2 *`).
+    pub fn next_after(&self, after: u64) -> Option<u64> {
+        let start = i64::try_from(after / 60 + 1).ok()?.checked_mul(60)?;
+        let mut time = DateTime::from_timestamp(start, 0)?.naive_utc();
+        let limit = time + chrono::Duration::days(CRON_SEARCH_DAYS);
+
+        while time <= limit {
+            if !has_bit(self.months, time.month()) {
+                let (year, month) = if time.month() == 12 {
+                    (time.year() + 1, 1)
+                } else {
+                    (time.year(), time.month() + 1)
+                };
+                time = NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)?;
+            } else if !self.day_matches(time.date()) {
+                time = time.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+            } else if !has_bit(self.hours, time.hour()) {
+                time = start_of_hour(time)? + chrono::Duration::hours(1);
+            } else if !has_bit(self.minutes, time.minute()) {
+                time += chrono::Duration::minutes(1);
+            } else {
+                return u64::try_from(time.and_utc().timestamp()).ok();
+            }
+        }
+
+        None
+    }
+
+    fn day_matches(&self, date: NaiveDate) -> bool {
+        let day_of_month = has_bit(self.days_of_month, date.day());
+        let day_of_week = has_bit(self.days_of_week, date.weekday().num_days_from_sunday());
+
+        match (self.day_of_month_restricted, self.day_of_week_restricted) {
+            (true, true) => day_of_month || day_of_week,
+            (true, false) => day_of_month,
+            (false, true) => day_of_week,
+            (false, false) => true,
+        }
+    }
+}
+
+impl TryFrom<String> for CronSchedule {
+    type Error = Error;
+
+    fn try_from(expression: String) -> Result<Self, Self::Error> {
+        Self::parse(&expression)
+    }
+}
+
+impl From<CronSchedule> for String {
+    fn from(schedule: CronSchedule) -> Self {
+        schedule.expression
+    }
+}
+
+/// Parses one cron field into a bit set of the matching values.
+fn parse_cron_field(field: &str, min: u32, max: u32) -> Result<u64, Error> {
+    let invalid = || Error::Task(format!("Invalid cron field '{}' (values {}-{})", field, min, max));
+    let parse_value = |value: &str| -> Result<u32, Error> {
+        let value: u32 = value.parse().map_err(|_| invalid())?;
+        if value < min || value > max {
+            return Err(invalid());
+        }
+        Ok(value)
+    };
+
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, Some(step.parse::<u32>().map_err(|_| invalid())?)),
+            None => (part, None),
+        };
+        if step == Some(0) {
+            return Err(invalid());
+        }
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (parse_value(start)?, parse_value(end)?)
+        } else {
+            let value = parse_value(range)?;
+            // A single value with a step runs from that value to the end of the range
+            (value, if step.is_some() { max } else { value })
+        };
+        if start > end {
+            return Err(invalid());
+        }
+
+        for value in (start..=end).step_by(step.unwrap_or(1) as usize) {
+            bits |= 1 << value;
+        }
+    }
+
+    Ok(bits)
+}
+
+fn has_bit(bits: u64, value: u32) -> bool {
+    bits & (1 << value) != 0
+}
+
+fn start_of_hour(time: NaiveDateTime) -> Option<NaiveDateTime> {
+    time.date().and_hms_opt(time.hour(), 0, 0)
+}
+
+/// When a recurring task runs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Recurrence {
+    /// Runs at a fixed interval after the first run.
+    Interval(Duration),
+    /// Runs whenever the cron expression matches.
+    Cron(CronSchedule),
+}
+
+impl Recurrence {
+    /// Creates a recurrence from a cron expression.
+    pub fn cron(expression: &str) -> Result<Self, Error> {
+        Ok(Self::Cron(CronSchedule::parse(expression)?))
+    }
+
+    /// Returns the first run at or after the given time.
+    pub fn first_at_or_after(&self, time: u64) -> Option<u64> {
+        match self {
+            Self::Interval(_) => Some(time),
+            Self::Cron(cron) => cron.next_after(time.checked_sub(1)?),
+        }
+    }
+
+    /// Returns the run following a run at the given time.
+    pub fn next_after(&self, time: u64) -> Option<u64> {
+        match self {
+            Self::Interval(interval) => time.checked_add(interval.as_secs().max(1)),
+            Self::Cron(cron) => cron.next_after(time),
+        }
+    }
+}
+
+/// What happens to runs that were due while the node was not running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MissedRunPolicy {
+    /// Missed runs are dropped; only runs that are at most the misfire grace late are queued.
+    Skip,
+    /// All missed runs are coalesced into a single run.
+    RunOnce,
+    /// Every missed run is queued, up to a limit.
+    CatchUp,
+}
+
+/// A task that is queued again and again according to a recurrence rule.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecurringSchedule {
+    /// The schedule ID.
+    pub id: String,
+    /// The task copied for every run; each copy gets its own ID.
+    pub template: Task,
+    /// When the task runs.
+    pub recurrence: Recurrence,
+    /// What happens to missed runs.
+    pub missed_runs: MissedRunPolicy,
+    /// How late a run may be before it counts as missed.
+    pub misfire_grace: Duration,
+    /// When the task runs next (seconds since the Unix epoch), or None if it never runs again.
+    pub next_run: Option<u64>,
+    /// When the task last ran (seconds since the Unix epoch).
+    pub last_run: Option<u64>,
+}
+
+impl RecurringSchedule {
+    /// Creates a new schedule whose first run is not before the given time.
+    ///
+    /// Missed runs are coalesced into a single run by default.
+    pub fn new(id: &str, template: Task, recurrence: Recurrence, not_before: u64) -> Result<Self, Error> {
+        let next_run = recurrence.first_at_or_after(not_before);
+        if next_run.is_none() {
+            return Err(Error::Task(format!("Recurring schedule {} never runs", id)));
+        }
+
+        Ok(Self {
+            id: id.to_string(),
+            template,
+            recurrence,
+            missed_runs: MissedRunPolicy::RunOnce,
+            misfire_grace: DEFAULT_MISFIRE_GRACE,
+            next_run,
+            last_run: None,
+        })
+    }
+
+    /// Sets what happens to missed runs.
+    pub fn set_missed_run_policy(&mut self, policy: MissedRunPolicy) {
+        self.missed_runs = policy;
+    }
+
+    /// Advances the schedule past the given time, returning the times of the runs to queue.
+    pub fn take_due_runs(&mut self, now: u64) -> Vec<u64> {
+        let mut due = VecDeque::new();
+
+        match (&self.recurrence, self.next_run) {
+            (Recurrence::Interval(interval), Some(first)) if first <= now => {
+                // Computed directly, since a short interval can miss very many runs
+                let interval = interval.as_secs().max(1);
+                let count = (now - first) / interval + 1;
+                let skipped = count.saturating_sub(MAX_CATCH_UP_RUNS as u64);
+                due.extend((skipped..count).map(|index| first + index * interval));
+                self.next_run = first.checked_add(count * interval);
+            },
+            _ => {
+                while let Some(run) = self.next_run.filter(|run| *run <= now) {
+                    due.push_back(run);
+                    if due.len() > MAX_CATCH_UP_RUNS {
+                        due.pop_front();
+                    }
+                    self.next_run = self.recurrence.next_after(run);
+                }
+            },
+        }
+
+        let runs: Vec<u64> = match self.missed_runs {
+            MissedRunPolicy::CatchUp => due.into_iter().collect(),
+            MissedRunPolicy::RunOnce => due.back().copied().into_iter().collect(),
+            MissedRunPolicy::Skip => due.back().copied()
+                .filter(|run| now - run <= self.misfire_grace.as_secs())
+                .into_iter()
+                .collect(),
+        };
+        if let Some(last) = runs.last() {
+            self.last_run = Some(*last);
+        }
+
+        runs
+    }
+
+    /// Creates the task for a run at the given time.
+    pub fn task_for_run(&self, run: u64, now: u64) -> Task {
+        let mut task = self.template.clone();
+        task.id = format!("{}@{}", self.id, run);
+        task.status = TaskStatus::Pending;
+        task.created_at = now;
+        task.completed_at = None;
+        task.checkpoint = None;
+        task
+    }
+
+    /// Serializes the schedule to bytes.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        serde_json::to_vec(self).map_err(Error::Serialization)
+    }
+
+    /// Deserializes a schedule from bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        serde_json::from_slice(bytes).map_err(Error::Serialization)
+    }
+}
+
+/// Persists recurring schedules so they survive restarts.
+pub struct RecurringStore {
+    tree: Tree,
+}
+
+impl RecurringStore {
+    /// Creates a new RecurringStore in the given database.
+    pub fn new(database: &Database) -> Result<Self, Error> {
+        Ok(Self {
+            tree: database.open_tree(RECURRING_TREE)?,
+        })
+    }
+
+    /// Stores a schedule, replacing any schedule with the same ID.
+    pub fn put(&self, schedule: &RecurringSchedule) -> Result<(), Error> {
+        self.tree.put(&schedule.id, schedule.to_bytes()?)
+    }
+
+    /// Removes a schedule.
+    pub fn remove(&self, schedule_id: &str) -> Result<(), Error> {
+        self.tree.remove(schedule_id)
+    }
+
+    /// Returns all stored schedules.
+    pub fn all(&self) -> Result<Vec<RecurringSchedule>, Error> {
+        self.tree.iter()
+            .map(|entry| entry.and_then(|(_, bytes)| RecurringSchedule::from_bytes(&bytes)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::quota::DiskQuota;
+    use crate::tasks::clock::ManualClock;
+    use crate::tasks::scheduler::TaskScheduler;
+    use crate::tasks::TaskResourceType;
+    use std::sync::Arc;
+
+    /// 2025-01-01T00:00:00Z, a Wednesday.
+    const NEW_YEAR: u64 = 1_735_689_600;
+    const HOUR: u64 = 3600;
+    const DAY: u64 = 24 * HOUR;
+
+    fn task(id: &str) -> Task {
+        Task {
+            id: id.to_string(),
+            resource_type: TaskResourceType::Cpu,
+            data: Vec::new(),
+            status: TaskStatus::Pending,
+            created_at: 0,
+            completed_at: None,
+            checkpoint: None,
+            deterministic: false,
+        }
+    }
+
+    #[test]
+    fn test_cron_next_after() {
+        let nightly = CronSchedule::parse("0 3 * * *").unwrap();
+        assert_eq!(nightly.next_after(NEW_YEAR), Some(NEW_YEAR + 3 * HOUR));
+        assert_eq!(nightly.next_after(NEW_YEAR + 3 * HOUR), Some(NEW_YEAR + DAY + 3 * HOUR));
+
+        // From Saturday noon, the next working-hours slot is Monday at 09:00
+        let working_hours = CronSchedule::parse("*/15 9-17 * * 1-5").unwrap();
+        assert_eq!(working_hours.next_after(NEW_YEAR + 3 * DAY + 12 * HOUR), Some(NEW_YEAR + 5 * DAY + 9 * HOUR));
+
+        let leap_day = NaiveDate::from_ymd_opt(2028, 2, 29).unwrap().and_hms_opt(0, 0, 0).unwrap();
+        assert_eq!(
+            CronSchedule::parse("0 0 29 2 *").unwrap().next_after(NEW_YEAR),
+            Some(leap_day.and_utc().timestamp() as u64)
+        );
+        assert_eq!(CronSchedule::parse("0 0 31 2 *").unwrap().next_after(NEW_YEAR), None);
+
+        for invalid in ["60 * * * *", "* * *", "*/0 * * * *", "5-1 * * * *"] {
+            assert!(CronSchedule::parse(invalid).is_err(), "{} should be rejected", invalid);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delayed_and_missed_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::open(dir.path()).unwrap();
+        let store = Arc::new(RecurringStore::new(&database).unwrap());
+        let clock = Arc::new(ManualClock::new(NEW_YEAR));
+
+        let mut scheduler = TaskScheduler::new(1, Duration::from_secs(5));
+        scheduler.set_clock(clock.clone());
+        scheduler.set_recurring_store(store.clone()).unwrap();
+
+        scheduler.schedule_task_at(task("delayed"), NEW_YEAR + 30).await.unwrap();
+        for policy in [MissedRunPolicy::Skip, MissedRunPolicy::RunOnce, MissedRunPolicy::CatchUp] {
+            let recurrence = Recurrence::Interval(Duration::from_secs(600));
+            let mut schedule = RecurringSchedule::new(&format!("{:?}", policy), task("template"), recurrence, NEW_YEAR).unwrap();
+            schedule.set_missed_run_policy(policy);
+            scheduler.add_recurring(schedule).await.unwrap();
+        }
+
+        // The first run of every schedule is on time
+        assert_eq!(scheduler.tick().await.unwrap(), 3);
+        clock.advance(Duration::from_secs(30));
+        assert_eq!(scheduler.tick().await.unwrap(), 1);
+
+        // Five runs are missed, the last one by two minutes
+        clock.set(NEW_YEAR + 3000 + 120);
+        assert_eq!(scheduler.tick().await.unwrap(), 6);
+        assert_eq!(scheduler.pending_task_count().await, 10);
+        assert!(scheduler.pop_pending().await.is_some_and(|task| task.id.ends_with(&format!("@{}", NEW_YEAR))));
+
+        // Schedules survive a restart
+        let mut restarted = TaskScheduler::new(1, Duration::from_secs(5));
+        restarted.set_clock(clock.clone());
+        restarted.set_recurring_store(store).unwrap();
+        let schedules = restarted.recurring_schedules().await;
+        assert_eq!(schedules.len(), 3);
+        assert!(schedules.iter().all(|schedule| schedule.next_run == Some(NEW_YEAR + 3600)));
+        assert_eq!(restarted.tick().await.unwrap(), 0);
+
+        // Due runs are still queued when their schedules cannot be persisted
+        let mut database = database;
+        database.set_quota(Arc::new(DiskQuota::new(0)));
+        let mut full = TaskScheduler::new(1, Duration::from_secs(5));
+        full.set_clock(clock.clone());
+        full.set_recurring_store(Arc::new(RecurringStore::new(&database).unwrap())).unwrap();
+        clock.set(NEW_YEAR + 3600);
+        assert_eq!(full.tick().await.unwrap(), 3);
+        assert_eq!(full.pending_task_count().await, 3);
+    }
+}
@@ -0,0 +1,109 @@
+/* Copyright 2025 Joao Guimaraes, Catp2p Project
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Task function registry mapping task kinds to handlers.
+
+use crate::error::Error;
+use crate::tasks::TaskContext;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// A task handler that turns input bytes into output bytes.
+pub type TaskHandler = Arc<dyn Fn(&TaskContext, &[u8]) -> Result<Vec<u8>, Error> + Send + Sync>;
+
+/// A registry of task handlers, keyed by task kind.
+pub struct TaskRegistry {
+    handlers: RwLock<HashMap<String, TaskHandler>>,
+}
+
+impl TaskRegistry {
+    /// Creates a new, empty TaskRegistry.
+    pub fn new() -> Self {
+        Self {
+            handlers: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Registers a raw handler for the given kind, replacing any existing handler.
+    pub fn register<F>(&self, kind: &str, handler: F) -> Result<(), Error>
+    where
+        F: Fn(&[u8]) -> Result<Vec<u8>, Error> + Send + Sync + 'static,
+    {
+        self.register_with_context(kind, move |_, input| handler(input))
+    }
+
+    /// Registers a raw handler that also receives the [`TaskContext`], e.g. to emit checkpoints or report progress.
+    pub fn register_with_context<F>(&self, kind: &str, handler: F) -> Result<(), Error>
+    where
+        F: Fn(&TaskContext, &[u8]) -> Result<Vec<u8>, Error> + Send + Sync + 'static,
+    {
+        let mut handlers = self.handlers.write()
+            .map_err(|_| Error::Task("Failed to lock task registry".to_string()))?;
+
+        handlers.insert(kind.to_string(), Arc::new(handler));
+
+        Ok(())
+    }
+
+    /// Registers a typed handler whose input and output are serialized as JSON.
+    pub fn register_typed<I, O, F>(&self, kind: &str, handler: F) -> Result<(), Error>
+    where
+        I: DeserializeOwned,
+        O: Serialize,
+        F: Fn(I) -> Result<O, Error> + Send + Sync + 'static,
+    {
+        self.register(kind, move |input| {
+            let input: I = serde_json::from_slice(input).map_err(Error::Serialization)?;
+            let output = handler(input)?;
+            serde_json::to_vec(&output).map_err(Error::Serialization)
+        })
+    }
+
+    /// Removes the handler for the given kind.
+    pub fn unregister(&self, kind: &str) -> Result<(), Error> {
+        let mut handlers = self.handlers.write()
+            .map_err(|_| Error::Task("Failed to lock task registry".to_string()))?;
+
+        handlers.remove(kind);
+
+        Ok(())
+    }
+
+    /// Gets the handler for the given kind.
+    pub fn get(&self, kind: &str) -> Result<TaskHandler, Error> {
+        let handlers = self.handlers.read()
+            .map_err(|_| Error::Task("Failed to lock task registry".to_string()))?;
+
+        handlers.get(kind)
+            .cloned()
+            .ok_or_else(|| Error::Task(format!("No handler registered for task kind: {}", kind)))
+    }
+
+    /// Returns the registered task kinds.
+    pub fn kinds(&self) -> Result<Vec<String>, Error> {
+        let handlers = self.handlers.read()
+            .map_err(|_| Error::Task("Failed to lock task registry".to_string()))?;
+
+        Ok(handlers.keys().cloned().collect())
+    }
+}
+
+impl Default for TaskRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,1147 @@
+/* Copyright 2025 Joao Guimaraes, Catp2p Project
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Remote task execution on peers.
+//!
+//! The originating node uses a [`RemoteTaskClient`] to ship tasks to peers over the
+//! request/response protocol and to track them while they run remotely. The executing
+//! node uses a [`RemoteTaskHost`] to admit tasks within its own `ResourceMode` limits,
+//! run them, and stream status updates and the final result back to the originator.
+//!
+//! A remote task is held under a lease: every status update from the executing peer
+//! renews it, and the originator gives up on the task once the lease expires.
+
+use crate::error::Error;
+use crate::network::protocol::{Message, MessageHandler, MessageSender};
+use crate::resources::allocation::{ResourceAllocator, ResourceLease, ResourceRequest};
+use crate::resources::SystemResources;
+use crate::tasks::checkpoint::{Checkpoint, CheckpointMetadata, CheckpointStore};
+use crate::tasks::events::{TaskEvent, TaskEventKind, TaskEvents};
+use crate::tasks::scheduler::TaskScheduler;
+use crate::tasks::{Task, TaskResourceType, TaskResult, TaskStatus};
+use async_trait::async_trait;
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, oneshot};
+
+/// Message type for submitting a task to a peer.
+pub const TASK_SUBMIT_MESSAGE: &str = "task-submit";
+/// Message type for a status update about a remote task.
+pub const TASK_STATUS_MESSAGE: &str = "task-status";
+/// Message type for the final result of a remote task.
+pub const TASK_RESULT_MESSAGE: &str = "task-result";
+/// Message type for replicating a task checkpoint to the originator.
+pub const TASK_CHECKPOINT_MESSAGE: &str = "task-checkpoint";
+/// Message type for forwarding a task lifecycle event to the originator.
+pub const TASK_EVENT_MESSAGE: &str = "task-event";
+/// Message type for cancelling a task on the executing peer.
+pub const TASK_CANCEL_MESSAGE: &str = "task-cancel";
+
+/// Resources a peer advertises for running remote tasks.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerAdvertisement {
+    /// Number of CPU cores.
+    pub cpu_cores: u32,
+    /// Available memory in bytes.
+    pub available_memory: u64,
+    /// Whether the peer can run GPU tasks.
+    pub has_gpu: bool,
+    /// Number of remote tasks the peer is currently willing to accept.
+    pub free_slots: usize,
+    /// Number of tasks waiting in the peer's scheduler queue.
+    #[serde(default)]
+    pub queue_length: usize,
+}
+
+impl PeerAdvertisement {
+    /// Creates an advertisement from the local system resources.
+    pub fn from_resources(resources: &SystemResources, free_slots: usize) -> Self {
+        Self {
+            cpu_cores: resources.cpu_cores,
+            available_memory: resources.available_memory,
+            has_gpu: resources.has_gpu(),
+            free_slots,
+            queue_length: 0,
+        }
+    }
+}
+
+/// A request to run a task on a peer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteTaskRequest {
+    /// The task to run.
+    pub task: Task,
+    /// How long the originator waits between status updates before giving up.
+    pub lease_timeout: Duration,
+    /// The latest checkpoint to resume from, if the task ran before.
+    #[serde(default)]
+    pub checkpoint: Option<Checkpoint>,
+}
+
+/// A peer's reply to a [`RemoteTaskRequest`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteTaskReply {
+    /// Whether the peer accepted the task.
+    pub accepted: bool,
+    /// Why the task was rejected, if it was.
+    pub reason: Option<String>,
+}
+
+/// A status update about a task running on a peer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteStatusUpdate {
+    /// The task ID.
+    pub task_id: String,
+    /// The task status on the executing peer.
+    pub status: TaskStatus,
+}
+
+/// A request to cancel a task on the executing peer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteCancelRequest {
+    /// The task ID.
+    pub task_id: String,
+}
+
+/// The final outcome of a task that ran on a peer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteTaskOutcome {
+    /// The task ID.
+    pub task_id: String,
+    /// The task result, if the task could be executed.
+    pub result: Option<TaskResult>,
+    /// The execution error, if the task could not be executed.
+    pub error: Option<String>,
+}
+
+/// Configuration for offloading tasks to peers.
+#[derive(Debug, Clone)]
+pub struct RemoteConfig {
+    /// How long to wait between status updates before a remote task is considered lost.
+    pub lease_timeout: Duration,
+    /// Maximum number of peers to try before giving up on a task.
+    pub max_attempts: usize,
+}
+
+impl Default for RemoteConfig {
+    fn default() -> Self {
+        Self {
+            lease_timeout: Duration::from_secs(30),
+            max_attempts: 3,
+        }
+    }
+}
+
+/// The state of a task running on a remote peer.
+#[derive(Debug, Clone)]
+pub struct RemoteTaskState {
+    /// The peer running the task.
+    pub peer_id: PeerId,
+    /// The last status reported by the peer.
+    pub status: TaskStatus,
+    /// When the lease expires unless the peer sends another update.
+    pub lease_deadline: Instant,
+    /// Metadata of the latest checkpoint replicated from the peer.
+    pub checkpoint: Option<CheckpointMetadata>,
+}
+
+/// What the client knows about a peer.
+#[derive(Debug, Clone)]
+struct PeerRecord {
+    advertisement: PeerAdvertisement,
+    successes: u32,
+    failures: u32,
+}
+
+impl PeerRecord {
+    /// Estimated probability that the peer completes a task, with a uniform prior.
+    fn reliability(&self) -> f64 {
+        (self.successes as f64 + 1.0) / (self.successes as f64 + self.failures as f64 + 2.0)
+    }
+
+    /// Scores the peer for a task, or returns None if the peer cannot run it.
+    fn score(&self, task: &Task) -> Option<f64> {
+        let advertisement = &self.advertisement;
+        if advertisement.free_slots == 0 {
+            return None;
+        }
+        if task.resource_type == TaskResourceType::Gpu && !advertisement.has_gpu {
+            return None;
+        }
+
+        let memory_gb = advertisement.available_memory as f64 / (1024.0 * 1024.0 * 1024.0);
+        let capacity = (advertisement.cpu_cores as f64).ln_1p()
+            + memory_gb.ln_1p()
+            + (advertisement.free_slots as f64).ln_1p();
+
+        Some(capacity * self.reliability())
+    }
+}
+
+/// How a task handed over to a peer ended.
+#[derive(Debug)]
+pub enum LeaseOutcome {
+    /// The peer reported the outcome of the task.
+    Finished(Result<TaskResult, Error>),
+    /// The peer stopped renewing the lease or handed the task back, so the task may not have run.
+    Expired,
+}
+
+/// A task handed over to a peer, waiting for its outcome.
+pub struct TaskLease {
+    task_id: String,
+    peer_id: PeerId,
+    result_rx: oneshot::Receiver<Result<TaskResult, Error>>,
+}
+
+impl TaskLease {
+    /// Returns the ID of the leased task.
+    pub fn task_id(&self) -> &str {
+        &self.task_id
+    }
+
+    /// Returns the peer holding the lease.
+    pub fn peer_id(&self) -> PeerId {
+        self.peer_id
+    }
+}
+
+/// A task waiting for its result from a peer.
+struct PendingTask {
+    state: RemoteTaskState,
+    result_tx: Option<oneshot::Sender<Result<TaskResult, Error>>>,
+}
+
+/// Tasks running remotely, keyed by task ID and the peer running them.
+type RemoteTasks = HashMap<(String, PeerId), PendingTask>;
+
+/// Offloads tasks to peers and tracks them while they run remotely.
+pub struct RemoteTaskClient {
+    sender: Arc<dyn MessageSender + Send + Sync>,
+    config: RemoteConfig,
+    peers: Arc<Mutex<HashMap<PeerId, PeerRecord>>>,
+    tasks: Arc<Mutex<RemoteTasks>>,
+    checkpoints: Option<Arc<CheckpointStore>>,
+    events: Option<TaskEvents>,
+}
+
+impl RemoteTaskClient {
+    /// Creates a new RemoteTaskClient that sends requests through the given sender.
+    pub fn new(sender: Arc<dyn MessageSender + Send + Sync>, config: RemoteConfig) -> Self {
+        Self {
+            sender,
+            config,
+            peers: Arc::new(Mutex::new(HashMap::new())),
+            tasks: Arc::new(Mutex::new(HashMap::new())),
+            checkpoints: None,
+            events: None,
+        }
+    }
+
+    /// Sets the channel that retries and events forwarded by peers are published to,
+    /// usually the scheduler's.
+    ///
+    /// Terminal events are not forwarded, since the originator publishes those itself
+    /// once the result arrives.
+    pub fn set_events(&mut self, events: TaskEvents) {
+        self.events = Some(events);
+    }
+
+    /// Sets the store for checkpoints replicated by peers.
+    ///
+    /// Replicated checkpoints are sent along when a task is resubmitted, so a task
+    /// migrated to another peer resumes from where the previous peer left off.
+    pub fn set_checkpoint_store(&mut self, checkpoints: Arc<CheckpointStore>) {
+        self.checkpoints = Some(checkpoints);
+    }
+
+    /// Records the resources a peer has advertised.
+    pub fn update_peer(&self, peer_id: PeerId, advertisement: PeerAdvertisement) -> Result<(), Error> {
+        let mut peers = self.lock_peers()?;
+
+        peers.entry(peer_id)
+            .and_modify(|record| record.advertisement = advertisement.clone())
+            .or_insert(PeerRecord {
+                advertisement,
+                successes: 0,
+                failures: 0,
+            });
+
+        Ok(())
+    }
+
+    /// Forgets a peer, e.g. after it disconnects.
+    pub fn remove_peer(&self, peer_id: &PeerId) -> Result<(), Error> {
+        self.lock_peers()?.remove(peer_id);
+        Ok(())
+    }
+
+    /// Returns the peers known to the client together with their latest advertisement.
+    pub fn peers(&self) -> Result<Vec<(PeerId, PeerAdvertisement)>, Error> {
+        Ok(self.lock_peers()?.iter()
+            .map(|(peer_id, record)| (*peer_id, record.advertisement.clone()))
+            .collect())
+    }
+
+    /// Returns the estimated reliability of a peer, between 0 and 1.
+    pub fn peer_reliability(&self, peer_id: &PeerId) -> Result<Option<f64>, Error> {
+        Ok(self.lock_peers()?.get(peer_id).map(PeerRecord::reliability))
+    }
+
+    /// Returns the peers able to run a task, best first.
+    pub fn rank_peers(&self, task: &Task) -> Result<Vec<PeerId>, Error> {
+        let peers = self.lock_peers()?;
+
+        let mut scored: Vec<(PeerId, f64)> = peers.iter()
+            .filter_map(|(peer_id, record)| record.score(task).map(|score| (*peer_id, score)))
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        Ok(scored.into_iter().map(|(peer_id, _)| peer_id).collect())
+    }
+
+    /// Gets the state of a task that is running remotely.
+    ///
+    /// If the task runs on several peers, the state on one of them is returned.
+    pub fn get_remote_task(&self, task_id: &str) -> Result<Option<RemoteTaskState>, Error> {
+        Ok(self.lock_tasks()?.iter()
+            .find(|((id, _), _)| id == task_id)
+            .map(|(_, pending)| pending.state.clone()))
+    }
+
+    /// Returns the IDs of the tasks that are currently running remotely.
+    pub fn remote_tasks(&self) -> Result<Vec<String>, Error> {
+        let tasks = self.lock_tasks()?;
+
+        let mut task_ids: Vec<String> = tasks.keys().map(|(task_id, _)| task_id.clone()).collect();
+        task_ids.sort();
+        task_ids.dedup();
+
+        Ok(task_ids)
+    }
+
+    /// Runs a task on the best available peer and waits for its result.
+    pub async fn offload(&self, task: &Task) -> Result<TaskResult, Error> {
+        self.offload_with_peer(task).await.map(|(_, result)| result)
+    }
+
+    /// Runs a task on the best available peer, returning the peer together with the result.
+    ///
+    /// The next peer is tried if a peer rejects the task or lets its lease expire,
+    /// up to `max_attempts` peers.
+    pub async fn offload_with_peer(&self, task: &Task) -> Result<(PeerId, TaskResult), Error> {
+        let candidates = self.rank_peers(task)?;
+        if candidates.is_empty() {
+            return Err(Error::Task(format!("No peer available to run task {}", task.id)));
+        }
+
+        let mut last_error: Option<Error> = None;
+        for (attempt, peer_id) in candidates.into_iter().take(self.config.max_attempts.max(1)).enumerate() {
+            if let (Some(events), Some(e)) = (&self.events, &last_error) {
+                events.emit_kind(&task.id, TaskEventKind::Retrying {
+                    attempt: attempt as u32 + 1,
+                    reason: e.to_string(),
+                });
+            }
+
+            let result_rx = match self.submit(task, peer_id).await {
+                Ok(result_rx) => result_rx,
+                Err(e) => {
+                    last_error = Some(e);
+                    continue;
+                },
+            };
+
+            match self.await_outcome(&task.id, peer_id, result_rx).await? {
+                LeaseOutcome::Finished(result) => return result.map(|result| (peer_id, result)),
+                LeaseOutcome::Expired => last_error = Some(lease_expired(&task.id, &peer_id)),
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| Error::Task(format!("No peer accepted task {}", task.id))))
+    }
+
+    /// Runs a task on a specific peer and waits for its result.
+    pub async fn offload_to(&self, task: &Task, peer_id: PeerId) -> Result<TaskResult, Error> {
+        let result_rx = self.submit(task, peer_id).await?;
+        self.wait_for_result(&task.id, peer_id, result_rx).await
+    }
+
+    /// Cancels a task on every peer running it.
+    ///
+    /// Waiting callers get a cancelled result right away; the cancel request to the
+    /// peers is best effort. Returns whether the task was running remotely.
+    pub async fn cancel(&self, task_id: &str) -> Result<bool, Error> {
+        let cancelled: Vec<(PeerId, PendingTask)> = {
+            let mut tasks = self.lock_tasks()?;
+            let keys: Vec<(String, PeerId)> = tasks.keys()
+                .filter(|(id, _)| id == task_id)
+                .cloned()
+                .collect();
+            keys.into_iter()
+                .filter_map(|key| tasks.remove(&key).map(|pending| (key.1, pending)))
+                .collect()
+        };
+
+        let mut peers = Vec::with_capacity(cancelled.len());
+        for (peer_id, pending) in cancelled {
+            if let Some(result_tx) = pending.result_tx {
+                let _ = result_tx.send(Ok(TaskResult::cancelled(Duration::ZERO)));
+            }
+            peers.push(peer_id);
+        }
+
+        let request = RemoteCancelRequest {
+            task_id: task_id.to_string(),
+        };
+        for peer_id in &peers {
+            if let Err(e) = send_message(&*self.sender, peer_id, TASK_CANCEL_MESSAGE, &request).await {
+                log::debug!("Failed to cancel task {} on peer {}: {}", task_id, peer_id, e);
+            }
+        }
+
+        Ok(!peers.is_empty())
+    }
+
+    /// Hands a task over to a peer that pulled it, e.g. by stealing it from the local queue.
+    ///
+    /// The task is tracked like an offloaded task, so the peer must keep renewing the
+    /// lease. Returns the request to deliver to the peer and the lease to wait on.
+    pub fn grant_lease(&self, task: &Task, peer_id: PeerId) -> Result<(RemoteTaskRequest, TaskLease), Error> {
+        let request = self.request_for(task)?;
+        let result_rx = self.track(task, peer_id)?;
+
+        Ok((request, TaskLease {
+            task_id: task.id.clone(),
+            peer_id,
+            result_rx,
+        }))
+    }
+
+    /// Waits until the peer holding a lease reports the outcome of the task or the lease expires.
+    pub async fn wait_for_lease(&self, lease: TaskLease) -> Result<LeaseOutcome, Error> {
+        self.await_outcome(&lease.task_id, lease.peer_id, lease.result_rx).await
+    }
+
+    /// Ends the lease of a peer that handed a task back without running it.
+    ///
+    /// The task is not counted against the peer's reliability, and whoever waits on
+    /// the lease sees it expire right away. Returns whether the peer held a lease.
+    pub fn return_lease(&self, task_id: &str, peer_id: &PeerId) -> Result<bool, Error> {
+        // Dropping the result sender wakes the waiter
+        Ok(self.lock_tasks()?.remove(&(task_id.to_string(), *peer_id)).is_some())
+    }
+
+    /// Reports that a peer returned a result that failed verification.
+    pub fn report_misbehavior(&self, peer_id: &PeerId) -> Result<(), Error> {
+        self.record_outcome(peer_id, false)
+    }
+
+    /// Submits a task to a peer, returning a receiver for its result once the peer accepts it.
+    async fn submit(&self, task: &Task, peer_id: PeerId) -> Result<oneshot::Receiver<Result<TaskResult, Error>>, Error> {
+        let request = self.request_for(task)?;
+        let message = Message::new(
+            TASK_SUBMIT_MESSAGE.to_string(),
+            serde_json::to_vec(&request).map_err(Error::Serialization)?,
+        ).to_bytes()?;
+
+        // Track the task before submitting, since the peer may report back before replying
+        let result_rx = self.track(task, peer_id)?;
+
+        let reply = self.sender.send_message(&peer_id, &message).await
+            .and_then(|bytes| serde_json::from_slice::<RemoteTaskReply>(&bytes).map_err(Error::Serialization));
+
+        match reply {
+            Ok(reply) if reply.accepted => Ok(result_rx),
+            Ok(reply) => {
+                // A rejection only means the peer is busy, so it does not count against its reliability
+                self.untrack(&task.id, &peer_id)?;
+                Err(Error::Task(format!(
+                    "Peer {} rejected task {}: {}",
+                    peer_id, task.id, reply.reason.unwrap_or_default()
+                )))
+            },
+            Err(e) => {
+                self.untrack(&task.id, &peer_id)?;
+                self.record_outcome(&peer_id, false)?;
+                Err(e)
+            },
+        }
+    }
+
+    /// Builds the request for running a task on a peer, including its latest checkpoint.
+    fn request_for(&self, task: &Task) -> Result<RemoteTaskRequest, Error> {
+        let checkpoint = match &self.checkpoints {
+            Some(checkpoints) => checkpoints.latest(&task.id)?,
+            None => None,
+        };
+
+        Ok(RemoteTaskRequest {
+            task: task.clone(),
+            lease_timeout: self.config.lease_timeout,
+            checkpoint,
+        })
+    }
+
+    /// Waits for the result of a task, failing once its lease expires.
+    async fn wait_for_result(
+        &self,
+        task_id: &str,
+        peer_id: PeerId,
+        result_rx: oneshot::Receiver<Result<TaskResult, Error>>,
+    ) -> Result<TaskResult, Error> {
+        match self.await_outcome(task_id, peer_id, result_rx).await? {
+            LeaseOutcome::Finished(result) => result,
+            LeaseOutcome::Expired => Err(lease_expired(task_id, &peer_id)),
+        }
+    }
+
+    /// Waits for the outcome of a task, renewing the wait as long as the peer renews its lease.
+    async fn await_outcome(
+        &self,
+        task_id: &str,
+        peer_id: PeerId,
+        mut result_rx: oneshot::Receiver<Result<TaskResult, Error>>,
+    ) -> Result<LeaseOutcome, Error> {
+        loop {
+            let deadline = self.lease_deadline(task_id, &peer_id)?.unwrap_or_else(Instant::now);
+
+            match tokio::time::timeout_at(deadline.into(), &mut result_rx).await {
+                Ok(Ok(result)) => {
+                    self.record_outcome(&peer_id, true)?;
+                    return Ok(LeaseOutcome::Finished(result));
+                },
+                Ok(Err(_)) => {
+                    // The lease ended without a result, because the peer returned the task
+                    return Ok(LeaseOutcome::Expired);
+                },
+                Err(_) => {
+                    // The lease may have been renewed while we were waiting
+                    let expired = self.lease_deadline(task_id, &peer_id)?
+                        .is_none_or(|deadline| deadline <= Instant::now());
+
+                    if expired {
+                        self.untrack(task_id, &peer_id)?;
+                        self.record_outcome(&peer_id, false)?;
+                        return Ok(LeaseOutcome::Expired);
+                    }
+                },
+            }
+        }
+    }
+
+    fn lease_deadline(&self, task_id: &str, peer_id: &PeerId) -> Result<Option<Instant>, Error> {
+        Ok(self.lock_tasks()?
+            .get(&(task_id.to_string(), *peer_id))
+            .map(|pending| pending.state.lease_deadline))
+    }
+
+    fn track(&self, task: &Task, peer_id: PeerId) -> Result<oneshot::Receiver<Result<TaskResult, Error>>, Error> {
+        let (result_tx, result_rx) = oneshot::channel();
+
+        self.lock_tasks()?.insert((task.id.clone(), peer_id), PendingTask {
+            state: RemoteTaskState {
+                peer_id,
+                status: TaskStatus::Pending,
+                lease_deadline: Instant::now() + self.config.lease_timeout,
+                checkpoint: task.checkpoint.clone(),
+            },
+            result_tx: Some(result_tx),
+        });
+
+        Ok(result_rx)
+    }
+
+    fn untrack(&self, task_id: &str, peer_id: &PeerId) -> Result<(), Error> {
+        self.lock_tasks()?.remove(&(task_id.to_string(), *peer_id));
+        Ok(())
+    }
+
+    fn record_outcome(&self, peer_id: &PeerId, success: bool) -> Result<(), Error> {
+        if let Some(record) = self.lock_peers()?.get_mut(peer_id) {
+            if success {
+                record.successes += 1;
+            } else {
+                record.failures += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_status(&self, peer_id: &PeerId, update: RemoteStatusUpdate) -> Result<(), Error> {
+        let mut tasks = self.lock_tasks()?;
+
+        if let Some(pending) = tasks.get_mut(&(update.task_id, *peer_id)) {
+            pending.state.status = update.status;
+            pending.state.lease_deadline = Instant::now() + self.config.lease_timeout;
+        }
+
+        Ok(())
+    }
+
+    fn handle_event(&self, peer_id: &PeerId, mut event: TaskEvent) -> Result<(), Error> {
+        // Only accept events for tasks this peer is actually running
+        match self.lock_tasks()?.get_mut(&(event.task_id.clone(), *peer_id)) {
+            Some(pending) => {
+                if event.kind == TaskEventKind::Started {
+                    pending.state.status = TaskStatus::Running;
+                }
+                pending.state.lease_deadline = Instant::now() + self.config.lease_timeout;
+            },
+            None => return Ok(()),
+        }
+
+        if let Some(events) = &self.events {
+            if !event.kind.is_terminal() {
+                event.peer_id = Some(peer_id.to_string());
+                events.emit(event);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_checkpoint(&self, peer_id: &PeerId, checkpoint: Checkpoint) -> Result<(), Error> {
+        let key = (checkpoint.task_id.clone(), *peer_id);
+
+        // Only accept checkpoints for tasks this peer is actually running
+        match self.lock_tasks()?.get_mut(&key) {
+            Some(pending) => {
+                pending.state.checkpoint = Some(checkpoint.metadata.clone());
+                pending.state.lease_deadline = Instant::now() + self.config.lease_timeout;
+            },
+            None => return Ok(()),
+        }
+
+        if let Some(checkpoints) = &self.checkpoints {
+            checkpoints.store(&checkpoint)?;
+        }
+
+        Ok(())
+    }
+
+    fn handle_outcome(&self, peer_id: &PeerId, outcome: RemoteTaskOutcome) -> Result<(), Error> {
+        let pending = self.lock_tasks()?.remove(&(outcome.task_id.clone(), *peer_id));
+
+        if pending.is_some() && outcome.result.is_some() {
+            if let Some(checkpoints) = &self.checkpoints {
+                checkpoints.remove(&outcome.task_id)?;
+            }
+        }
+
+        if let Some(result_tx) = pending.and_then(|pending| pending.result_tx) {
+            let result = match (outcome.result, outcome.error) {
+                (Some(result), _) => Ok(result),
+                (None, error) => Err(Error::Task(format!(
+                    "Task {} failed on peer {}: {}",
+                    outcome.task_id, peer_id, error.unwrap_or_default()
+                ))),
+            };
+            let _ = result_tx.send(result);
+        }
+
+        Ok(())
+    }
+
+    fn lock_peers(&self) -> Result<std::sync::MutexGuard<'_, HashMap<PeerId, PeerRecord>>, Error> {
+        self.peers.lock()
+            .map_err(|_| Error::Task("Failed to lock remote peers".to_string()))
+    }
+
+    fn lock_tasks(&self) -> Result<std::sync::MutexGuard<'_, RemoteTasks>, Error> {
+        self.tasks.lock()
+            .map_err(|_| Error::Task("Failed to lock remote tasks".to_string()))
+    }
+}
+
+#[async_trait]
+impl MessageHandler for RemoteTaskClient {
+    async fn handle_message(&self, peer_id: &PeerId, message: &[u8]) -> Result<Vec<u8>, Error> {
+        let message = Message::from_bytes(message)?;
+
+        match message.message_type.as_str() {
+            TASK_STATUS_MESSAGE => {
+                let update = serde_json::from_slice(&message.payload).map_err(Error::Serialization)?;
+                self.handle_status(peer_id, update)?;
+            },
+            TASK_RESULT_MESSAGE => {
+                let outcome = serde_json::from_slice(&message.payload).map_err(Error::Serialization)?;
+                self.handle_outcome(peer_id, outcome)?;
+            },
+            TASK_EVENT_MESSAGE => {
+                let event = serde_json::from_slice(&message.payload).map_err(Error::Serialization)?;
+                self.handle_event(peer_id, event)?;
+            },
+            TASK_CHECKPOINT_MESSAGE => {
+                let checkpoint = serde_json::from_slice(&message.payload).map_err(Error::Serialization)?;
+                self.handle_checkpoint(peer_id, checkpoint)?;
+            },
+            other => return Err(Error::Network(format!("Unexpected message type: {}", other))),
+        }
+
+        Ok(Vec::new())
+    }
+}
+
+/// Runs tasks submitted by peers, within the local `ResourceMode` limits.
+pub struct RemoteTaskHost {
+    scheduler: Arc<TaskScheduler>,
+    allocator: Arc<ResourceAllocator>,
+    sender: Arc<dyn MessageSender + Send + Sync>,
+    max_remote_tasks: usize,
+    active_tasks: Arc<Mutex<HashMap<String, PeerId>>>,
+    checkpoints: Option<Arc<CheckpointStore>>,
+}
+
+impl RemoteTaskHost {
+    /// Creates a new RemoteTaskHost.
+    ///
+    /// Each remote task is accounted as one CPU core against the allocator's
+    /// `ResourceMode`, and at most `max_remote_tasks` run at the same time.
+    pub fn new(
+        scheduler: Arc<TaskScheduler>,
+        allocator: Arc<ResourceAllocator>,
+        sender: Arc<dyn MessageSender + Send + Sync>,
+        max_remote_tasks: usize,
+    ) -> Self {
+        Self {
+            scheduler,
+            allocator,
+            sender,
+            max_remote_tasks,
+            active_tasks: Arc::new(Mutex::new(HashMap::new())),
+            checkpoints: None,
+        }
+    }
+
+    /// Sets the checkpoint store shared with the local executors.
+    ///
+    /// Checkpoints sent along with a task are stored before it runs, and checkpoints
+    /// emitted while it runs are replicated to the originator.
+    pub fn set_checkpoint_store(&mut self, checkpoints: Arc<CheckpointStore>) {
+        self.checkpoints = Some(checkpoints);
+    }
+
+    /// Returns the advertisement to publish to other peers.
+    pub fn advertisement(&self) -> Result<PeerAdvertisement, Error> {
+        let resources = self.allocator.get_resources()?;
+        let active = self.lock_active()?.len();
+        let free_slots = if self.scheduler.is_paused() {
+            0
+        } else {
+            self.max_remote_tasks.saturating_sub(active)
+        };
+
+        let mut advertisement = PeerAdvertisement::from_resources(&resources, free_slots);
+        // Memory reserved by running tasks is not free
+        advertisement.available_memory = self.allocator.available()?.memory;
+        advertisement.has_gpu = self.scheduler.has_executor_for(TaskResourceType::Gpu);
+
+        Ok(advertisement)
+    }
+
+    /// Returns the number of remote tasks currently running.
+    pub fn active_task_count(&self) -> Result<usize, Error> {
+        Ok(self.lock_active()?.len())
+    }
+
+    /// Admits a task from the given peer if it fits within the local limits.
+    ///
+    /// The returned lease holds the task's core and GPU memory until it is dropped,
+    /// or until it is no longer renewed within `lease_timeout`.
+    fn admit(&self, origin: PeerId, task: &Task, lease_timeout: Duration) -> Result<ResourceLease, String> {
+        if !self.scheduler.has_executor_for(task.resource_type) {
+            return Err(format!("No executor for {:?} tasks", task.resource_type));
+        }
+        if self.scheduler.is_paused() {
+            return Err("Host is busy".to_string());
+        }
+
+        let mut active = self.lock_active().map_err(|e| e.to_string())?;
+        if active.contains_key(&task.id) {
+            return Err(format!("Task {} is already running", task.id));
+        }
+        if active.len() >= self.max_remote_tasks {
+            return Err("Remote task limit reached".to_string());
+        }
+
+        let lease = self.allocator.reserve(ResourceRequest::for_task(task), lease_timeout)
+            .map_err(|_| "Resource mode limit reached".to_string())?;
+
+        active.insert(task.id.clone(), origin);
+        Ok(lease)
+    }
+
+    /// Runs a task on behalf of a peer, reporting its status and result back to that peer.
+    pub(crate) fn handle_submit(&self, origin: PeerId, request: RemoteTaskRequest) -> RemoteTaskReply {
+        let lease = match self.admit(origin, &request.task, request.lease_timeout) {
+            Ok(lease) => lease,
+            Err(reason) => {
+                return RemoteTaskReply {
+                    accepted: false,
+                    reason: Some(reason),
+                };
+            },
+        };
+
+        if let Some(checkpoints) = &self.checkpoints {
+            if let Err(e) = self.replicate_checkpoints(checkpoints, origin, &request) {
+                if let Ok(mut active) = self.active_tasks.lock() {
+                    active.remove(&request.task.id);
+                }
+                return RemoteTaskReply {
+                    accepted: false,
+                    reason: Some(e.to_string()),
+                };
+            }
+        }
+
+        let scheduler = self.scheduler.clone();
+        let sender = self.sender.clone();
+        let active_tasks = self.active_tasks.clone();
+        let checkpoints = self.checkpoints.clone();
+        // Report well within the lease so a single lost update does not expire it
+        let heartbeat_interval = (request.lease_timeout / 3).max(Duration::from_millis(10));
+        let lease_timeout = request.lease_timeout;
+
+        // Subscribe before the task starts so that no events are missed
+        let mut events = scheduler.events().subscribe();
+
+        tokio::spawn(async move {
+            let task = request.task;
+            send_status(&*sender, &origin, &task.id, TaskStatus::Running).await;
+
+            // Run locally; remote tasks must not be offloaded again
+            let execution = scheduler.execute_locally(&task);
+            tokio::pin!(execution);
+
+            let mut heartbeat = tokio::time::interval(heartbeat_interval);
+            heartbeat.tick().await;
+
+            let mut forwarding = true;
+            let result = loop {
+                tokio::select! {
+                    result = &mut execution => break result,
+                    _ = heartbeat.tick() => {
+                        if let Err(e) = lease.renew(lease_timeout) {
+                            log::warn!("Failed to renew the resources of task {}: {}", task.id, e);
+                        }
+                        send_status(&*sender, &origin, &task.id, TaskStatus::Running).await;
+                    },
+                    event = events.recv(), if forwarding => match event {
+                        Ok(event) if event.task_id == task.id && !event.kind.is_terminal() => {
+                            let _ = send_message(&*sender, &origin, TASK_EVENT_MESSAGE, &event).await;
+                        },
+                        Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {},
+                        Err(broadcast::error::RecvError::Closed) => forwarding = false,
+                    },
+                }
+            };
+
+            // Forward events that were emitted right before the task finished
+            while let Ok(event) = events.try_recv() {
+                if event.task_id == task.id && !event.kind.is_terminal() {
+                    let _ = send_message(&*sender, &origin, TASK_EVENT_MESSAGE, &event).await;
+                }
+            }
+
+            if let Ok(mut active) = active_tasks.lock() {
+                active.remove(&task.id);
+            }
+            drop(lease);
+            if let Some(checkpoints) = &checkpoints {
+                let _ = checkpoints.remove_listener(&task.id);
+            }
+
+            let outcome = match result {
+                Ok(result) => RemoteTaskOutcome {
+                    task_id: task.id.clone(),
+                    result: Some(result),
+                    error: None,
+                },
+                Err(e) => RemoteTaskOutcome {
+                    task_id: task.id.clone(),
+                    result: None,
+                    error: Some(e.to_string()),
+                },
+            };
+
+            if let Err(e) = send_message(&*sender, &origin, TASK_RESULT_MESSAGE, &outcome).await {
+                log::warn!("Failed to send result of task {} to {}: {}", task.id, origin, e);
+            }
+        });
+
+        RemoteTaskReply {
+            accepted: true,
+            reason: None,
+        }
+    }
+
+    /// Stores the checkpoint sent with a task and forwards new checkpoints to the originator.
+    fn replicate_checkpoints(&self, checkpoints: &CheckpointStore, origin: PeerId, request: &RemoteTaskRequest) -> Result<(), Error> {
+        if let Some(checkpoint) = &request.checkpoint {
+            checkpoints.store(checkpoint)?;
+        }
+
+        let sender = self.sender.clone();
+        let runtime = tokio::runtime::Handle::current();
+        checkpoints.set_listener(&request.task.id, Arc::new(move |checkpoint: &Checkpoint| {
+            // Checkpoints are saved from executor threads, so send them from the runtime
+            let sender = sender.clone();
+            let checkpoint = checkpoint.clone();
+            runtime.spawn(async move {
+                let _ = send_message(&*sender, &origin, TASK_CHECKPOINT_MESSAGE, &checkpoint).await;
+            });
+        }))
+    }
+
+    /// Cancels a running task on behalf of the peer that submitted it.
+    async fn handle_cancel(&self, origin: PeerId, request: RemoteCancelRequest) -> Result<(), Error> {
+        let submitted_by_origin = self.lock_active()?.get(&request.task_id) == Some(&origin);
+        if !submitted_by_origin {
+            return Err(Error::Task(format!(
+                "Task {} was not submitted by {}", request.task_id, origin
+            )));
+        }
+
+        // Release the slot now; the outcome is reported to the originator once the task stops
+        self.scheduler.cancel_task(&request.task_id).await?;
+        self.lock_active()?.remove(&request.task_id);
+
+        Ok(())
+    }
+
+    fn lock_active(&self) -> Result<std::sync::MutexGuard<'_, HashMap<String, PeerId>>, Error> {
+        self.active_tasks.lock()
+            .map_err(|_| Error::Task("Failed to lock remote tasks".to_string()))
+    }
+}
+
+#[async_trait]
+impl MessageHandler for RemoteTaskHost {
+    async fn handle_message(&self, peer_id: &PeerId, message: &[u8]) -> Result<Vec<u8>, Error> {
+        let message = Message::from_bytes(message)?;
+
+        match message.message_type.as_str() {
+            TASK_SUBMIT_MESSAGE => {
+                let request = serde_json::from_slice(&message.payload).map_err(Error::Serialization)?;
+                let reply = self.handle_submit(*peer_id, request);
+
+                serde_json::to_vec(&reply).map_err(Error::Serialization)
+            },
+            TASK_CANCEL_MESSAGE => {
+                let request = serde_json::from_slice(&message.payload).map_err(Error::Serialization)?;
+                self.handle_cancel(*peer_id, request).await?;
+
+                Ok(Vec::new())
+            },
+            other => Err(Error::Network(format!("Unexpected message type: {}", other))),
+        }
+    }
+}
+
+fn lease_expired(task_id: &str, peer_id: &PeerId) -> Error {
+    Error::Task(format!("Lease for task {} on peer {} expired", task_id, peer_id))
+}
+
+async fn send_message<T: Serialize>(
+    sender: &(dyn MessageSender + Send + Sync),
+    peer_id: &PeerId,
+    message_type: &str,
+    payload: &T,
+) -> Result<(), Error> {
+    let payload = serde_json::to_vec(payload).map_err(Error::Serialization)?;
+    let message = Message::new(message_type.to_string(), payload).to_bytes()?;
+
+    sender.send_message(peer_id, &message).await.map(|_| ())
+}
+
+async fn send_status(sender: &(dyn MessageSender + Send + Sync), peer_id: &PeerId, task_id: &str, status: TaskStatus) {
+    let update = RemoteStatusUpdate {
+        task_id: task_id.to_string(),
+        status,
+    };
+
+    // Status updates are best effort; a missed update is covered by the next heartbeat
+    let _ = send_message(sender, peer_id, TASK_STATUS_MESSAGE, &update).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ResourceMode;
+    use crate::tasks::cancel::CancellationToken;
+    use crate::tasks::events::ProgressReporter;
+    use crate::tasks::testing::{resources, Handlers, Loopback};
+    use crate::tasks::TaskExecutor;
+
+    struct FakeGpuExecutor;
+
+    #[async_trait]
+    impl TaskExecutor for FakeGpuExecutor {
+        async fn execute(&self, task: &Task, progress: &ProgressReporter, _cancel: &CancellationToken) -> Result<TaskResult, Error> {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            progress.report(0.5, "halfway");
+            Ok(TaskResult::completed(task.data.iter().rev().cloned().collect(), Duration::ZERO, 0))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_gpu_task_is_offloaded_to_capable_peer() {
+        let handlers = Handlers::default();
+        let origin_id = PeerId::random();
+        let worker_id = PeerId::random();
+
+        let mut worker_scheduler = TaskScheduler::new(4, Duration::from_secs(5));
+        worker_scheduler.set_gpu_executor(Arc::new(FakeGpuExecutor));
+        let allocator = ResourceAllocator::new(ResourceMode::HighPerformance, None, resources(8));
+        let host = Arc::new(RemoteTaskHost::new(
+            Arc::new(worker_scheduler),
+            Arc::new(allocator),
+            Arc::new(Loopback::new(worker_id, &handlers)),
+            2,
+        ));
+
+        let events = TaskEvents::new();
+        let mut client = RemoteTaskClient::new(
+            Arc::new(Loopback::new(origin_id, &handlers)),
+            RemoteConfig { lease_timeout: Duration::from_millis(200), max_attempts: 2 },
+        );
+        client.set_events(events.clone());
+        let client = Arc::new(client);
+        client.update_peer(worker_id, host.advertisement().unwrap()).unwrap();
+        client.update_peer(PeerId::random(), PeerAdvertisement::from_resources(&resources(8), 4)).unwrap();
+
+        handlers.lock().unwrap().insert(worker_id, host.clone());
+        handlers.lock().unwrap().insert(origin_id, client.clone());
+
+        // The originator has no GPU executor, so the task must run on the worker
+        let mut scheduler = TaskScheduler::new(4, Duration::from_secs(5));
+        scheduler.set_remote_executor(client.clone());
+        scheduler.set_events(events.clone());
+        let mut subscription = events.subscribe();
+
+        let task = Task {
+            id: "remote-1".to_string(),
+            resource_type: TaskResourceType::Gpu,
+            data: vec![1, 2, 3],
+            status: TaskStatus::Pending,
+            created_at: 0,
+            completed_at: None,
+            checkpoint: None,
+            deterministic: false,
+        };
+
+        let result = scheduler.execute_task(&task).await.unwrap();
+
+        // Progress reported on the worker is forwarded to the originator
+        let mut forwarded = Vec::new();
+        while let Ok(event) = subscription.try_recv() {
+            forwarded.push(event);
+        }
+        assert!(forwarded.iter().any(|event| {
+            event.peer_id == Some(worker_id.to_string())
+                && matches!(&event.kind, TaskEventKind::Progress(progress) if progress.fraction == 0.5)
+        }));
+        assert_eq!(forwarded.last().map(|event| &event.kind), Some(&TaskEventKind::Completed));
+        assert_eq!(result.output, vec![3, 2, 1]);
+        assert!(client.remote_tasks().unwrap().is_empty());
+        assert!(client.peer_reliability(&worker_id).unwrap().unwrap() > 0.5);
+        assert_eq!(host.active_task_count().unwrap(), 0);
+
+        // A task that times out on the originator is cancelled on the worker
+        let mut impatient = TaskScheduler::new(4, Duration::from_millis(10));
+        impatient.set_remote_executor(client.clone());
+        let task = Task {
+            id: "remote-2".to_string(),
+            ..task
+        };
+        assert!(impatient.execute_task(&task).await.is_err());
+        assert!(client.remote_tasks().unwrap().is_empty());
+        assert_eq!(host.active_task_count().unwrap(), 0);
+    }
+
+    /// Accepts every task but never reports back, as a peer that dropped out would.
+    struct SilentPeer;
+
+    #[async_trait]
+    impl MessageHandler for SilentPeer {
+        async fn handle_message(&self, _peer_id: &PeerId, _message: &[u8]) -> Result<Vec<u8>, Error> {
+            serde_json::to_vec(&RemoteTaskReply { accepted: true, reason: None }).map_err(Error::Serialization)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_expired_lease_is_retried_on_next_peer() {
+        let handlers = Handlers::default();
+        let origin_id = PeerId::random();
+        let worker_id = PeerId::random();
+        let silent_id = PeerId::random();
+
+        let mut worker_scheduler = TaskScheduler::new(4, Duration::from_secs(5));
+        worker_scheduler.set_gpu_executor(Arc::new(FakeGpuExecutor));
+        let allocator = ResourceAllocator::new(ResourceMode::HighPerformance, None, resources(8));
+        let host = Arc::new(RemoteTaskHost::new(
+            Arc::new(worker_scheduler),
+            Arc::new(allocator),
+            Arc::new(Loopback::new(worker_id, &handlers)),
+            2,
+        ));
+
+        let events = TaskEvents::new();
+        let mut client = RemoteTaskClient::new(
+            Arc::new(Loopback::new(origin_id, &handlers)),
+            RemoteConfig { lease_timeout: Duration::from_millis(100), max_attempts: 2 },
+        );
+        client.set_events(events.clone());
+        let client = Arc::new(client);
+        client.update_peer(worker_id, host.advertisement().unwrap()).unwrap();
+        // The silent peer advertises more resources, so it is tried first
+        let mut silent = PeerAdvertisement::from_resources(&resources(64), 16);
+        silent.has_gpu = true;
+        client.update_peer(silent_id, silent).unwrap();
+
+        handlers.lock().unwrap().insert(worker_id, host.clone());
+        handlers.lock().unwrap().insert(silent_id, Arc::new(SilentPeer));
+        handlers.lock().unwrap().insert(origin_id, client.clone());
+        let mut subscription = events.subscribe();
+
+        let task = Task {
+            id: "remote-3".to_string(),
+            resource_type: TaskResourceType::Gpu,
+            data: vec![1, 2, 3],
+            status: TaskStatus::Pending,
+            created_at: 0,
+            completed_at: None,
+            checkpoint: None,
+            deterministic: false,
+        };
+        assert_eq!(client.rank_peers(&task).unwrap()[0], silent_id);
+        let (peer_id, result) = client.offload_with_peer(&task).await.unwrap();
+        assert_eq!(peer_id, worker_id);
+        assert_eq!(result.output, vec![3, 2, 1]);
+
+        let mut retried = false;
+        while let Ok(event) = subscription.try_recv() {
+            retried |= matches!(event.kind, TaskEventKind::Retrying { attempt: 2, .. });
+        }
+        assert!(retried);
+        assert!(client.peer_reliability(&silent_id).unwrap().unwrap() < 0.5);
+    }
+}
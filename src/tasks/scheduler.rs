@@ -16,22 +16,60 @@
 //! Task scheduling functionality.
 
 use crate::error::Error;
-use crate::tasks::{Task, TaskExecutor, TaskResourceType}; // Removed unused TaskStatus import
+use crate::resources::allocation::{ResourceAllocator, ResourceLease, ResourceRequest};
+use crate::scoring::ScoringSystem;
+use crate::tasks::cancel::CancellationToken;
+use crate::tasks::checkpoint::CheckpointStore;
+use crate::tasks::clock::{Clock, SystemClock};
+use crate::tasks::events::{ProgressReporter, TaskEventKind, TaskEvents};
+use crate::tasks::recurring::{RecurringSchedule, RecurringStore};
+use crate::tasks::remote::RemoteTaskClient;
+use crate::tasks::store::TaskStore;
+use crate::tasks::{current_timestamp, ExitReason, Task, TaskExecutor, TaskResourceType, TaskResult, TaskStatus};
 // Removed unused async_trait import
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use tokio::sync::{mpsc, Mutex, OwnedSemaphorePermit, Semaphore};
 use tokio::time::Duration; // Removed unused Instant import
 
+/// How long the lease of a local task outlives the task timeout.
+const LEASE_GRACE: Duration = Duration::from_secs(60);
+
+/// How often a queued task checks whether it may start.
+const START_RETRY_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Default number of finished tasks kept for status queries.
+const DEFAULT_MAX_FINISHED_TASKS: usize = 10_000;
+
+/// The ID of a finished task together with its result.
+pub type TaskCompletion = (String, Result<TaskResult, Error>);
+
 /// Task scheduler for distributing tasks to executors.
 #[allow(dead_code)]
 pub struct TaskScheduler {
     cpu_executor: Option<Arc<dyn TaskExecutor + Send + Sync>>,
     gpu_executor: Option<Arc<dyn TaskExecutor + Send + Sync>>,
+    remote_executor: Option<Arc<RemoteTaskClient>>,
+    checkpoint_store: Option<Arc<CheckpointStore>>,
+    events: TaskEvents,
+    clock: Arc<dyn Clock + Send + Sync>,
     pending_tasks: Arc<Mutex<Vec<Task>>>,
-    running_tasks: Arc<Mutex<HashMap<String, Task>>>,
-    completed_tasks: Arc<Mutex<HashMap<String, Task>>>,
+    delayed_tasks: Mutex<Vec<(u64, Task)>>,
+    recurring: Mutex<HashMap<String, RecurringSchedule>>,
+    recurring_store: Option<Arc<RecurringStore>>,
+    task_store: Option<Arc<TaskStore>>,
+    running_tasks: Arc<Mutex<HashMap<String, RunningTask>>>,
+    waiting_tasks: Mutex<HashMap<String, CancellationToken>>,
+    slots: Arc<Semaphore>,
+    completed_tasks: Mutex<FinishedTasks>,
+    submitted_tasks: Mutex<HashSet<String>>,
+    results: Mutex<Vec<mpsc::Sender<TaskCompletion>>>,
+    scoring: Option<(Arc<ScoringSystem>, String)>,
+    allocator: Option<Arc<ResourceAllocator>>,
     max_concurrent_tasks: usize,
+    concurrency_limit: AtomicUsize,
+    paused: AtomicBool,
     task_timeout: Duration,
 }
 
@@ -41,10 +79,26 @@ impl TaskScheduler {
         Self {
             cpu_executor: None,
             gpu_executor: None,
+            remote_executor: None,
+            checkpoint_store: None,
+            events: TaskEvents::new(),
+            clock: Arc::new(SystemClock),
             pending_tasks: Arc::new(Mutex::new(Vec::new())),
+            delayed_tasks: Mutex::new(Vec::new()),
+            recurring: Mutex::new(HashMap::new()),
+            recurring_store: None,
+            task_store: None,
             running_tasks: Arc::new(Mutex::new(HashMap::new())),
-            completed_tasks: Arc::new(Mutex::new(HashMap::new())),
+            waiting_tasks: Mutex::new(HashMap::new()),
+            slots: Arc::new(Semaphore::new(max_concurrent_tasks.max(1))),
+            completed_tasks: Mutex::new(FinishedTasks::new(DEFAULT_MAX_FINISHED_TASKS)),
+            submitted_tasks: Mutex::new(HashSet::new()),
+            results: Mutex::new(Vec::new()),
+            scoring: None,
+            allocator: None,
             max_concurrent_tasks,
+            concurrency_limit: AtomicUsize::new(max_concurrent_tasks),
+            paused: AtomicBool::new(false),
             task_timeout,
         }
     }
@@ -59,13 +113,385 @@ impl TaskScheduler {
         self.gpu_executor = Some(executor);
     }
     
+    /// Sets the client used to offload tasks to remote peers.
+    ///
+    /// Tasks are offloaded when no local executor can run them, or when
+    /// [`concurrency_limit`](Self::concurrency_limit) tasks are already running locally.
+    pub fn set_remote_executor(&mut self, remote: Arc<RemoteTaskClient>) {
+        self.remote_executor = Some(remote);
+    }
+    
+    /// Sets the checkpoint store used to fill in the checkpoint metadata of running tasks.
+    pub fn set_checkpoint_store(&mut self, checkpoint_store: Arc<CheckpointStore>) {
+        self.checkpoint_store = Some(checkpoint_store);
+    }
+    
+    /// Sets the channel that task lifecycle events are published to.
+    pub fn set_events(&mut self, events: TaskEvents) {
+        self.events = events;
+    }
+    
+    /// Returns the channel that task lifecycle events are published to.
+    pub fn events(&self) -> &TaskEvents {
+        &self.events
+    }
+    
+    /// Sets the clock that delayed and recurring tasks are scheduled by.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock + Send + Sync>) {
+        self.clock = clock;
+    }
+    
+    /// Sets the store that recurring schedules are persisted in, and loads the
+    /// schedules stored there.
+    pub fn set_recurring_store(&mut self, store: Arc<RecurringStore>) -> Result<(), Error> {
+        let recurring = self.recurring.get_mut();
+        for schedule in store.all()? {
+            recurring.insert(schedule.id.clone(), schedule);
+        }
+        
+        self.recurring_store = Some(store);
+        Ok(())
+    }
+    
+    /// Sets the store that submitted tasks are persisted in.
+    ///
+    /// The final status of every submitted task is written back to the store when it
+    /// finishes, however it finishes.
+    pub fn set_task_store(&mut self, store: Arc<TaskStore>) {
+        self.task_store = Some(store);
+    }
+    
+    /// Returns the store that submitted tasks are persisted in.
+    pub fn task_store(&self) -> Option<&Arc<TaskStore>> {
+        self.task_store.as_ref()
+    }
+    
+    /// Sets the scoring system that the resources used by locally executed tasks
+    /// are recorded in, credited to the given local peer.
+    pub fn set_scoring(&mut self, scoring: Arc<ScoringSystem>, local_peer_id: &str) {
+        self.scoring = Some((scoring, local_peer_id.to_string()));
+    }
+    
+    /// Sets the allocator whose budget local tasks reserve a core of, and GPU tasks
+    /// the GPU memory of their buffers.
+    ///
+    /// Tasks only start while the allocator has a core that is not reserved,
+    /// including by tasks run on behalf of remote peers.
+    pub fn set_allocator(&mut self, allocator: Arc<ResourceAllocator>) {
+        self.allocator = Some(allocator);
+    }
+    
+    /// Sets the number of finished tasks kept for status queries, oldest first.
+    ///
+    /// The status of older submitted tasks can still be read from the task store.
+    pub fn set_max_finished_tasks(&mut self, max_finished_tasks: usize) {
+        self.completed_tasks.get_mut().max = max_finished_tasks;
+    }
+    
+    /// Returns the maximum number of tasks running locally at the same time.
+    pub fn max_concurrent_tasks(&self) -> usize {
+        self.max_concurrent_tasks
+    }
+    
+    /// Returns the slots that queued tasks hold while they run, one per concurrent task.
+    pub(crate) fn slots(&self) -> &Arc<Semaphore> {
+        &self.slots
+    }
+    
+    /// Subscribes to the results of submitted tasks in completion order.
+    ///
+    /// Every subscriber receives every result. At most `capacity` results are
+    /// buffered per subscriber; once a subscriber's buffer is full, finishing
+    /// submitted tasks wait until it reads or is dropped.
+    pub async fn subscribe_results(&self, capacity: usize) -> mpsc::Receiver<TaskCompletion> {
+        let (sender, receiver) = mpsc::channel(capacity.max(1));
+        self.results.lock().await.push(sender);
+        receiver
+    }
+    
+    /// Returns whether a local executor is available for the given resource type.
+    pub fn has_executor_for(&self, resource_type: TaskResourceType) -> bool {
+        match resource_type {
+            TaskResourceType::Cpu | TaskResourceType::Memory | TaskResourceType::Disk => self.cpu_executor.is_some(),
+            TaskResourceType::Gpu => self.gpu_executor.is_some(),
+            TaskResourceType::Network => false,
+        }
+    }
+    
+    /// Returns the number of tasks currently running locally.
+    pub async fn running_task_count(&self) -> usize {
+        self.running_tasks.lock().await.len()
+    }
+    
+    /// Returns the number of tasks waiting in the queue.
+    pub async fn pending_task_count(&self) -> usize {
+        self.pending_tasks.lock().await.len()
+    }
+    
+    /// Returns whether the queue is empty and another task could run locally.
+    pub async fn is_idle(&self) -> bool {
+        self.pending_tasks.lock().await.is_empty() && self.can_start_task().await
+    }
+    
+    /// Pauses or resumes starting queued tasks locally.
+    ///
+    /// Running tasks are not affected.
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+    }
+    
+    /// Returns whether starting queued tasks locally is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+    
+    /// Limits the number of tasks running locally to a fraction of `max_concurrent_tasks`.
+    ///
+    /// At least one task may run unless the scheduler is paused.
+    pub fn set_concurrency_budget(&self, fraction: f32) {
+        let limit = (self.max_concurrent_tasks as f32 * fraction.clamp(0.0, 1.0)).ceil() as usize;
+        self.concurrency_limit.store(limit.clamp(1, self.max_concurrent_tasks.max(1)), Ordering::Relaxed);
+    }
+    
+    /// Returns the number of tasks currently allowed to run locally.
+    pub fn concurrency_limit(&self) -> usize {
+        if self.is_paused() {
+            0
+        } else {
+            self.concurrency_limit.load(Ordering::Relaxed)
+        }
+    }
+    
+    /// Returns whether another task may start locally.
+    pub async fn can_start_task(&self) -> bool {
+        let has_free_core = match &self.allocator {
+            Some(allocator) => allocator.available().is_ok_and(|available| available.cpu_cores > 0),
+            None => true,
+        };
+        
+        has_free_core && self.running_task_count().await < self.concurrency_limit()
+    }
+    
+    /// Removes the oldest task from the queue.
+    pub async fn pop_pending(&self) -> Option<Task> {
+        let mut pending_tasks = self.pending_tasks.lock().await;
+        if pending_tasks.is_empty() {
+            None
+        } else {
+            Some(pending_tasks.remove(0))
+        }
+    }
+    
+    /// Removes up to `max_tasks` tasks from the queue, e.g. to hand them over to another peer.
+    ///
+    /// Tasks with the highest preference are taken first; among equally preferred
+    /// tasks the most recently queued go first, so the oldest stay with this node.
+    pub async fn take_pending<F>(&self, max_tasks: usize, preference: F) -> Vec<Task>
+    where
+        F: Fn(&Task) -> f64,
+    {
+        let mut pending_tasks = self.pending_tasks.lock().await;
+        
+        let mut ranked: Vec<(usize, f64)> = pending_tasks.iter()
+            .enumerate()
+            .map(|(index, task)| (index, preference(task)))
+            .collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(b.0.cmp(&a.0)));
+        
+        let mut taken: Vec<usize> = ranked.into_iter().take(max_tasks).map(|(index, _)| index).collect();
+        // Remove from the back so the remaining indices stay valid
+        taken.sort_unstable_by(|a, b| b.cmp(a));
+        taken.into_iter().map(|index| pending_tasks.remove(index)).collect()
+    }
+    
+    /// Gets a task that is currently running locally, including its latest checkpoint metadata.
+    pub async fn get_running_task(&self, task_id: &str) -> Result<Option<Task>, Error> {
+        let mut task = match self.running_tasks.lock().await.get(task_id) {
+            Some(running) => running.task.clone(),
+            None => return Ok(None),
+        };
+        
+        if let Some(checkpoint_store) = &self.checkpoint_store {
+            task.checkpoint = checkpoint_store.metadata(task_id)?;
+        }
+        
+        Ok(Some(task))
+    }
+    
+    /// Gets a task that has finished, with its final status and completion time.
+    ///
+    /// Only the most recently finished tasks are kept.
+    pub async fn get_finished_task(&self, task_id: &str) -> Option<Task> {
+        self.completed_tasks.lock().await.get(task_id).cloned()
+    }
+    
+    /// Gets the status of a task known to the scheduler.
+    pub async fn get_task_status(&self, task_id: &str) -> Option<TaskStatus> {
+        if self.running_tasks.lock().await.contains_key(task_id) {
+            return Some(TaskStatus::Running);
+        }
+        if let Some(task) = self.completed_tasks.lock().await.get(task_id) {
+            return Some(task.status);
+        }
+        if self.pending_tasks.lock().await.iter().any(|task| task.id == task_id)
+            || self.waiting_tasks.lock().await.contains_key(task_id) {
+            return Some(TaskStatus::Pending);
+        }
+        
+        None
+    }
+    
+    /// Cancels a pending, queued or running task.
+    ///
+    /// A running task's slot and reserved resources are released immediately and it
+    /// is recorded as cancelled. A task waiting in [`execute_queued`](Self::execute_queued)
+    /// finishes as cancelled without starting.
+    /// Executors stop the task as soon as they can, and remote tasks are cancelled on
+    /// the executing peer. Returns whether a matching task was found.
+    pub async fn cancel_task(&self, task_id: &str) -> Result<bool, Error> {
+        let running = self.running_tasks.lock().await.remove(task_id);
+        if let Some(running) = running {
+            running.cancel.cancel();
+            // The executor may take a while to return, so record the cancellation now
+            let mut record = running.task;
+            record.status = TaskStatus::Cancelled;
+            record.completed_at = Some(current_timestamp());
+            self.completed_tasks.lock().await.insert(task_id.to_string(), record);
+            return Ok(true);
+        }
+        
+        if let Some(remote) = &self.remote_executor {
+            if remote.cancel(task_id).await? {
+                return Ok(true);
+            }
+        }
+        
+        if let Some(cancel) = self.waiting_tasks.lock().await.get(task_id) {
+            cancel.cancel();
+            return Ok(true);
+        }
+        
+        let cancelled = {
+            let mut pending_tasks = self.pending_tasks.lock().await;
+            pending_tasks.iter()
+                .position(|task| task.id == task_id)
+                .map(|index| pending_tasks.remove(index))
+        };
+        let cancelled = match cancelled {
+            Some(task) => Some(task),
+            None => {
+                let mut delayed_tasks = self.delayed_tasks.lock().await;
+                delayed_tasks.iter()
+                    .position(|(_, task)| task.id == task_id)
+                    .map(|index| delayed_tasks.remove(index).1)
+            },
+        };
+        
+        match cancelled {
+            Some(task) => {
+                self.finish(&task, &Ok(TaskResult::cancelled(Duration::ZERO))).await;
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+    
+    /// Schedules a submitted task for execution, persisting its final status and
+    /// publishing its result to the result subscribers once it finishes.
+    pub async fn schedule_submitted(&self, task: Task) -> Result<(), Error> {
+        self.submitted_tasks.lock().await.insert(task.id.clone());
+        self.schedule_task(task).await
+    }
+    
     /// Schedules a task for execution.
     pub async fn schedule_task(&self, task: Task) -> Result<(), Error> {
         let mut pending_tasks = self.pending_tasks.lock().await;
+        self.events.emit_kind(&task.id, TaskEventKind::Scheduled);
         pending_tasks.push(task);
         Ok(())
     }
     
+    /// Schedules a task that must not start before the given time (seconds since the Unix epoch).
+    ///
+    /// The task is queued by the first call to [`TaskScheduler::tick`] at or after that time.
+    pub async fn schedule_task_at(&self, task: Task, not_before: u64) -> Result<(), Error> {
+        if not_before <= self.clock.now() {
+            return self.schedule_task(task).await;
+        }
+        
+        self.events.emit_kind(&task.id, TaskEventKind::Scheduled);
+        self.delayed_tasks.lock().await.push((not_before, task));
+        Ok(())
+    }
+    
+    /// Adds a recurring schedule, replacing any schedule with the same ID.
+    pub async fn add_recurring(&self, schedule: RecurringSchedule) -> Result<(), Error> {
+        if let Some(store) = &self.recurring_store {
+            store.put(&schedule)?;
+        }
+        
+        self.recurring.lock().await.insert(schedule.id.clone(), schedule);
+        Ok(())
+    }
+    
+    /// Removes a recurring schedule. Runs that were already queued are not affected.
+    pub async fn remove_recurring(&self, schedule_id: &str) -> Result<bool, Error> {
+        if let Some(store) = &self.recurring_store {
+            store.remove(schedule_id)?;
+        }
+        
+        Ok(self.recurring.lock().await.remove(schedule_id).is_some())
+    }
+    
+    /// Returns the recurring schedules.
+    pub async fn recurring_schedules(&self) -> Vec<RecurringSchedule> {
+        self.recurring.lock().await.values().cloned().collect()
+    }
+    
+    /// Queues the delayed tasks and recurring runs that are due.
+    ///
+    /// Should be called periodically; returns the number of queued tasks.
+    pub async fn tick(&self) -> Result<usize, Error> {
+        let now = self.clock.now();
+        
+        let due: Vec<Task> = {
+            let mut delayed_tasks = self.delayed_tasks.lock().await;
+            let (due, waiting): (Vec<_>, Vec<_>) = delayed_tasks.drain(..)
+                .partition(|(not_before, _)| *not_before <= now);
+            *delayed_tasks = waiting;
+            due.into_iter().map(|(_, task)| task).collect()
+        };
+        let mut queued = due.len();
+        self.pending_tasks.lock().await.extend(due);
+        
+        let mut runs = Vec::new();
+        {
+            let mut recurring = self.recurring.lock().await;
+            for schedule in recurring.values_mut() {
+                if schedule.next_run.is_none_or(|next_run| next_run > now) {
+                    continue;
+                }
+                
+                for run in schedule.take_due_runs(now) {
+                    runs.push(schedule.task_for_run(run, now));
+                }
+                // The runs are queued even if the schedule cannot be persisted
+                if let Some(store) = &self.recurring_store {
+                    if let Err(e) = store.put(schedule) {
+                        log::warn!("Failed to persist recurring schedule {}: {}", schedule.id, e);
+                    }
+                }
+            }
+        }
+        
+        queued += runs.len();
+        for task in runs {
+            self.schedule_task(task).await?;
+        }
+        
+        Ok(queued)
+    }
+    
     /// Starts the scheduler.
     pub async fn start(&self) -> Result<(), Error> {
         // Implementation will be added later
@@ -78,8 +504,242 @@ impl TaskScheduler {
         Ok(())
     }
     
+    /// Executes a task immediately, bounded by the task timeout.
+    ///
+    /// The task runs on the matching local executor, or on a remote peer if a remote
+    /// executor is set and the task cannot run locally right now.
+    pub async fn execute_task(&self, task: &Task) -> Result<TaskResult, Error> {
+        if let Some(remote) = &self.remote_executor {
+            if self.should_offload(task).await {
+                return self.offload(remote, task).await;
+            }
+        }
+        
+        // Holds a core, and the GPU memory of GPU tasks, of the allocator's budget while the task runs
+        let lease = match &self.allocator {
+            Some(allocator) => match allocator.reserve(ResourceRequest::for_task(task), self.task_timeout + LEASE_GRACE) {
+                Ok(lease) => Some(lease),
+                Err(e) => {
+                    let result = Err(e);
+                    self.finish(task, &result).await;
+                    return result;
+                },
+            },
+            None => None,
+        };
+        
+        self.execute_with_lease(task, lease, CancellationToken::new()).await
+    }
+    
+    /// Executes a task once it may start, bounded by the task timeout.
+    ///
+    /// Unlike [`execute_task`](Self::execute_task), the task waits for one of the
+    /// `max_concurrent_tasks` slots and for the allocator to fit it instead of failing,
+    /// so that large fan-outs are queued. The task can be cancelled through `cancel`,
+    /// or through [`cancel_task`](Self::cancel_task) once this is polled.
+    pub async fn execute_queued(&self, task: &Task, cancel: CancellationToken) -> Result<TaskResult, Error> {
+        self.waiting_tasks.lock().await.insert(task.id.clone(), cancel.clone());
+        let result = self.start_queued(task, cancel).await;
+        self.waiting_tasks.lock().await.remove(&task.id);
+        result
+    }
+    
+    async fn start_queued(&self, task: &Task, cancel: CancellationToken) -> Result<TaskResult, Error> {
+        let waited = tokio::select! {
+            waited = self.wait_for_start(task) => Some(waited),
+            _ = cancel.cancelled() => None,
+        };
+        
+        let (_permit, start) = match waited {
+            Some(Ok(started)) if !cancel.is_cancelled() => started,
+            Some(Err(e)) => {
+                let result = Err(e);
+                self.finish(task, &result).await;
+                return result;
+            },
+            _ => {
+                let result = Ok(TaskResult::cancelled(Duration::ZERO));
+                self.finish(task, &result).await;
+                return result;
+            },
+        };
+        
+        match start {
+            Start::Local(lease) => self.execute_with_lease(task, lease, cancel).await,
+            Start::Remote(remote) => self.offload(&remote, task).await,
+        }
+    }
+    
+    /// Waits for a slot and until the task may start, reserving its resources if it
+    /// runs locally.
+    async fn wait_for_start(&self, task: &Task) -> Result<(OwnedSemaphorePermit, Start), Error> {
+        let permit = self.slots.clone().acquire_owned().await
+            .map_err(|_| Error::Task("The scheduler no longer accepts tasks".to_string()))?;
+        
+        loop {
+            if let Some(remote) = &self.remote_executor {
+                if self.should_offload(task).await {
+                    return Ok((permit, Start::Remote(remote.clone())));
+                }
+            }
+            
+            if self.can_start_task().await {
+                match &self.allocator {
+                    Some(allocator) => match allocator.reserve(ResourceRequest::for_task(task), self.task_timeout + LEASE_GRACE) {
+                        Ok(lease) => return Ok((permit, Start::Local(Some(lease)))),
+                        // Retried once running tasks may have released their resources
+                        Err(e) => log::trace!("Task {} is waiting for resources: {}", task.id, e),
+                    },
+                    None => return Ok((permit, Start::Local(None))),
+                }
+            }
+            
+            tokio::time::sleep(START_RETRY_INTERVAL).await;
+        }
+    }
+    
+    /// Executes a task on the matching local executor, bounded by the task timeout.
+    pub async fn execute_locally(&self, task: &Task) -> Result<TaskResult, Error> {
+        self.execute_with_lease(task, None, CancellationToken::new()).await
+    }
+    
+    /// Offloads a task to a remote peer, bounded by the task timeout.
+    async fn offload(&self, remote: &RemoteTaskClient, task: &Task) -> Result<TaskResult, Error> {
+        // Started and progress events are forwarded by the remote peer
+        let result = match tokio::time::timeout(self.task_timeout, remote.offload(task)).await {
+            Ok(result) => result,
+            Err(_) => {
+                // The peer keeps running the task until told otherwise
+                if let Err(e) = remote.cancel(&task.id).await {
+                    log::warn!("Failed to cancel timed-out task {} on its peer: {}", task.id, e);
+                }
+                Err(Error::Task(format!("Task {} timed out after {:?}", task.id, self.task_timeout)))
+            },
+        };
+        self.finish(task, &result).await;
+        result
+    }
+    
+    /// Executes a task locally, holding the lease until it finishes or is cancelled.
+    async fn execute_with_lease(
+        &self,
+        task: &Task,
+        lease: Option<ResourceLease>,
+        cancel: CancellationToken,
+    ) -> Result<TaskResult, Error> {
+        let result = self.run_locally(task, lease, cancel).await;
+        if let (Some((scoring, local_peer_id)), Ok(result)) = (&self.scoring, &result) {
+            if let Err(e) = scoring.record_task_usage(local_peer_id, &result.usage()) {
+                log::warn!("Failed to record the contribution of task {}: {}", task.id, e);
+            }
+        }
+        self.finish(task, &result).await;
+        result
+    }
+    
+    async fn run_locally(&self, task: &Task, lease: Option<ResourceLease>, cancel: CancellationToken) -> Result<TaskResult, Error> {
+        let executor = self.get_executor_for_task(task).ok_or_else(|| {
+            Error::Task(format!("No executor available for task {} ({:?})", task.id, task.resource_type))
+        })?;
+        
+        let mut record = task.clone();
+        record.status = TaskStatus::Running;
+        if let Some(checkpoint_store) = &self.checkpoint_store {
+            // A resumed task restarts from its latest checkpoint
+            record.checkpoint = checkpoint_store.metadata(&task.id)?;
+        }
+        self.running_tasks.lock().await.insert(task.id.clone(), RunningTask {
+            task: record,
+            cancel: cancel.clone(),
+            _lease: lease,
+        });
+        let _running = RunningTaskGuard {
+            running_tasks: self.running_tasks.clone(),
+            task_id: task.id.clone(),
+        };
+        
+        self.events.emit_kind(&task.id, TaskEventKind::Started);
+        let progress = ProgressReporter::new(&task.id, self.events.clone());
+        
+        let result = match tokio::time::timeout(self.task_timeout, executor.execute(task, &progress, &cancel)).await {
+            Ok(result) => result,
+            Err(_) => {
+                // Handlers still running on a pool stop once they poll the token
+                cancel.cancel();
+                return Ok(TaskResult {
+                    exit_reason: ExitReason::TimedOut,
+                    ..TaskResult::cancelled(Duration::ZERO)
+                });
+            },
+        };
+        
+        // Whatever a task produced after it was cancelled is discarded
+        if cancel.is_cancelled() {
+            let cpu_time = result.map(|result| result.cpu_time).unwrap_or_default();
+            return Ok(TaskResult::cancelled(cpu_time));
+        }
+        
+        result
+    }
+    
+    /// Records the final status of a task and emits the matching terminal event.
+    pub(crate) async fn finish(&self, task: &Task, result: &Result<TaskResult, Error>) {
+        let (status, kind) = match result {
+            Ok(result) => match &result.exit_reason {
+                ExitReason::Completed => (TaskStatus::Completed, TaskEventKind::Completed),
+                ExitReason::Failed(reason) => (TaskStatus::Failed, TaskEventKind::Failed(reason.clone())),
+                ExitReason::TimedOut => (TaskStatus::Failed, TaskEventKind::Failed("Task timed out".to_string())),
+                ExitReason::Cancelled => (TaskStatus::Cancelled, TaskEventKind::Cancelled),
+            },
+            Err(e) => (TaskStatus::Failed, TaskEventKind::Failed(e.to_string())),
+        };
+        
+        let mut record = task.clone();
+        record.status = status;
+        record.completed_at = Some(current_timestamp());
+        self.completed_tasks.lock().await.insert(task.id.clone(), record.clone());
+        
+        self.events.emit_kind(&task.id, kind);
+        
+        if self.submitted_tasks.lock().await.remove(&task.id) {
+            if let Some(store) = &self.task_store {
+                if let Err(e) = store.update(&record) {
+                    log::warn!("Failed to persist status of task {}: {}", task.id, e);
+                }
+            }
+            self.publish(task, result).await;
+        }
+    }
+    
+    /// Publishes the result of a submitted task to every result subscriber.
+    ///
+    /// Waits while a subscriber's buffer is full, so a slow subscriber holds back
+    /// the dispatch of further tasks instead of missing results.
+    async fn publish(&self, task: &Task, result: &Result<TaskResult, Error>) {
+        // Cloned so that waiting on one subscriber does not block new subscriptions
+        let subscribers = self.results.lock().await.clone();
+        for subscriber in subscribers {
+            let result = match result {
+                Ok(result) => Ok(result.clone()),
+                Err(e) => Err(Error::Task(e.to_string())),
+            };
+            if subscriber.send((task.id.clone(), result)).await.is_err() {
+                // The subscriber is gone, so stop publishing to it
+                self.results.lock().await.retain(|other| !other.same_channel(&subscriber));
+            }
+        }
+    }
+    
+    /// Returns whether a task should run on a remote peer instead of locally.
+    async fn should_offload(&self, task: &Task) -> bool {
+        if !self.has_executor_for(task.resource_type) {
+            return true;
+        }
+        
+        !self.can_start_task().await
+    }
+    
     /// Gets the appropriate executor for a task.
-    #[allow(dead_code)]
     fn get_executor_for_task(&self, task: &Task) -> Option<Arc<dyn TaskExecutor + Send + Sync>> {
         match task.resource_type {
             TaskResourceType::Cpu | TaskResourceType::Memory | TaskResourceType::Disk => self.cpu_executor.clone(),
@@ -88,3 +748,107 @@ impl TaskScheduler {
         }
     }
 }
+
+/// The most recently finished tasks, by ID.
+struct FinishedTasks {
+    tasks: HashMap<String, Task>,
+    order: VecDeque<String>,
+    max: usize,
+}
+
+impl FinishedTasks {
+    fn new(max: usize) -> Self {
+        Self {
+            tasks: HashMap::new(),
+            order: VecDeque::new(),
+            max,
+        }
+    }
+    
+    fn get(&self, task_id: &str) -> Option<&Task> {
+        self.tasks.get(task_id)
+    }
+    
+    /// Records a finished task, forgetting the oldest ones beyond the maximum.
+    fn insert(&mut self, task_id: String, task: Task) {
+        if self.tasks.insert(task_id.clone(), task).is_none() {
+            self.order.push_back(task_id);
+        }
+        
+        while self.order.len() > self.max {
+            if let Some(oldest) = self.order.pop_front() {
+                self.tasks.remove(&oldest);
+            }
+        }
+    }
+}
+
+/// Where a queued task runs once it may start.
+enum Start {
+    /// On a local executor, holding the lease of its resources if an allocator is set.
+    Local(Option<ResourceLease>),
+    /// On a remote peer.
+    Remote(Arc<RemoteTaskClient>),
+}
+
+/// A task running on a local executor, together with the resources reserved for it.
+struct RunningTask {
+    task: Task,
+    cancel: CancellationToken,
+    // Released when the task finishes or is cancelled
+    _lease: Option<ResourceLease>,
+}
+
+/// Removes a task from the running set when its execution finishes or is dropped.
+struct RunningTaskGuard {
+    running_tasks: Arc<Mutex<HashMap<String, RunningTask>>>,
+    task_id: String,
+}
+
+impl Drop for RunningTaskGuard {
+    fn drop(&mut self) {
+        if let Ok(mut running) = self.running_tasks.try_lock() {
+            running.remove(&self.task_id);
+            return;
+        }
+        
+        // The lock is held elsewhere; remove the entry once it is released
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            let running_tasks = self.running_tasks.clone();
+            let task_id = std::mem::take(&mut self.task_id);
+            handle.spawn(async move {
+                running_tasks.lock().await.remove(&task_id);
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tasks::TaskResourceType;
+    
+    #[tokio::test]
+    async fn test_finished_tasks_are_bounded() {
+        let mut scheduler = TaskScheduler::new(1, Duration::from_secs(5));
+        scheduler.set_max_finished_tasks(2);
+        
+        for index in 0..3 {
+            let task = Task {
+                id: format!("task-{}", index),
+                resource_type: TaskResourceType::Cpu,
+                data: Vec::new(),
+                status: TaskStatus::Pending,
+                created_at: 0,
+                completed_at: None,
+                checkpoint: None,
+                deterministic: false,
+            };
+            scheduler.finish(&task, &Ok(TaskResult::completed(Vec::new(), Duration::ZERO, 0))).await;
+        }
+        
+        assert!(scheduler.get_finished_task("task-0").await.is_none());
+        assert_eq!(scheduler.get_task_status("task-1").await, Some(TaskStatus::Completed));
+        assert_eq!(scheduler.get_task_status("task-2").await, Some(TaskStatus::Completed));
+    }
+}
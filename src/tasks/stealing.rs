@@ -0,0 +1,533 @@
+/* Copyright 2025 Joao Guimaraes, Catp2p Project
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Work stealing between idle and busy peers.
+//!
+//! An idle node asks the busiest peers for queued tasks. The owner moves a batch of
+//! entries out of its scheduler queue, preferring tasks whose inputs the thief already
+//! holds, and leases them to the thief. Stolen tasks report back to the owner like
+//! offloaded tasks do; if the thief stops renewing a lease, or returns a task it
+//! cannot run, the task is queued again.
+
+use crate::error::Error;
+use crate::network::protocol::{Message, MessageHandler, MessageSender};
+use crate::tasks::remote::{
+    LeaseOutcome, PeerAdvertisement, RemoteTaskClient, RemoteTaskHost, RemoteTaskRequest,
+    TASK_CANCEL_MESSAGE, TASK_SUBMIT_MESSAGE,
+};
+use crate::tasks::scheduler::TaskScheduler;
+use crate::tasks::{content_hash, Task, TaskPayload};
+use async_trait::async_trait;
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+
+/// Message type for asking a peer for queued tasks.
+pub const TASK_STEAL_MESSAGE: &str = "task-steal";
+/// Message type for handing a stolen task back to its owner.
+pub const TASK_RETURN_MESSAGE: &str = "task-return";
+
+/// Default number of input hashes remembered as held locally.
+const DEFAULT_MAX_HELD_INPUTS: usize = 1024;
+
+/// A request from an idle peer for queued tasks.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StealRequest {
+    /// The resources of the idle peer.
+    pub advertisement: PeerAdvertisement,
+    /// Content hashes of the task inputs the idle peer already holds.
+    pub held_inputs: Vec<String>,
+}
+
+/// The tasks handed over in reply to a [`StealRequest`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StealReply {
+    /// The handed over tasks, each leased to the idle peer.
+    pub tasks: Vec<RemoteTaskRequest>,
+}
+
+/// A stolen task handed back to its owner without running it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReturnedTask {
+    /// The task ID.
+    pub task_id: String,
+}
+
+/// Decides when and how much work is stolen.
+#[derive(Debug, Clone)]
+pub struct StealPolicy {
+    /// Peers with at most this many queued tasks are not stolen from.
+    pub min_queue_length: usize,
+    /// Maximum number of tasks handed over per request.
+    pub max_batch: usize,
+}
+
+impl StealPolicy {
+    /// Returns how many tasks an owner with `queue_length` queued tasks hands over
+    /// to a thief with `free_slots` free slots.
+    ///
+    /// At most half of the queue is given away, and never more than the surplus over
+    /// `min_queue_length`.
+    pub fn batch_size(&self, queue_length: usize, free_slots: usize) -> usize {
+        queue_length.saturating_sub(self.min_queue_length)
+            .min(queue_length / 2)
+            .min(free_slots)
+            .min(self.max_batch)
+    }
+
+    /// Scores a peer as a victim, or returns None if it has too little queued work.
+    ///
+    /// Peers with the longest queue relative to their cores are the most overloaded.
+    pub fn victim_score(&self, advertisement: &PeerAdvertisement) -> Option<f64> {
+        if advertisement.queue_length <= self.min_queue_length {
+            return None;
+        }
+
+        Some(advertisement.queue_length as f64 / advertisement.cpu_cores.max(1) as f64)
+    }
+}
+
+impl Default for StealPolicy {
+    fn default() -> Self {
+        Self {
+            min_queue_length: 1,
+            max_batch: 8,
+        }
+    }
+}
+
+/// Returns the content hashes of the inputs a task needs.
+pub type InputLocator = Arc<dyn Fn(&Task) -> Vec<String> + Send + Sync>;
+
+/// Locates the input of a task as the hash of its payload input, or of its data
+/// if it does not carry a [`TaskPayload`].
+fn default_inputs(task: &Task) -> Vec<String> {
+    match TaskPayload::from_bytes(&task.data) {
+        Ok(payload) => vec![content_hash(&payload.input)],
+        Err(_) => vec![content_hash(&task.data)],
+    }
+}
+
+/// Steals tasks from busy peers when idle, and hands over tasks to idle peers.
+pub struct WorkStealer {
+    scheduler: Arc<TaskScheduler>,
+    client: Arc<RemoteTaskClient>,
+    host: Arc<RemoteTaskHost>,
+    sender: Arc<dyn MessageSender + Send + Sync>,
+    policy: StealPolicy,
+    input_locator: InputLocator,
+    held_inputs: Mutex<HeldInputs>,
+}
+
+impl WorkStealer {
+    /// Creates a new WorkStealer.
+    ///
+    /// Tasks are stolen from the scheduler's queue, leased out through the client and
+    /// stolen tasks run on the host. The stealer also dispatches the client's and
+    /// host's messages, so it can be registered as the node's only task handler.
+    pub fn new(
+        scheduler: Arc<TaskScheduler>,
+        client: Arc<RemoteTaskClient>,
+        host: Arc<RemoteTaskHost>,
+        sender: Arc<dyn MessageSender + Send + Sync>,
+        policy: StealPolicy,
+    ) -> Self {
+        Self {
+            scheduler,
+            client,
+            host,
+            sender,
+            policy,
+            input_locator: Arc::new(default_inputs),
+            held_inputs: Mutex::new(HeldInputs::new(DEFAULT_MAX_HELD_INPUTS)),
+        }
+    }
+
+    /// Sets how the inputs of a task are located.
+    pub fn set_input_locator(&mut self, input_locator: InputLocator) {
+        self.input_locator = input_locator;
+    }
+
+    /// Sets the number of input hashes remembered as held locally, oldest first.
+    ///
+    /// All of them are sent with every steal request.
+    pub fn set_max_held_inputs(&mut self, max_held_inputs: usize) {
+        if let Ok(held_inputs) = self.held_inputs.get_mut() {
+            held_inputs.max = max_held_inputs;
+        }
+    }
+
+    /// Records inputs that are available locally, making tasks using them preferred when stealing.
+    pub fn add_held_inputs(&self, hashes: impl IntoIterator<Item = String>) -> Result<(), Error> {
+        let mut held_inputs = self.lock_held_inputs()?;
+        for hash in hashes {
+            held_inputs.insert(hash);
+        }
+        Ok(())
+    }
+
+    /// Forgets inputs that are no longer available locally.
+    pub fn remove_held_input(&self, hash: &str) -> Result<(), Error> {
+        self.lock_held_inputs()?.remove(hash);
+        Ok(())
+    }
+
+    /// Returns the advertisement to publish to other peers, including the queue length.
+    pub async fn advertisement(&self) -> Result<PeerAdvertisement, Error> {
+        let mut advertisement = self.host.advertisement()?;
+        advertisement.queue_length = self.scheduler.pending_task_count().await;
+
+        Ok(advertisement)
+    }
+
+    /// Steals tasks from the busiest peer willing to give some, if this node is idle.
+    ///
+    /// Returns the number of stolen tasks that were started.
+    pub async fn steal(&self) -> Result<usize, Error> {
+        if !self.scheduler.is_idle().await {
+            return Ok(0);
+        }
+
+        let advertisement = self.advertisement().await?;
+        if advertisement.free_slots == 0 {
+            return Ok(0);
+        }
+
+        let mut victims: Vec<(PeerId, f64)> = self.client.peers()?.into_iter()
+            .filter_map(|(peer_id, peer)| self.policy.victim_score(&peer).map(|score| (peer_id, score)))
+            .collect();
+        victims.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        let request = StealRequest {
+            advertisement,
+            held_inputs: self.lock_held_inputs()?.order.iter().cloned().collect(),
+        };
+        let message = Message::new(
+            TASK_STEAL_MESSAGE.to_string(),
+            serde_json::to_vec(&request).map_err(Error::Serialization)?,
+        ).to_bytes()?;
+
+        for (victim, _) in victims {
+            let reply = match self.sender.send_message(&victim, &message).await
+                .and_then(|bytes| serde_json::from_slice::<StealReply>(&bytes).map_err(Error::Serialization))
+            {
+                Ok(reply) => reply,
+                Err(e) => {
+                    log::debug!("Failed to steal tasks from peer {}: {}", victim, e);
+                    continue;
+                },
+            };
+            if reply.tasks.is_empty() {
+                continue;
+            }
+
+            let mut started = 0;
+            for request in reply.tasks {
+                let inputs = (self.input_locator)(&request.task);
+                let task_id = request.task.id.clone();
+
+                let submitted = self.host.handle_submit(victim, request);
+                if submitted.accepted {
+                    self.add_held_inputs(inputs)?;
+                    started += 1;
+                } else {
+                    log::debug!(
+                        "Could not run task {} stolen from peer {}: {}",
+                        task_id, victim, submitted.reason.unwrap_or_default()
+                    );
+                    // Otherwise the task is queued again once its lease expires
+                    if let Err(e) = self.return_task(&victim, &task_id).await {
+                        log::debug!("Failed to return task {} to peer {}: {}", task_id, victim, e);
+                    }
+                }
+            }
+
+            return Ok(started);
+        }
+
+        Ok(0)
+    }
+
+    /// Hands a stolen task back to the victim, so it is queued again right away.
+    async fn return_task(&self, victim: &PeerId, task_id: &str) -> Result<(), Error> {
+        let returned = ReturnedTask { task_id: task_id.to_string() };
+        let message = Message::new(
+            TASK_RETURN_MESSAGE.to_string(),
+            serde_json::to_vec(&returned).map_err(Error::Serialization)?,
+        ).to_bytes()?;
+
+        self.sender.send_message(victim, &message).await?;
+        Ok(())
+    }
+
+    /// Hands over a batch of queued tasks to an idle peer.
+    async fn handle_steal(&self, thief: PeerId, request: StealRequest) -> Result<StealReply, Error> {
+        let queue_length = self.scheduler.pending_task_count().await;
+        let batch_size = self.policy.batch_size(queue_length, request.advertisement.free_slots);
+        if batch_size == 0 {
+            return Ok(StealReply { tasks: Vec::new() });
+        }
+
+        // Prefer the tasks whose inputs the thief already holds
+        let held_inputs: HashSet<String> = request.held_inputs.into_iter().collect();
+        let input_locator = self.input_locator.clone();
+        let stolen = self.scheduler.take_pending(batch_size, |task| {
+            input_locator(task).iter().filter(|hash| held_inputs.contains(*hash)).count() as f64
+        }).await;
+
+        let mut tasks = Vec::with_capacity(stolen.len());
+        for task in stolen {
+            let (request, lease) = match self.client.grant_lease(&task, thief) {
+                Ok(granted) => granted,
+                Err(e) => {
+                    // The task stays with this node; the others are still handed over
+                    log::warn!("Failed to lease task {} to peer {}: {}", task.id, thief, e);
+                    let _ = self.scheduler.schedule_task(task).await;
+                    continue;
+                },
+            };
+            tasks.push(request);
+
+            let scheduler = self.scheduler.clone();
+            let client = self.client.clone();
+            tokio::spawn(async move {
+                match client.wait_for_lease(lease).await {
+                    Ok(LeaseOutcome::Finished(result)) => scheduler.finish(&task, &result).await,
+                    Ok(LeaseOutcome::Expired) | Err(_) => {
+                        // The thief is gone or handed the task back, so it goes back to the queue
+                        let _ = scheduler.schedule_task(task).await;
+                    },
+                }
+            });
+        }
+
+        Ok(StealReply { tasks })
+    }
+
+    fn lock_held_inputs(&self) -> Result<std::sync::MutexGuard<'_, HeldInputs>, Error> {
+        self.held_inputs.lock()
+            .map_err(|_| Error::Task("Failed to lock held inputs".to_string()))
+    }
+}
+
+/// The most recently added inputs held locally, by content hash.
+struct HeldInputs {
+    hashes: HashSet<String>,
+    order: VecDeque<String>,
+    max: usize,
+}
+
+impl HeldInputs {
+    fn new(max: usize) -> Self {
+        Self {
+            hashes: HashSet::new(),
+            order: VecDeque::new(),
+            max,
+        }
+    }
+
+    /// Records a held input, forgetting the oldest ones beyond the maximum.
+    fn insert(&mut self, hash: String) {
+        if self.hashes.insert(hash.clone()) {
+            self.order.push_back(hash);
+        }
+
+        while self.order.len() > self.max {
+            if let Some(oldest) = self.order.pop_front() {
+                self.hashes.remove(&oldest);
+            }
+        }
+    }
+
+    fn remove(&mut self, hash: &str) {
+        if self.hashes.remove(hash) {
+            self.order.retain(|held| held != hash);
+        }
+    }
+}
+
+#[async_trait]
+impl MessageHandler for WorkStealer {
+    async fn handle_message(&self, peer_id: &PeerId, message: &[u8]) -> Result<Vec<u8>, Error> {
+        let decoded = Message::from_bytes(message)?;
+
+        match decoded.message_type.as_str() {
+            TASK_STEAL_MESSAGE => {
+                let request = serde_json::from_slice(&decoded.payload).map_err(Error::Serialization)?;
+                let reply = self.handle_steal(*peer_id, request).await?;
+
+                serde_json::to_vec(&reply).map_err(Error::Serialization)
+            },
+            TASK_RETURN_MESSAGE => {
+                let returned: ReturnedTask = serde_json::from_slice(&decoded.payload).map_err(Error::Serialization)?;
+                // Only the peer holding the lease can return the task
+                self.client.return_lease(&returned.task_id, peer_id)?;
+
+                Ok(Vec::new())
+            },
+            TASK_SUBMIT_MESSAGE | TASK_CANCEL_MESSAGE => self.host.handle_message(peer_id, message).await,
+            _ => self.client.handle_message(peer_id, message).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ResourceMode;
+    use crate::resources::allocation::ResourceAllocator;
+    use crate::tasks::cancel::CancellationToken;
+    use crate::tasks::events::ProgressReporter;
+    use crate::tasks::remote::RemoteConfig;
+    use crate::tasks::testing::{resources, Handlers, Loopback};
+    use crate::tasks::{TaskExecutor, TaskResourceType, TaskResult, TaskStatus};
+    use std::time::Duration;
+
+    struct EchoExecutor;
+
+    #[async_trait]
+    impl TaskExecutor for EchoExecutor {
+        async fn execute(&self, task: &Task, _progress: &ProgressReporter, _cancel: &CancellationToken) -> Result<TaskResult, Error> {
+            Ok(TaskResult::completed(task.data.clone(), Duration::ZERO, 0))
+        }
+    }
+
+    fn node(
+        peer_id: PeerId,
+        handlers: &Handlers,
+    ) -> (Arc<TaskScheduler>, Arc<RemoteTaskClient>, Arc<WorkStealer>) {
+        let sender = Arc::new(Loopback::new(peer_id, handlers));
+
+        let mut scheduler = TaskScheduler::new(2, Duration::from_secs(5));
+        scheduler.set_cpu_executor(Arc::new(EchoExecutor));
+        let scheduler = Arc::new(scheduler);
+
+        let client = Arc::new(RemoteTaskClient::new(sender.clone(), RemoteConfig::default()));
+        let allocator = ResourceAllocator::new(ResourceMode::HighPerformance, None, resources(4));
+        let host = Arc::new(RemoteTaskHost::new(scheduler.clone(), Arc::new(allocator), sender.clone(), 2));
+        let stealer = Arc::new(WorkStealer::new(scheduler.clone(), client.clone(), host, sender, StealPolicy::default()));
+
+        handlers.lock().unwrap().insert(peer_id, stealer.clone());
+        (scheduler, client, stealer)
+    }
+
+    #[tokio::test]
+    async fn test_idle_peer_steals_tasks_with_local_inputs() {
+        let handlers = Handlers::default();
+        let owner_id = PeerId::random();
+        let thief_id = PeerId::random();
+        let (owner, _, owner_stealer) = node(owner_id, &handlers);
+        let (_, thief_client, thief) = node(thief_id, &handlers);
+
+        let tasks: Vec<Task> = (0..6u8).map(|i| Task {
+            id: format!("queued-{}", i),
+            resource_type: TaskResourceType::Cpu,
+            data: vec![i],
+            status: TaskStatus::Pending,
+            created_at: 0,
+            completed_at: None,
+            checkpoint: None,
+            deterministic: false,
+        }).collect();
+        for task in &tasks {
+            owner.schedule_task(task.clone()).await.unwrap();
+        }
+
+        // The thief already holds the input of the oldest task
+        thief.add_held_inputs(default_inputs(&tasks[0])).unwrap();
+        thief_client.update_peer(owner_id, owner_stealer.advertisement().await.unwrap()).unwrap();
+
+        // Half of the queue, capped by the thief's two free slots
+        assert_eq!(thief.steal().await.unwrap(), 2);
+        assert_eq!(owner.pending_task_count().await, 4);
+
+        // The task with local inputs goes first, then the most recently queued one
+        for stolen in ["queued-0", "queued-5"] {
+            let mut finished = None;
+            for _ in 0..100 {
+                finished = owner.get_finished_task(stolen).await;
+                if finished.is_some() {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            assert_eq!(finished.map(|task| task.status), Some(TaskStatus::Completed));
+        }
+
+        // Peers with a short queue are not stolen from
+        let mut advertisement = owner_stealer.advertisement().await.unwrap();
+        advertisement.queue_length = 1;
+        thief_client.update_peer(owner_id, advertisement).unwrap();
+        assert_eq!(thief.steal().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_held_inputs_are_bounded() {
+        let handlers = Handlers::default();
+        let sender = Arc::new(Loopback::new(PeerId::random(), &handlers));
+        let scheduler = Arc::new(TaskScheduler::new(1, Duration::from_secs(5)));
+        let client = Arc::new(RemoteTaskClient::new(sender.clone(), RemoteConfig::default()));
+        let allocator = ResourceAllocator::new(ResourceMode::HighPerformance, None, resources(1));
+        let host = Arc::new(RemoteTaskHost::new(scheduler.clone(), Arc::new(allocator), sender.clone(), 1));
+        let mut stealer = WorkStealer::new(scheduler, client, host, sender, StealPolicy::default());
+        stealer.set_max_held_inputs(2);
+
+        stealer.add_held_inputs(["a", "b", "c"].map(String::from)).unwrap();
+        stealer.remove_held_input("b").unwrap();
+        stealer.add_held_inputs(["d".to_string()]).unwrap();
+
+        // The oldest input is forgotten first
+        let held: Vec<String> = stealer.lock_held_inputs().unwrap().order.iter().cloned().collect();
+        assert_eq!(held, vec!["c".to_string(), "d".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_rejected_stolen_tasks_are_returned_to_owner() {
+        let handlers = Handlers::default();
+        let owner_id = PeerId::random();
+        let thief_id = PeerId::random();
+        let (owner, _, owner_stealer) = node(owner_id, &handlers);
+        let (_, thief_client, thief) = node(thief_id, &handlers);
+
+        // The thief has no GPU executor, so it cannot run what it steals
+        for i in 0..6u8 {
+            owner.schedule_task(Task {
+                id: format!("gpu-{}", i),
+                resource_type: TaskResourceType::Gpu,
+                data: vec![i],
+                status: TaskStatus::Pending,
+                created_at: 0,
+                completed_at: None,
+                checkpoint: None,
+                deterministic: false,
+            }).await.unwrap();
+        }
+        thief_client.update_peer(owner_id, owner_stealer.advertisement().await.unwrap()).unwrap();
+
+        assert_eq!(thief.steal().await.unwrap(), 0);
+
+        // The tasks are queued again long before their 30 second lease expires
+        let mut pending = 0;
+        for _ in 0..100 {
+            pending = owner.pending_task_count().await;
+            if pending == 6 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(pending, 6);
+    }
+}
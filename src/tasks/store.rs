@@ -0,0 +1,251 @@
+/* Copyright 2025 Joao Guimaraes, Catp2p Project
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Persistent storage of submitted tasks.
+
+use crate::error::Error;
+use crate::storage::db::{Database, Tree};
+use crate::tasks::{current_timestamp, Task, TaskStatus};
+use std::sync::Mutex;
+
+/// Name of the database tree holding submitted tasks.
+const TASK_TREE: &str = "tasks";
+
+/// Key prefix of task records.
+const TASK_PREFIX: &str = "task/";
+
+/// Key of the next task ID to assign.
+const NEXT_ID_KEY: &str = "meta/next-id";
+
+/// Persists submitted tasks and assigns their IDs.
+pub struct TaskStore {
+    tree: Tree,
+    // Serializes ID assignment so that concurrent batches never share IDs
+    next_id: Mutex<u64>,
+}
+
+impl TaskStore {
+    /// Opens the task store in the given database.
+    pub fn new(database: &Database) -> Result<Self, Error> {
+        let tree = database.open_tree(TASK_TREE)?;
+
+        let next_id = match tree.get(NEXT_ID_KEY)? {
+            Some(bytes) => {
+                let bytes: [u8; 8] = bytes.as_slice().try_into()
+                    .map_err(|_| Error::Storage("Corrupt task ID counter".to_string()))?;
+                u64::from_be_bytes(bytes)
+            },
+            None => 1,
+        };
+
+        Ok(Self {
+            tree,
+            next_id: Mutex::new(next_id),
+        })
+    }
+
+    /// Assigns IDs to a batch of tasks and persists them in one atomic write.
+    ///
+    /// The tasks are marked as pending; their IDs are consecutive.
+    pub fn insert_batch(&self, tasks: Vec<Task>) -> Result<Vec<Task>, Error> {
+        let mut next_id = self.next_id.lock()
+            .map_err(|_| Error::Storage("Failed to lock task ID counter".to_string()))?;
+
+        let now = current_timestamp();
+        let mut entries = Vec::with_capacity(tasks.len() + 1);
+        let mut assigned = Vec::with_capacity(tasks.len());
+        let mut id = *next_id;
+        for mut task in tasks {
+            task.id = format!("task-{}", id);
+            task.status = TaskStatus::Pending;
+            task.created_at = now;
+            task.completed_at = None;
+            id += 1;
+
+            entries.push((task_key(&task.id), serde_json::to_vec(&task).map_err(Error::Serialization)?));
+            assigned.push(task);
+        }
+        entries.push((NEXT_ID_KEY.to_string(), id.to_be_bytes().to_vec()));
+
+        self.tree.put_batch(entries)?;
+        *next_id = id;
+
+        Ok(assigned)
+    }
+
+    /// Updates a stored task, e.g. with its final status.
+    pub fn update(&self, task: &Task) -> Result<(), Error> {
+        self.tree.put(task_key(&task.id), serde_json::to_vec(task).map_err(Error::Serialization)?)
+    }
+
+    /// Gets a stored task.
+    pub fn get(&self, task_id: &str) -> Result<Option<Task>, Error> {
+        self.tree.get(task_key(task_id))?
+            .map(|bytes| serde_json::from_slice(&bytes).map_err(Error::Serialization))
+            .transpose()
+    }
+
+    /// Returns the stored tasks that have not finished, e.g. to resubmit them after a restart.
+    pub fn unfinished(&self) -> Result<Vec<Task>, Error> {
+        let mut tasks = Vec::new();
+        for entry in self.tree.scan_prefix(TASK_PREFIX) {
+            let (_, bytes) = entry?;
+            let task: Task = serde_json::from_slice(&bytes).map_err(Error::Serialization)?;
+            if matches!(task.status, TaskStatus::Pending | TaskStatus::Running) {
+                tasks.push(task);
+            }
+        }
+
+        Ok(tasks)
+    }
+
+    /// Removes a stored task.
+    pub fn remove(&self, task_id: &str) -> Result<(), Error> {
+        self.tree.remove(task_key(task_id))
+    }
+}
+
+fn task_key(task_id: &str) -> String {
+    format!("{}{}", TASK_PREFIX, task_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tasks::cpu::CpuTaskExecutor;
+    use crate::tasks::scheduler::TaskScheduler;
+    use crate::tasks::{TaskManager, TaskPayload, TaskResourceType, RESULTS_BUFFER};
+    use futures::StreamExt;
+    use std::collections::HashSet;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn square_task(value: u64) -> Task {
+        Task {
+            id: String::new(),
+            resource_type: TaskResourceType::Cpu,
+            data: TaskPayload::typed("square", &value).unwrap().to_bytes().unwrap(),
+            status: TaskStatus::Pending,
+            created_at: 0,
+            completed_at: None,
+            checkpoint: None,
+            deterministic: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batch_results_are_streamed_and_persisted() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::open(dir.path()).unwrap();
+        let store = Arc::new(TaskStore::new(&database).unwrap());
+
+        let executor = CpuTaskExecutor::new(2).unwrap();
+        executor.registry().register_typed("square", |value: u64| Ok(value * value)).unwrap();
+        let mut scheduler = TaskScheduler::new(2, Duration::from_secs(5));
+        scheduler.set_cpu_executor(Arc::new(executor));
+        scheduler.set_task_store(store.clone());
+
+        let manager = TaskManager::new_with_scheduler(Arc::new(scheduler));
+        let mut results = manager.results().await.unwrap();
+        let mut second = manager.results().await.unwrap();
+
+        let tasks: Vec<Task> = (0..20u64).map(square_task).collect();
+        let task_ids = manager.submit_batch(tasks).await.unwrap();
+        assert_eq!(task_ids.first().map(String::as_str), Some("task-1"));
+        assert_eq!(task_ids.last().map(String::as_str), Some("task-20"));
+
+        let mut finished = HashSet::new();
+        while finished.len() < task_ids.len() {
+            let (task_id, result) = results.next().await.unwrap();
+            assert!(result.is_ok());
+            finished.insert(task_id);
+        }
+        assert_eq!(finished, task_ids.iter().cloned().collect());
+        // A second subscriber receives the results too
+        for _ in 0..task_ids.len() {
+            assert!(second.next().await.unwrap().1.is_ok());
+        }
+
+        assert_eq!(manager.get_task_status("task-7").await.unwrap(), TaskStatus::Completed);
+        // Statuses are persisted before the results are published
+        assert!(store.unfinished().unwrap().is_empty());
+
+        // IDs continue after reopening the store
+        drop(manager);
+        drop(store);
+        let store = TaskStore::new(&database).unwrap();
+        assert_eq!(store.get("task-20").unwrap().unwrap().status, TaskStatus::Completed);
+        let next = store.insert_batch(vec![store.get("task-1").unwrap().unwrap()]).unwrap();
+        assert_eq!(next[0].id, "task-21");
+    }
+
+    #[tokio::test]
+    async fn test_slow_result_consumers_hold_back_dispatch() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::open(dir.path()).unwrap();
+        let store = Arc::new(TaskStore::new(&database).unwrap());
+
+        let executor = CpuTaskExecutor::new(2).unwrap();
+        executor.registry().register_typed("square", |value: u64| Ok(value * value)).unwrap();
+        let mut scheduler = TaskScheduler::new(2, Duration::from_secs(5));
+        scheduler.set_cpu_executor(Arc::new(executor));
+        scheduler.set_task_store(store.clone());
+
+        let manager = TaskManager::new_with_scheduler(Arc::new(scheduler));
+        let mut results = manager.results().await.unwrap();
+
+        let count = RESULTS_BUFFER as u64 + 50;
+        let task_ids = manager.submit_batch((0..count).map(square_task).collect()).await.unwrap();
+
+        // The buffer fills up, and each slot holds a finished task until it is read
+        let stalled = task_ids.len() - RESULTS_BUFFER - 2;
+        while store.unfinished().unwrap().len() > stalled {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(store.unfinished().unwrap().len(), stalled);
+
+        let mut finished = HashSet::new();
+        while finished.len() < task_ids.len() {
+            let (task_id, result) = results.next().await.unwrap();
+            assert!(result.is_ok());
+            finished.insert(task_id);
+        }
+        assert_eq!(finished, task_ids.iter().cloned().collect());
+        assert!(store.unfinished().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_pending_task_is_persisted() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::open(dir.path()).unwrap();
+        let store = Arc::new(TaskStore::new(&database).unwrap());
+
+        let mut scheduler = TaskScheduler::new(1, Duration::from_secs(5));
+        scheduler.set_cpu_executor(Arc::new(CpuTaskExecutor::new(1).unwrap()));
+        scheduler.set_task_store(store.clone());
+        // Keeps submitted tasks queued
+        scheduler.set_paused(true);
+
+        let manager = TaskManager::new_with_scheduler(Arc::new(scheduler));
+        let task_id = manager.submit_task(square_task(3)).await.unwrap();
+        assert_eq!(store.unfinished().unwrap().len(), 1);
+
+        manager.cancel_task(&task_id).await.unwrap();
+        assert!(store.unfinished().unwrap().is_empty());
+        assert_eq!(store.get(&task_id).unwrap().unwrap().status, TaskStatus::Cancelled);
+        assert_eq!(manager.resubmit_unfinished().await.unwrap(), 0);
+    }
+}
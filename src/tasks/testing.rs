@@ -0,0 +1,67 @@
+/* Copyright 2025 Joao Guimaraes, Catp2p Project
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Helpers shared by the tests of tasks exchanged between peers.
+
+use crate::error::Error;
+use crate::network::protocol::{MessageHandler, MessageSender};
+use crate::resources::SystemResources;
+use async_trait::async_trait;
+use libp2p::PeerId;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// The message handlers of every peer, by peer ID.
+pub(crate) type Handlers = Arc<Mutex<HashMap<PeerId, Arc<dyn MessageHandler + Send + Sync>>>>;
+
+/// Delivers messages directly to the handlers registered for each peer.
+pub(crate) struct Loopback {
+    local_peer_id: PeerId,
+    handlers: Handlers,
+}
+
+impl Loopback {
+    /// Creates a sender for the given peer.
+    pub(crate) fn new(local_peer_id: PeerId, handlers: &Handlers) -> Self {
+        Self {
+            local_peer_id,
+            handlers: handlers.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl MessageSender for Loopback {
+    async fn send_message(&self, peer_id: &PeerId, message: &[u8]) -> Result<Vec<u8>, Error> {
+        let handler = self.handlers.lock().unwrap().get(peer_id).cloned()
+            .ok_or_else(|| Error::Network(format!("Unknown peer {}", peer_id)))?;
+        handler.handle_message(&self.local_peer_id, message).await
+    }
+}
+
+/// Resources of a peer with the given number of cores and plenty of memory and disk.
+pub(crate) fn resources(cpu_cores: u32) -> SystemResources {
+    SystemResources {
+        cpu_usage: 0.0,
+        cpu_cores,
+        total_memory: 1 << 34,
+        available_memory: 1 << 33,
+        total_disk: 1 << 40,
+        available_disk: 1 << 39,
+        gpus: Vec::new(),
+        network_interfaces: Vec::new(),
+        numa_nodes: Vec::new(),
+    }
+}
@@ -0,0 +1,414 @@
+/* Copyright 2025 Joao Guimaraes, Catp2p Project
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Verification of results returned by untrusted peers.
+//!
+//! A task can be run redundantly on several distinct peers, accepting the result
+//! that a quorum of them agree on, or spot-checked by re-running a random sample of
+//! tasks locally. Peers are only awarded points for results that were verified, and
+//! peers whose results disagree are reported to the scoring system. Points are a
+//! fixed amount per verified task, as the resources a peer reports cannot be trusted.
+
+use crate::error::Error;
+use crate::scoring::ScoringSystem;
+use crate::tasks::remote::RemoteTaskClient;
+use crate::tasks::scheduler::TaskScheduler;
+use crate::tasks::{content_hash, ExitReason, Task, TaskResult};
+use futures::stream::{FuturesUnordered, StreamExt};
+use libp2p::PeerId;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// How the result of a task is verified.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum VerificationMode {
+    /// The result is not verified.
+    None,
+    /// The task runs on `replicas` distinct peers, and the result is accepted once
+    /// at least `quorum` of them return the same result. Replicas still running
+    /// then are cancelled.
+    Redundant {
+        /// Number of peers to run the task on.
+        replicas: usize,
+        /// Number of matching results required, more than half of `replicas`.
+        quorum: usize,
+    },
+    /// The task runs on one peer, and a random fraction of tasks is re-run locally.
+    SpotCheck {
+        /// Probability (0.0 - 1.0) that a task is re-run locally.
+        sample_rate: f64,
+    },
+}
+
+impl VerificationMode {
+    /// Creates a redundant mode with a simple majority quorum.
+    pub fn majority(replicas: usize) -> Self {
+        Self::Redundant {
+            replicas,
+            quorum: replicas / 2 + 1,
+        }
+    }
+}
+
+/// A task result together with how it was verified.
+#[derive(Debug, Clone)]
+pub struct VerifiedResult {
+    /// The accepted result.
+    pub result: TaskResult,
+    /// Content hash of the accepted output.
+    pub output_hash: String,
+    /// Whether the result was verified.
+    pub verified: bool,
+    /// Peers whose result matched the accepted result.
+    pub agreeing_peers: Vec<PeerId>,
+    /// Peers whose result differed from the accepted result.
+    pub disagreeing_peers: Vec<PeerId>,
+}
+
+/// Runs tasks on peers and verifies their results.
+pub struct ResultVerifier {
+    scheduler: Arc<TaskScheduler>,
+    remote: Arc<RemoteTaskClient>,
+    scoring: Arc<ScoringSystem>,
+}
+
+impl ResultVerifier {
+    /// Creates a new ResultVerifier.
+    pub fn new(scheduler: Arc<TaskScheduler>, remote: Arc<RemoteTaskClient>, scoring: Arc<ScoringSystem>) -> Self {
+        Self {
+            scheduler,
+            remote,
+            scoring,
+        }
+    }
+
+    /// Executes a task and verifies its result according to the given mode.
+    pub async fn execute_verified(&self, task: &Task, mode: VerificationMode) -> Result<VerifiedResult, Error> {
+        match mode {
+            VerificationMode::None => {
+                let result = self.scheduler.execute_task(task).await?;
+                Ok(VerifiedResult {
+                    output_hash: content_hash(&result.output),
+                    result,
+                    verified: false,
+                    agreeing_peers: Vec::new(),
+                    disagreeing_peers: Vec::new(),
+                })
+            },
+            VerificationMode::Redundant { replicas, quorum } => self.execute_redundant(task, replicas, quorum).await,
+            VerificationMode::SpotCheck { sample_rate } => self.execute_spot_check(task, sample_rate).await,
+        }
+    }
+
+    async fn execute_redundant(&self, task: &Task, replicas: usize, quorum: usize) -> Result<VerifiedResult, Error> {
+        // A majority quorum guarantees that at most one result can reach it
+        if quorum <= replicas / 2 || quorum > replicas {
+            return Err(Error::Task(format!(
+                "Invalid quorum {} for {} replicas (must be a majority)", quorum, replicas
+            )));
+        }
+
+        let peers: Vec<PeerId> = self.remote.rank_peers(task)?.into_iter().take(replicas).collect();
+        if peers.len() < quorum {
+            return Err(Error::Task(format!(
+                "Task {} needs a quorum of {} but only {} peers are available",
+                task.id, quorum, peers.len()
+            )));
+        }
+
+        let mut running: FuturesUnordered<_> = peers.into_iter()
+            .map(|peer_id| async move { (peer_id, self.remote.offload_to(task, peer_id).await) })
+            .collect();
+
+        // Group successful results by output hash until one group reaches the quorum
+        let mut groups: HashMap<String, Vec<(PeerId, TaskResult)>> = HashMap::new();
+        let mut responded = Vec::new();
+        let mut accepted = None;
+        while let Some((peer_id, result)) = running.next().await {
+            if let Ok(result) = result {
+                responded.push(peer_id);
+                if result.exit_reason == ExitReason::Completed {
+                    let output_hash = content_hash(&result.output);
+                    let group = groups.entry(output_hash.clone()).or_default();
+                    group.push((peer_id, result));
+                    if group.len() >= quorum {
+                        accepted = Some(output_hash);
+                        break;
+                    }
+                }
+            }
+
+            let largest = groups.values().map(Vec::len).max().unwrap_or(0);
+            if largest + running.len() < quorum {
+                break;
+            }
+        }
+
+        let outstanding = !running.is_empty();
+        drop(running);
+        if outstanding {
+            self.remote.cancel(&task.id).await?;
+        }
+
+        let (output_hash, agreeing) = accepted
+            .and_then(|output_hash| groups.remove(&output_hash).map(|group| (output_hash, group)))
+            .ok_or_else(|| Error::Task(format!("No quorum of {} reached for task {}", quorum, task.id)))?;
+
+        let agreeing_peers: Vec<PeerId> = agreeing.iter().map(|(peer_id, _)| *peer_id).collect();
+        let disagreeing_peers: Vec<PeerId> = responded.into_iter()
+            .filter(|peer_id| !agreeing_peers.contains(peer_id))
+            .collect();
+
+        for peer_id in &agreeing_peers {
+            self.award_points(peer_id)?;
+        }
+        for peer_id in &disagreeing_peers {
+            self.report_disagreement(peer_id)?;
+        }
+
+        let result = agreeing.into_iter()
+            .map(|(_, result)| result)
+            .next()
+            .ok_or_else(|| Error::Task(format!("No result for task {}", task.id)))?;
+
+        Ok(VerifiedResult {
+            result,
+            output_hash,
+            verified: true,
+            agreeing_peers,
+            disagreeing_peers,
+        })
+    }
+
+    async fn execute_spot_check(&self, task: &Task, sample_rate: f64) -> Result<VerifiedResult, Error> {
+        let (peer_id, remote_result) = self.remote.offload_with_peer(task).await?;
+        let remote_hash = content_hash(&remote_result.output);
+
+        // Tasks that cannot run locally, e.g. GPU tasks offloaded for lack of a GPU, are not checked
+        let checked = self.scheduler.has_executor_for(task.resource_type)
+            && rand::thread_rng().gen_bool(sample_rate.clamp(0.0, 1.0));
+        if !checked {
+            // Unchecked results are accepted, but earn no points
+            return Ok(VerifiedResult {
+                result: remote_result,
+                output_hash: remote_hash,
+                verified: false,
+                agreeing_peers: Vec::new(),
+                disagreeing_peers: Vec::new(),
+            });
+        }
+
+        let local_result = self.scheduler.execute_locally(task).await?;
+        let local_hash = content_hash(&local_result.output);
+
+        if local_hash == remote_hash && remote_result.exit_reason == local_result.exit_reason {
+            self.award_points(&peer_id)?;
+
+            Ok(VerifiedResult {
+                result: remote_result,
+                output_hash: remote_hash,
+                verified: true,
+                agreeing_peers: vec![peer_id],
+                disagreeing_peers: Vec::new(),
+            })
+        } else {
+            // The local result is trusted, so it replaces the peer's result
+            self.report_disagreement(&peer_id)?;
+
+            Ok(VerifiedResult {
+                result: local_result,
+                output_hash: local_hash,
+                verified: true,
+                agreeing_peers: Vec::new(),
+                disagreeing_peers: vec![peer_id],
+            })
+        }
+    }
+
+    fn award_points(&self, peer_id: &PeerId) -> Result<u64, Error> {
+        self.scoring.record_verified_task(&peer_id.to_string())
+    }
+
+    fn report_disagreement(&self, peer_id: &PeerId) -> Result<(), Error> {
+        self.scoring.record_verification_failure(&peer_id.to_string())?;
+        self.remote.report_misbehavior(peer_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ResourceMode;
+    use crate::resources::allocation::ResourceAllocator;
+    use crate::tasks::cancel::CancellationToken;
+    use crate::tasks::events::ProgressReporter;
+    use crate::tasks::remote::{RemoteConfig, RemoteTaskHost};
+    use crate::tasks::testing::{resources, Handlers, Loopback};
+    use crate::tasks::{TaskExecutor, TaskResourceType, TaskStatus};
+    use async_trait::async_trait;
+    use std::time::Duration;
+
+    /// Returns a fixed output after a delay, or fails without one. Every result
+    /// claims ten hours of CPU time.
+    struct ScriptedExecutor {
+        output: Option<&'static [u8]>,
+        delay: Duration,
+    }
+
+    impl ScriptedExecutor {
+        fn honest() -> Self {
+            Self { output: Some(b"correct"), delay: Duration::from_millis(20) }
+        }
+
+        fn forger() -> Self {
+            Self { output: Some(b"forged"), delay: Duration::ZERO }
+        }
+
+        fn failing() -> Self {
+            Self { output: None, delay: Duration::ZERO }
+        }
+
+        fn stalled() -> Self {
+            Self { output: Some(b"correct"), delay: Duration::from_secs(3600) }
+        }
+    }
+
+    #[async_trait]
+    impl TaskExecutor for ScriptedExecutor {
+        async fn execute(&self, task: &Task, _progress: &ProgressReporter, _cancel: &CancellationToken) -> Result<TaskResult, Error> {
+            tokio::time::sleep(self.delay).await;
+            let output = self.output.ok_or_else(|| Error::Task(format!("{} failed", task.id)))?;
+            Ok(TaskResult::completed(output.to_vec(), Duration::from_secs(36_000), 0))
+        }
+    }
+
+    /// Creates a verifier with one peer per worker, and optionally a local executor.
+    fn network(workers: Vec<ScriptedExecutor>, local: Option<ScriptedExecutor>) -> (ResultVerifier, Arc<ScoringSystem>, Vec<PeerId>) {
+        let handlers = Handlers::default();
+        let origin_id = PeerId::random();
+        let client = Arc::new(RemoteTaskClient::new(Arc::new(Loopback::new(origin_id, &handlers)), RemoteConfig::default()));
+        handlers.lock().unwrap().insert(origin_id, client.clone());
+
+        let peer_ids = workers.into_iter()
+            .map(|executor| {
+                let peer_id = PeerId::random();
+                let mut scheduler = TaskScheduler::new(4, Duration::from_secs(3600));
+                scheduler.set_cpu_executor(Arc::new(executor));
+                let allocator = ResourceAllocator::new(ResourceMode::HighPerformance, None, resources(8));
+                let host = Arc::new(RemoteTaskHost::new(
+                    Arc::new(scheduler),
+                    Arc::new(allocator),
+                    Arc::new(Loopback::new(peer_id, &handlers)),
+                    4,
+                ));
+                client.update_peer(peer_id, host.advertisement().unwrap()).unwrap();
+                handlers.lock().unwrap().insert(peer_id, host);
+                peer_id
+            })
+            .collect();
+
+        let mut scheduler = TaskScheduler::new(4, Duration::from_secs(3600));
+        if let Some(local) = local {
+            scheduler.set_cpu_executor(Arc::new(local));
+        }
+        let scoring = Arc::new(ScoringSystem::new());
+        (ResultVerifier::new(Arc::new(scheduler), client, scoring.clone()), scoring, peer_ids)
+    }
+
+    fn task() -> Task {
+        Task {
+            id: "verified-1".to_string(),
+            resource_type: TaskResourceType::Cpu,
+            data: Vec::new(),
+            status: TaskStatus::Pending,
+            created_at: 0,
+            completed_at: None,
+            checkpoint: None,
+            deterministic: true,
+        }
+    }
+
+    fn contribution(scoring: &ScoringSystem, peer_id: &PeerId) -> (u64, u64) {
+        let contribution = scoring.get_contribution(&peer_id.to_string()).unwrap().unwrap_or_default();
+        (contribution.points, contribution.verification_failures)
+    }
+
+    #[tokio::test]
+    async fn test_redundant_results_need_a_quorum() {
+        // The forged result arrives first, but the honest peers form the quorum
+        let (verifier, scoring, peers) = network(
+            vec![ScriptedExecutor::honest(), ScriptedExecutor::honest(), ScriptedExecutor::forger()],
+            None,
+        );
+        let verified = verifier.execute_verified(&task(), VerificationMode::majority(3)).await.unwrap();
+        assert!(verified.verified);
+        assert_eq!(verified.result.output, b"correct");
+        assert_eq!(verified.disagreeing_peers, vec![peers[2]]);
+        // Points are fixed per task, however much CPU time the peer claims
+        assert_eq!(contribution(&scoring, &peers[0]), (10, 0));
+        assert_eq!(contribution(&scoring, &peers[1]), (10, 0));
+        assert_eq!(contribution(&scoring, &peers[2]), (0, 1));
+
+        // Replicas still running once the quorum is reached are cancelled
+        let (verifier, scoring, peers) = network(
+            vec![ScriptedExecutor::honest(), ScriptedExecutor::honest(), ScriptedExecutor::stalled()],
+            None,
+        );
+        let verified = tokio::time::timeout(Duration::from_secs(5), verifier.execute_verified(&task(), VerificationMode::majority(3)))
+            .await
+            .expect("verification should not wait for the stalled replica")
+            .unwrap();
+        assert_eq!(verified.agreeing_peers.len(), 2);
+        assert!(verified.disagreeing_peers.is_empty());
+        assert_eq!(contribution(&scoring, &peers[2]), (0, 0));
+
+        // Without a quorum the task fails and nobody is credited
+        let (verifier, scoring, peers) = network(
+            vec![ScriptedExecutor::honest(), ScriptedExecutor::forger(), ScriptedExecutor::failing()],
+            None,
+        );
+        assert!(verifier.execute_verified(&task(), VerificationMode::majority(3)).await.is_err());
+        assert_eq!(contribution(&scoring, &peers[0]), (0, 0));
+
+        // Quorums that two results could reach at once are rejected
+        let tie = VerificationMode::Redundant { replicas: 4, quorum: 2 };
+        assert!(verifier.execute_verified(&task(), tie).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_spot_check_replaces_mismatching_result() {
+        let always = VerificationMode::SpotCheck { sample_rate: 1.0 };
+        let (verifier, scoring, peers) = network(vec![ScriptedExecutor::forger()], Some(ScriptedExecutor::honest()));
+        let verified = verifier.execute_verified(&task(), always).await.unwrap();
+        assert!(verified.verified);
+        assert_eq!(verified.result.output, b"correct");
+        assert_eq!(verified.disagreeing_peers, vec![peers[0]]);
+        assert_eq!(contribution(&scoring, &peers[0]), (0, 1));
+
+        let (verifier, scoring, peers) = network(vec![ScriptedExecutor::honest()], Some(ScriptedExecutor::honest()));
+        let verified = verifier.execute_verified(&task(), always).await.unwrap();
+        assert_eq!(verified.agreeing_peers, vec![peers[0]]);
+        assert_eq!(contribution(&scoring, &peers[0]), (10, 0));
+
+        // Tasks that cannot run locally are accepted unchecked
+        let (verifier, scoring, peers) = network(vec![ScriptedExecutor::forger()], None);
+        let verified = verifier.execute_verified(&task(), always).await.unwrap();
+        assert!(!verified.verified);
+        assert_eq!(verified.result.output, b"forged");
+        assert_eq!(contribution(&scoring, &peers[0]), (0, 0));
+    }
+}
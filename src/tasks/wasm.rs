@@ -0,0 +1,473 @@
+/* Copyright 2025 Joao Guimaraes, Catp2p Project
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Sandboxed WebAssembly task execution functionality.
+//!
+//! Tasks run as WASI (preview 1) command modules with fuel metering, a memory cap,
+//! a wall-clock timeout and no filesystem or network access. The task input is
+//! provided on stdin and the task output is whatever the module writes to stdout.
+
+use crate::config::ResourceLimits;
+use crate::error::Error;
+use crate::resources::accounting::ThreadUsageMeter;
+use crate::tasks::cancel::CancellationToken;
+use crate::tasks::events::ProgressReporter;
+use crate::tasks::{content_hash, ExitReason, Task, TaskExecutor, TaskResult};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use wasmtime::{Config, Engine, Linker, Module, ResourceLimiter, Store, Trap, UpdateDeadline};
+use wasmtime_wasi::pipe::{MemoryInputPipe, MemoryOutputPipe};
+use wasmtime_wasi::preview1::{self, WasiP1Ctx};
+use wasmtime_wasi::{I32Exit, WasiCtxBuilder};
+
+/// How often the engine epoch advances, which bounds the timeout granularity.
+const EPOCH_TICK: Duration = Duration::from_millis(10);
+
+/// Default number of compiled modules kept in the cache.
+const DEFAULT_MAX_CACHED_MODULES: usize = 64;
+
+/// Sandbox limits for WebAssembly tasks.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WasmConfig {
+    /// Fuel (roughly, instructions) available to each task.
+    pub fuel: u64,
+    /// Maximum linear memory per task in bytes.
+    pub memory_limit: u64,
+    /// Wall-clock timeout per task.
+    pub timeout: Duration,
+    /// Maximum number of bytes a task may write to stdout or stderr.
+    pub max_output_bytes: usize,
+}
+
+impl WasmConfig {
+    /// Creates a config whose memory cap is taken from the given resource limits.
+    pub fn from_limits(limits: &ResourceLimits, timeout: Duration) -> Self {
+        Self {
+            memory_limit: limits.memory_limit,
+            timeout,
+            ..Self::default()
+        }
+    }
+}
+
+impl Default for WasmConfig {
+    fn default() -> Self {
+        Self {
+            fuel: 10_000_000_000,
+            memory_limit: 256 * 1024 * 1024, // 256 MB
+            timeout: Duration::from_secs(60),
+            max_output_bytes: 16 * 1024 * 1024, // 16 MB
+        }
+    }
+}
+
+/// A reference to the module a WebAssembly task should run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum WasmModuleSource {
+    /// The module bytes (binary or text format).
+    Inline(Vec<u8>),
+    /// The hash of a module previously loaded into the executor's cache.
+    Hash(String),
+}
+
+/// The contents of `Task.data` for a WebAssembly task.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WasmTask {
+    /// The module to run.
+    pub module: WasmModuleSource,
+    /// Command-line arguments passed to the module.
+    pub args: Vec<String>,
+    /// Bytes provided to the module on stdin.
+    pub input: Vec<u8>,
+}
+
+impl WasmTask {
+    /// Serializes the task to bytes suitable for `Task.data`.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        serde_json::to_vec(self).map_err(Error::Serialization)
+    }
+
+    /// Deserializes a task from `Task.data`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        serde_json::from_slice(bytes).map_err(Error::Serialization)
+    }
+}
+
+/// Enforces the memory cap and records peak memory usage.
+struct MemoryLimiter {
+    limit: usize,
+    peak: usize,
+}
+
+impl ResourceLimiter for MemoryLimiter {
+    fn memory_growing(&mut self, _current: usize, desired: usize, _maximum: Option<usize>) -> wasmtime::Result<bool> {
+        if desired > self.limit {
+            return Ok(false);
+        }
+        self.peak = self.peak.max(desired);
+        Ok(true)
+    }
+
+    fn table_growing(&mut self, _current: u32, desired: u32, _maximum: Option<u32>) -> wasmtime::Result<bool> {
+        Ok(desired <= 100_000)
+    }
+}
+
+/// Compiled modules by content hash, evicting the least recently used.
+struct ModuleCache {
+    modules: HashMap<String, (Module, u64)>,
+    capacity: usize,
+    clock: u64,
+}
+
+impl ModuleCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            modules: HashMap::new(),
+            capacity,
+            clock: 0,
+        }
+    }
+
+    fn get(&mut self, hash: &str) -> Option<Module> {
+        self.clock += 1;
+        let clock = self.clock;
+        self.modules.get_mut(hash).map(|(module, last_used)| {
+            *last_used = clock;
+            module.clone()
+        })
+    }
+
+    fn insert(&mut self, hash: String, module: Module) {
+        self.clock += 1;
+        self.modules.insert(hash, (module, self.clock));
+        self.evict();
+    }
+
+    fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    fn evict(&mut self) {
+        while self.modules.len() > self.capacity {
+            let oldest = self.modules.iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(hash, _)| hash.clone());
+            match oldest {
+                Some(hash) => self.modules.remove(&hash),
+                None => break,
+            };
+        }
+    }
+}
+
+struct WasmState {
+    wasi: WasiP1Ctx,
+    limiter: MemoryLimiter,
+}
+
+/// A task executor that runs untrusted WebAssembly modules in a sandbox.
+pub struct WasmTaskExecutor {
+    engine: Engine,
+    linker: Arc<Linker<WasmState>>,
+    config: WasmConfig,
+    modules: Arc<Mutex<ModuleCache>>,
+    ticker_running: Arc<AtomicBool>,
+}
+
+impl WasmTaskExecutor {
+    /// Creates a new WasmTaskExecutor with the given sandbox limits.
+    pub fn new(config: WasmConfig) -> Result<Self, Error> {
+        let mut engine_config = Config::new();
+        engine_config.consume_fuel(true);
+        engine_config.epoch_interruption(true);
+
+        let engine = Engine::new(&engine_config)
+            .map_err(|e| Error::Task(format!("Failed to create WebAssembly engine: {}", e)))?;
+
+        let mut linker = Linker::new(&engine);
+        preview1::add_to_linker_sync(&mut linker, |state: &mut WasmState| &mut state.wasi)
+            .map_err(|e| Error::Task(format!("Failed to link WASI: {}", e)))?;
+
+        // Advance the epoch on a fixed tick so each store can express its own deadline
+        let ticker_running = Arc::new(AtomicBool::new(true));
+        let ticker_engine = engine.clone();
+        let running = ticker_running.clone();
+        std::thread::Builder::new()
+            .name("catp2p-wasm-epoch".to_string())
+            .spawn(move || {
+                while running.load(Ordering::Relaxed) {
+                    std::thread::sleep(EPOCH_TICK);
+                    ticker_engine.increment_epoch();
+                }
+            })
+            .map_err(|e| Error::Task(format!("Failed to start epoch ticker: {}", e)))?;
+
+        Ok(Self {
+            engine,
+            linker: Arc::new(linker),
+            config,
+            modules: Arc::new(Mutex::new(ModuleCache::new(DEFAULT_MAX_CACHED_MODULES))),
+            ticker_running,
+        })
+    }
+
+    /// Compiles a module and adds it to the cache, returning its hash.
+    pub fn load_module(&self, bytes: &[u8]) -> Result<String, Error> {
+        let hash = content_hash(bytes);
+        get_or_compile(&self.engine, &self.modules, &hash, Some(bytes))?;
+        Ok(hash)
+    }
+
+    /// Returns whether a module with the given hash is cached.
+    pub fn has_module(&self, hash: &str) -> bool {
+        self.modules.lock()
+            .map(|modules| modules.modules.contains_key(hash))
+            .unwrap_or(false)
+    }
+
+    /// Sets the number of compiled modules kept in the cache, evicting the least
+    /// recently used ones beyond it.
+    pub fn set_max_cached_modules(&self, max_cached_modules: usize) -> Result<(), Error> {
+        self.modules.lock()
+            .map_err(|_| Error::Task("Failed to lock module cache".to_string()))?
+            .set_capacity(max_cached_modules);
+
+        Ok(())
+    }
+}
+
+impl Drop for WasmTaskExecutor {
+    fn drop(&mut self) {
+        self.ticker_running.store(false, Ordering::Relaxed);
+    }
+}
+
+#[async_trait]
+impl TaskExecutor for WasmTaskExecutor {
+    async fn execute(
+        &self,
+        task: &Task,
+        _progress: &ProgressReporter,
+        cancel: &CancellationToken,
+    ) -> Result<TaskResult, Error> {
+        let wasm_task = WasmTask::from_bytes(&task.data)?;
+
+        let engine = self.engine.clone();
+        let linker = self.linker.clone();
+        let modules = self.modules.clone();
+        let config = self.config.clone();
+        let task_id = task.id.clone();
+        let cancel = cancel.clone();
+
+        // Compiling and running modules blocks, so keep both off the async runtime threads
+        tokio::task::spawn_blocking(move || {
+            let module = match &wasm_task.module {
+                WasmModuleSource::Inline(bytes) => get_or_compile(&engine, &modules, &content_hash(bytes), Some(bytes))?,
+                WasmModuleSource::Hash(hash) => get_or_compile(&engine, &modules, hash, None)?,
+            };
+            run_module(&engine, &linker, &module, &config, wasm_task, &task_id, &cancel)
+        })
+        .await
+        .map_err(|e| Error::Task(format!("WebAssembly task failed to join: {}", e)))?
+    }
+}
+
+/// Gets a module from the cache, compiling and caching it if bytes are available.
+fn get_or_compile(
+    engine: &Engine,
+    modules: &Mutex<ModuleCache>,
+    hash: &str,
+    bytes: Option<&[u8]>,
+) -> Result<Module, Error> {
+    {
+        let mut modules = modules.lock()
+            .map_err(|_| Error::Task("Failed to lock module cache".to_string()))?;
+        if let Some(module) = modules.get(hash) {
+            return Ok(module.clone());
+        }
+    }
+
+    let bytes = bytes.ok_or_else(|| Error::Task(format!("Unknown WebAssembly module: {}", hash)))?;
+    let module = Module::new(engine, bytes)
+        .map_err(|e| Error::Task(format!("Failed to compile WebAssembly module: {}", e)))?;
+
+    let mut modules = modules.lock()
+        .map_err(|_| Error::Task("Failed to lock module cache".to_string()))?;
+    modules.insert(hash.to_string(), module.clone());
+
+    Ok(module)
+}
+
+fn run_module(
+    engine: &Engine,
+    linker: &Linker<WasmState>,
+    module: &Module,
+    config: &WasmConfig,
+    wasm_task: WasmTask,
+    task_id: &str,
+    cancel: &CancellationToken,
+) -> Result<TaskResult, Error> {
+    let stdout = MemoryOutputPipe::new(config.max_output_bytes);
+    let stderr = MemoryOutputPipe::new(config.max_output_bytes);
+
+    // No preopened directories and no socket access: the module only sees stdio
+    let wasi = WasiCtxBuilder::new()
+        .stdin(MemoryInputPipe::new(wasm_task.input))
+        .stdout(stdout.clone())
+        .stderr(stderr.clone())
+        .args(&wasm_task.args)
+        .allow_tcp(false)
+        .allow_udp(false)
+        .allow_ip_name_lookup(false)
+        .build_p1();
+
+    let mut store = Store::new(engine, WasmState {
+        wasi,
+        limiter: MemoryLimiter {
+            limit: usize::try_from(config.memory_limit).unwrap_or(usize::MAX),
+            peak: 0,
+        },
+    });
+    store.limiter(|state| &mut state.limiter);
+    store.set_fuel(config.fuel)
+        .map_err(|e| Error::Task(format!("Failed to set fuel: {}", e)))?;
+    // Interrupt the module on the first epoch tick after it is cancelled or times out
+    let deadline = Instant::now() + config.timeout;
+    let cancel_check = cancel.clone();
+    store.epoch_deadline_callback(move |_| {
+        if cancel_check.is_cancelled() || Instant::now() >= deadline {
+            return Err(Trap::Interrupt.into());
+        }
+        Ok(UpdateDeadline::Continue(1))
+    });
+    store.set_epoch_deadline(1);
+
+    let meter = ThreadUsageMeter::start();
+
+    let outcome = linker.instantiate(&mut store, module)
+        .and_then(|instance| instance.get_typed_func::<(), ()>(&mut store, "_start"))
+        .and_then(|start| start.call(&mut store, ()));
+
+    let cpu_time = meter.cpu_time();
+
+    let stderr_output = stderr.contents();
+    if !stderr_output.is_empty() {
+        log::debug!("WebAssembly task {} stderr: {}", task_id, String::from_utf8_lossy(&stderr_output));
+    }
+
+    let peak_memory = store.data().limiter.peak as u64;
+    let exit_reason = match outcome {
+        Ok(()) => ExitReason::Completed,
+        Err(_) if cancel.is_cancelled() => return Ok(TaskResult::cancelled(cpu_time)),
+        Err(e) => match (e.downcast_ref::<I32Exit>(), e.downcast_ref::<Trap>()) {
+            (Some(I32Exit(0)), _) => ExitReason::Completed,
+            (Some(I32Exit(code)), _) => ExitReason::Failed(format!("exited with code {}", code)),
+            (_, Some(Trap::OutOfFuel)) => ExitReason::Failed("ran out of fuel".to_string()),
+            (_, Some(Trap::Interrupt)) => ExitReason::TimedOut,
+            (_, Some(trap)) => ExitReason::Failed(format!("trapped: {}", trap)),
+            _ => return Err(Error::Task(format!("WebAssembly task {} failed: {:#}", task_id, e))),
+        },
+    };
+
+    Ok(TaskResult {
+        exit_reason,
+        ..TaskResult::completed(stdout.contents().to_vec(), cpu_time, peak_memory)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tasks::{TaskResourceType, TaskStatus};
+
+    // Copies stdin to stdout in 64-byte reads
+    const ECHO_WAT: &str = r#"
+        (module
+            (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+            (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+            (memory (export "memory") 1)
+            (func (export "_start")
+                (i32.store (i32.const 0) (i32.const 64))
+                (i32.store (i32.const 4) (i32.const 64))
+                (block $done
+                    (loop $copy
+                        (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))
+                        (br_if $done (i32.eqz (i32.load (i32.const 8))))
+                        (i32.store (i32.const 12) (i32.const 64))
+                        (i32.store (i32.const 16) (i32.load (i32.const 8)))
+                        (drop (call $fd_write (i32.const 1) (i32.const 12) (i32.const 1) (i32.const 20)))
+                        (br $copy)))))
+    "#;
+
+    const SPIN_WAT: &str = r#"(module (func (export "_start") (loop $spin (br $spin))))"#;
+
+    fn wasm_task(module: WasmModuleSource, input: &[u8]) -> Task {
+        Task {
+            id: "wasm-1".to_string(),
+            resource_type: TaskResourceType::Cpu,
+            data: WasmTask { module, args: Vec::new(), input: input.to_vec() }.to_bytes().unwrap(),
+            status: TaskStatus::Pending,
+            created_at: 0,
+            completed_at: None,
+            checkpoint: None,
+            deterministic: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_echo_uses_stdin_and_stdout() {
+        let executor = WasmTaskExecutor::new(WasmConfig::default()).unwrap();
+        let hash = executor.load_module(ECHO_WAT.as_bytes()).unwrap();
+        assert!(executor.has_module(&hash));
+
+        let input = b"hello from a sandboxed module, long enough to need two reads";
+        let result = executor.execute(&wasm_task(WasmModuleSource::Hash(hash), input), &ProgressReporter::disabled("test"), &CancellationToken::new()).await.unwrap();
+        assert_eq!(result.output, input);
+    }
+
+    #[tokio::test]
+    async fn test_runaway_modules_are_stopped_and_cache_is_bounded() {
+        let config = WasmConfig {
+            fuel: 1_000_000,
+            ..WasmConfig::default()
+        };
+        let executor = WasmTaskExecutor::new(config).unwrap();
+
+        let task = wasm_task(WasmModuleSource::Inline(SPIN_WAT.as_bytes().to_vec()), b"");
+        let result = executor.execute(&task, &ProgressReporter::disabled("test"), &CancellationToken::new()).await.unwrap();
+        assert_eq!(result.exit_reason, ExitReason::Failed("ran out of fuel".to_string()));
+
+        let executor_with_timeout = WasmTaskExecutor::new(WasmConfig {
+            timeout: Duration::from_millis(50),
+            ..WasmConfig::default()
+        }).unwrap();
+        let result = executor_with_timeout.execute(&task, &ProgressReporter::disabled("test"), &CancellationToken::new()).await.unwrap();
+        assert_eq!(result.exit_reason, ExitReason::TimedOut);
+
+        // Only the most recently used modules stay compiled
+        executor.set_max_cached_modules(1).unwrap();
+        let spin = content_hash(SPIN_WAT.as_bytes());
+        assert!(executor.has_module(&spin));
+        let echo = executor.load_module(ECHO_WAT.as_bytes()).unwrap();
+        assert!(executor.has_module(&echo));
+        assert!(!executor.has_module(&spin));
+    }
+}
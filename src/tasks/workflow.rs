@@ -0,0 +1,774 @@
+/* Copyright 2025 Joao Guimaraes, Catp2p Project
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Workflow functionality for running directed acyclic graphs (DAGs) of tasks.
+//!
+//! A task in a workflow becomes runnable only once all of its parents have completed.
+//! Children receive their parents' outputs through a [`WorkflowTaskInput`] encoded
+//! into their task data. Tasks run on the scheduler as `<workflow ID>/<task ID>`, so
+//! workflows with the same task IDs do not collide; neither ID may contain a `/`.
+
+use crate::error::Error;
+use crate::tasks::cancel::CancellationToken;
+use crate::tasks::scheduler::TaskScheduler;
+use crate::tasks::{current_timestamp, Task, TaskResult, TaskStatus};
+use futures::FutureExt;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::{Mutex, Notify};
+use tokio::task::JoinSet;
+
+/// Default number of finished workflows kept for status queries.
+const DEFAULT_MAX_FINISHED_WORKFLOWS: usize = 100;
+
+/// Separates the workflow ID from the task ID in the IDs tasks run under on the scheduler.
+const SCHEDULER_ID_SEPARATOR: char = '/';
+
+/// Orders finished workflows, since completion timestamps only have second resolution.
+static FINISH_SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+/// A task within a workflow, together with the IDs of the tasks it depends on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkflowNode {
+    /// The task to execute.
+    pub task: Task,
+    /// IDs of the parent tasks that must complete before this task can run.
+    pub dependencies: Vec<String>,
+}
+
+/// A directed acyclic graph of tasks.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Workflow {
+    /// Workflow ID.
+    pub id: String,
+    /// The tasks in the workflow.
+    pub nodes: Vec<WorkflowNode>,
+}
+
+impl Workflow {
+    /// Creates a new, empty Workflow with the given ID.
+    pub fn new(id: String) -> Self {
+        Self {
+            id,
+            nodes: Vec::new(),
+        }
+    }
+
+    /// Adds a task to the workflow, depending on the given parent task IDs.
+    pub fn add_task(&mut self, task: Task, dependencies: Vec<String>) -> &mut Self {
+        self.nodes.push(WorkflowNode {
+            task,
+            dependencies,
+        });
+        self
+    }
+
+    /// Validates the workflow and returns its task IDs in topological order.
+    ///
+    /// Fails if the workflow ID or a task ID contains a `/`, if task IDs are
+    /// duplicated, if a dependency refers to an unknown task, or if the
+    /// dependencies contain a cycle.
+    pub fn validate(&self) -> Result<Vec<String>, Error> {
+        // Scheduler IDs join the two with a separator, which must stay unambiguous
+        if self.id.contains(SCHEDULER_ID_SEPARATOR) {
+            return Err(Error::Task(format!("Workflow ID must not contain '{}': {}", SCHEDULER_ID_SEPARATOR, self.id)));
+        }
+
+        let mut in_degree: HashMap<&str, usize> = HashMap::new();
+        for node in &self.nodes {
+            if node.task.id.contains(SCHEDULER_ID_SEPARATOR) {
+                return Err(Error::Task(format!(
+                    "Task ID in workflow must not contain '{}': {}", SCHEDULER_ID_SEPARATOR, node.task.id
+                )));
+            }
+            if in_degree.insert(node.task.id.as_str(), node.dependencies.len()).is_some() {
+                return Err(Error::Task(format!("Duplicate task ID in workflow: {}", node.task.id)));
+            }
+        }
+
+        let mut children: HashMap<&str, Vec<&str>> = HashMap::new();
+        for node in &self.nodes {
+            for dependency in &node.dependencies {
+                if !in_degree.contains_key(dependency.as_str()) {
+                    return Err(Error::Task(format!(
+                        "Task {} depends on unknown task {}", node.task.id, dependency
+                    )));
+                }
+                children.entry(dependency.as_str()).or_default().push(node.task.id.as_str());
+            }
+        }
+
+        // Kahn's algorithm: any task left unvisited is part of a cycle
+        let mut ready: VecDeque<&str> = self.nodes.iter()
+            .map(|node| node.task.id.as_str())
+            .filter(|id| in_degree[id] == 0)
+            .collect();
+        let mut order = Vec::with_capacity(self.nodes.len());
+
+        while let Some(id) = ready.pop_front() {
+            order.push(id.to_string());
+            for child in children.get(id).into_iter().flatten() {
+                let degree = in_degree.get_mut(child).expect("child is a known task");
+                *degree -= 1;
+                if *degree == 0 {
+                    ready.push_back(child);
+                }
+            }
+        }
+
+        if order.len() != self.nodes.len() {
+            let cyclic: Vec<&str> = self.nodes.iter()
+                .map(|node| node.task.id.as_str())
+                .filter(|id| !order.iter().any(|visited| visited == id))
+                .collect();
+            return Err(Error::Task(format!("Workflow contains a cycle involving: {}", cyclic.join(", "))));
+        }
+
+        Ok(order)
+    }
+}
+
+/// The output of a parent task, as passed to its children.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParentOutput {
+    /// The parent task ID.
+    pub task_id: String,
+    /// The parent task output.
+    pub output: Vec<u8>,
+}
+
+/// The input handed to a workflow task that has parents.
+///
+/// Tasks without dependencies receive their original data unchanged.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkflowTaskInput {
+    /// The task's original data.
+    pub data: Vec<u8>,
+    /// Outputs of the parent tasks, in the order the dependencies were declared.
+    pub parent_outputs: Vec<ParentOutput>,
+}
+
+impl WorkflowTaskInput {
+    /// Serializes the input to bytes.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        serde_json::to_vec(self).map_err(Error::Serialization)
+    }
+
+    /// Deserializes the input from task data.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        serde_json::from_slice(bytes).map_err(Error::Serialization)
+    }
+}
+
+/// A snapshot of a single task's state within a workflow.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkflowTaskState {
+    /// Task status.
+    pub status: TaskStatus,
+    /// Task output, if completed.
+    pub output: Option<Vec<u8>>,
+    /// Error message, if failed.
+    pub error: Option<String>,
+}
+
+struct NodeState {
+    node: WorkflowNode,
+    children: Vec<String>,
+    state: WorkflowTaskState,
+}
+
+struct WorkflowState {
+    status: TaskStatus,
+    order: Vec<String>,
+    nodes: HashMap<String, NodeState>,
+    cancel: Arc<Notify>,
+    created_at: u64,
+    completed_at: Option<u64>,
+    finish_sequence: Option<u64>,
+}
+
+impl WorkflowState {
+    /// Marks runnable tasks as running and returns them with their inputs wired in.
+    fn take_ready_tasks(&mut self) -> Result<Vec<Task>, Error> {
+        let mut ready = Vec::new();
+
+        for id in &self.order {
+            let node = &self.nodes[id];
+            if node.state.status != TaskStatus::Pending {
+                continue;
+            }
+
+            let parents_done = node.node.dependencies.iter()
+                .all(|parent| self.nodes[parent].state.status == TaskStatus::Completed);
+            if !parents_done {
+                continue;
+            }
+
+            let mut task = node.node.task.clone();
+            if !node.node.dependencies.is_empty() {
+                let parent_outputs = node.node.dependencies.iter()
+                    .map(|parent| ParentOutput {
+                        task_id: parent.clone(),
+                        output: self.nodes[parent].state.output.clone().unwrap_or_default(),
+                    })
+                    .collect();
+                task.data = WorkflowTaskInput {
+                    data: task.data,
+                    parent_outputs,
+                }.to_bytes()?;
+            }
+            task.status = TaskStatus::Running;
+            ready.push(task);
+        }
+
+        for task in &ready {
+            if let Some(node) = self.nodes.get_mut(&task.id) {
+                node.state.status = TaskStatus::Running;
+            }
+        }
+
+        Ok(ready)
+    }
+
+    /// Records the outcome of a task, failing its descendants if it failed.
+    fn record_result(&mut self, task_id: &str, result: Result<Vec<u8>, Error>) {
+        match result {
+            Ok(output) => {
+                if let Some(node) = self.nodes.get_mut(task_id) {
+                    node.state.status = TaskStatus::Completed;
+                    node.state.output = Some(output);
+                }
+            },
+            Err(e) => {
+                if let Some(node) = self.nodes.get_mut(task_id) {
+                    node.state.status = TaskStatus::Failed;
+                    node.state.error = Some(e.to_string());
+                }
+                self.fail_descendants(task_id);
+            },
+        }
+    }
+
+    fn fail_descendants(&mut self, task_id: &str) {
+        let mut stack = vec![task_id.to_string()];
+        let mut visited = HashSet::new();
+
+        while let Some(id) = stack.pop() {
+            let children = match self.nodes.get(&id) {
+                Some(node) => node.children.clone(),
+                None => continue,
+            };
+            for child in children {
+                if !visited.insert(child.clone()) {
+                    continue;
+                }
+                if let Some(node) = self.nodes.get_mut(&child) {
+                    if node.state.status == TaskStatus::Pending {
+                        node.state.status = TaskStatus::Failed;
+                        node.state.error = Some(format!("Upstream task {} failed", task_id));
+                    }
+                }
+                stack.push(child);
+            }
+        }
+    }
+
+    /// Marks every unfinished task as cancelled.
+    fn cancel_unfinished(&mut self) {
+        for node in self.nodes.values_mut() {
+            if matches!(node.state.status, TaskStatus::Pending | TaskStatus::Running) {
+                node.state.status = TaskStatus::Cancelled;
+            }
+        }
+    }
+
+    fn finish(&mut self, status: TaskStatus) {
+        self.status = status;
+        self.completed_at = Some(current_timestamp());
+        self.finish_sequence = Some(FINISH_SEQUENCE.fetch_add(1, Ordering::Relaxed));
+    }
+}
+
+/// Workflow manager for submitting and tracking DAGs of tasks.
+///
+/// Finished workflows are kept for status queries until they are removed, or
+/// until more than `max_finished_workflows` have finished, oldest first.
+pub struct WorkflowManager {
+    scheduler: Arc<TaskScheduler>,
+    workflows: Arc<Mutex<HashMap<String, WorkflowState>>>,
+    max_finished_workflows: usize,
+}
+
+impl WorkflowManager {
+    /// Creates a new WorkflowManager that executes tasks through the given scheduler.
+    pub fn new(scheduler: Arc<TaskScheduler>) -> Self {
+        Self {
+            scheduler,
+            workflows: Arc::new(Mutex::new(HashMap::new())),
+            max_finished_workflows: DEFAULT_MAX_FINISHED_WORKFLOWS,
+        }
+    }
+
+    /// Sets the number of finished workflows kept for status queries.
+    pub fn set_max_finished_workflows(&mut self, max_finished_workflows: usize) {
+        self.max_finished_workflows = max_finished_workflows;
+    }
+
+    /// Validates and submits a workflow for execution, returning its ID.
+    ///
+    /// The workflow is rejected up front if it is not valid, see [`Workflow::validate`].
+    pub async fn submit_workflow(&self, workflow: Workflow) -> Result<String, Error> {
+        let order = workflow.validate()?;
+        let workflow_id = workflow.id.clone();
+
+        let mut children: HashMap<String, Vec<String>> = HashMap::new();
+        for node in &workflow.nodes {
+            for dependency in &node.dependencies {
+                children.entry(dependency.clone()).or_default().push(node.task.id.clone());
+            }
+        }
+
+        let nodes = workflow.nodes.into_iter()
+            .map(|node| {
+                let id = node.task.id.clone();
+                let state = NodeState {
+                    children: children.remove(&id).unwrap_or_default(),
+                    node,
+                    state: WorkflowTaskState {
+                        status: TaskStatus::Pending,
+                        output: None,
+                        error: None,
+                    },
+                };
+                (id, state)
+            })
+            .collect();
+
+        let cancel = Arc::new(Notify::new());
+
+        {
+            let mut workflows = self.workflows.lock().await;
+            if workflows.contains_key(&workflow_id) {
+                return Err(Error::Task(format!("Workflow {} already exists", workflow_id)));
+            }
+            prune_finished(&mut workflows, self.max_finished_workflows);
+            workflows.insert(workflow_id.clone(), WorkflowState {
+                status: TaskStatus::Running,
+                order,
+                nodes,
+                cancel: cancel.clone(),
+                created_at: current_timestamp(),
+                completed_at: None,
+                finish_sequence: None,
+            });
+        }
+
+        tokio::spawn(drive_workflow(
+            self.scheduler.clone(),
+            self.workflows.clone(),
+            workflow_id.clone(),
+            cancel,
+        ));
+
+        Ok(workflow_id)
+    }
+
+    /// Gets the overall status of a workflow.
+    pub async fn get_workflow_status(&self, workflow_id: &str) -> Result<TaskStatus, Error> {
+        let workflows = self.workflows.lock().await;
+        workflows.get(workflow_id)
+            .map(|state| state.status)
+            .ok_or_else(|| Error::Task(format!("Unknown workflow: {}", workflow_id)))
+    }
+
+    /// Gets the state of a single task within a workflow.
+    pub async fn get_task_state(&self, workflow_id: &str, task_id: &str) -> Result<WorkflowTaskState, Error> {
+        let workflows = self.workflows.lock().await;
+        let state = workflows.get(workflow_id)
+            .ok_or_else(|| Error::Task(format!("Unknown workflow: {}", workflow_id)))?;
+
+        state.nodes.get(task_id)
+            .map(|node| node.state.clone())
+            .ok_or_else(|| Error::Task(format!("Unknown task {} in workflow {}", task_id, workflow_id)))
+    }
+
+    /// Gets the creation and completion timestamps of a workflow.
+    pub async fn get_workflow_times(&self, workflow_id: &str) -> Result<(u64, Option<u64>), Error> {
+        let workflows = self.workflows.lock().await;
+        workflows.get(workflow_id)
+            .map(|state| (state.created_at, state.completed_at))
+            .ok_or_else(|| Error::Task(format!("Unknown workflow: {}", workflow_id)))
+    }
+
+    /// Cancels a workflow, stopping any running tasks and skipping the rest.
+    pub async fn cancel_workflow(&self, workflow_id: &str) -> Result<(), Error> {
+        let mut workflows = self.workflows.lock().await;
+        let state = workflows.get_mut(workflow_id)
+            .ok_or_else(|| Error::Task(format!("Unknown workflow: {}", workflow_id)))?;
+
+        if state.status != TaskStatus::Running {
+            return Ok(());
+        }
+
+        state.cancel_unfinished();
+        state.finish(TaskStatus::Cancelled);
+        state.cancel.notify_one();
+
+        Ok(())
+    }
+
+    /// Removes a finished workflow.
+    ///
+    /// Running workflows must be cancelled before they can be removed.
+    pub async fn remove_workflow(&self, workflow_id: &str) -> Result<(), Error> {
+        let mut workflows = self.workflows.lock().await;
+        let state = workflows.get(workflow_id)
+            .ok_or_else(|| Error::Task(format!("Unknown workflow: {}", workflow_id)))?;
+
+        if state.status == TaskStatus::Running {
+            return Err(Error::Task(format!("Workflow {} is still running", workflow_id)));
+        }
+        workflows.remove(workflow_id);
+
+        Ok(())
+    }
+}
+
+/// Removes the oldest finished workflows beyond `max_finished`.
+fn prune_finished(workflows: &mut HashMap<String, WorkflowState>, max_finished: usize) {
+    let mut finished: Vec<(u64, String)> = workflows.iter()
+        .filter_map(|(id, state)| state.finish_sequence.map(|sequence| (sequence, id.clone())))
+        .collect();
+    if finished.len() <= max_finished {
+        return;
+    }
+
+    finished.sort();
+    let excess = finished.len() - max_finished;
+    for (_, id) in finished.into_iter().take(excess) {
+        workflows.remove(&id);
+    }
+}
+
+/// Drives a workflow to completion, launching tasks as their parents complete.
+async fn drive_workflow(
+    scheduler: Arc<TaskScheduler>,
+    workflows: Arc<Mutex<HashMap<String, WorkflowState>>>,
+    workflow_id: String,
+    cancel: Arc<Notify>,
+) {
+    let mut running = JoinSet::new();
+    // Cancellation tokens of the tasks in `running`, by scheduler ID
+    let mut running_tokens = HashMap::new();
+
+    loop {
+        {
+            let mut workflows = workflows.lock().await;
+            let state = match workflows.get_mut(&workflow_id) {
+                Some(state) => state,
+                None => break,
+            };
+            if state.status != TaskStatus::Running {
+                break;
+            }
+
+            let ready = match state.take_ready_tasks() {
+                Ok(ready) => ready,
+                Err(e) => {
+                    log::error!("Failed to prepare tasks for workflow {}: {}", workflow_id, e);
+                    state.cancel_unfinished();
+                    state.finish(TaskStatus::Failed);
+                    break;
+                },
+            };
+
+            for mut task in ready {
+                let scheduler_id = scheduler_task_id(&workflow_id, &task.id);
+                let node_id = std::mem::replace(&mut task.id, scheduler_id);
+                let cancel = CancellationToken::new();
+                running_tokens.insert(task.id.clone(), cancel.clone());
+                let scheduler = scheduler.clone();
+                running.spawn(async move {
+                    // Waits for room on the scheduler, so wide fan-outs queue instead of failing
+                    let result = AssertUnwindSafe(scheduler.execute_queued(&task, cancel))
+                        .catch_unwind()
+                        .await
+                        .unwrap_or_else(|_| Err(Error::Task(format!("Task {} panicked", task.id))));
+                    (node_id, result)
+                });
+            }
+
+            if running.is_empty() {
+                let failed = state.nodes.values().any(|node| node.state.status == TaskStatus::Failed);
+                state.finish(if failed { TaskStatus::Failed } else { TaskStatus::Completed });
+                break;
+            }
+        }
+
+        tokio::select! {
+            _ = cancel.notified() => {
+                // Cancelled tasks still finish through the scheduler, recording their status
+                for (task_id, cancel) in &running_tokens {
+                    cancel.cancel();
+                    if let Err(e) = scheduler.cancel_task(task_id).await {
+                        log::warn!("Failed to cancel task {} of workflow {}: {}", task_id, workflow_id, e);
+                    }
+                }
+                while running.join_next().await.is_some() {}
+                break;
+            },
+            joined = running.join_next() => {
+                if let Some(Ok((task_id, result))) = joined {
+                    running_tokens.remove(&scheduler_task_id(&workflow_id, &task_id));
+                    let mut workflows = workflows.lock().await;
+                    if let Some(state) = workflows.get_mut(&workflow_id) {
+                        state.record_result(&task_id, result.and_then(TaskResult::into_output));
+                    }
+                }
+            },
+        }
+    }
+}
+
+/// Returns the ID a workflow task runs under on the scheduler.
+fn scheduler_task_id(workflow_id: &str, task_id: &str) -> String {
+    format!("{}{}{}", workflow_id, SCHEDULER_ID_SEPARATOR, task_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ResourceMode;
+    use crate::resources::allocation::ResourceAllocator;
+    use crate::tasks::events::{ProgressReporter, TaskEventKind};
+    use crate::tasks::testing::resources;
+    use crate::tasks::{TaskExecutor, TaskResourceType};
+    use async_trait::async_trait;
+    use std::time::Duration;
+
+    /// Records the order tasks run in, by task ID within their workflow.
+    /// Tasks named `fail-*` fail, `slow-*` run until cancelled and `busy-*` take a moment.
+    #[derive(Default)]
+    struct RecordingExecutor {
+        started: std::sync::Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl TaskExecutor for RecordingExecutor {
+        async fn execute(&self, task: &Task, _progress: &ProgressReporter, cancel: &CancellationToken) -> Result<TaskResult, Error> {
+            let name = task.id.rsplit('/').next().unwrap_or_default().to_string();
+            self.started.lock().unwrap().push(name.clone());
+            if name.starts_with("fail-") {
+                return Err(Error::Task(format!("{} failed", name)));
+            }
+            if name.starts_with("slow-") {
+                cancel.cancelled().await;
+                return Ok(TaskResult::cancelled(Duration::ZERO));
+            }
+            if name.starts_with("busy-") {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+            Ok(TaskResult::completed(name.into_bytes(), Duration::ZERO, 0))
+        }
+    }
+
+    fn manager() -> (WorkflowManager, Arc<RecordingExecutor>) {
+        let executor = Arc::new(RecordingExecutor::default());
+        let mut scheduler = TaskScheduler::new(4, Duration::from_secs(3600));
+        scheduler.set_cpu_executor(executor.clone());
+        (WorkflowManager::new(Arc::new(scheduler)), executor)
+    }
+
+    async fn wait_until_finished(manager: &WorkflowManager, workflow_id: &str) -> TaskStatus {
+        loop {
+            let status = manager.get_workflow_status(workflow_id).await.unwrap();
+            if status != TaskStatus::Running {
+                return status;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    }
+
+    fn task(id: &str) -> Task {
+        Task {
+            id: id.to_string(),
+            resource_type: TaskResourceType::Cpu,
+            data: Vec::new(),
+            status: TaskStatus::Pending,
+            created_at: 0,
+            completed_at: None,
+            checkpoint: None,
+            deterministic: false,
+        }
+    }
+
+    #[test]
+    fn test_validate_orders_diamond() {
+        let mut workflow = Workflow::new("diamond".to_string());
+        workflow
+            .add_task(task("aggregate"), vec!["left".to_string(), "right".to_string()])
+            .add_task(task("left"), vec!["preprocess".to_string()])
+            .add_task(task("right"), vec!["preprocess".to_string()])
+            .add_task(task("preprocess"), vec![]);
+
+        let order = workflow.validate().expect("diamond is acyclic");
+        let position = |id: &str| order.iter().position(|x| x == id).unwrap();
+        assert!(position("preprocess") < position("left"));
+        assert!(position("right") < position("aggregate"));
+    }
+
+    #[test]
+    fn test_validate_rejects_cycle() {
+        let mut workflow = Workflow::new("cycle".to_string());
+        workflow
+            .add_task(task("a"), vec!["c".to_string()])
+            .add_task(task("b"), vec!["a".to_string()])
+            .add_task(task("c"), vec!["b".to_string()]);
+
+        assert!(workflow.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_rejects_separator_in_ids() {
+        // Both would run their task as `a/b/c` on the scheduler
+        let mut nested_workflow = Workflow::new("a/b".to_string());
+        nested_workflow.add_task(task("c"), vec![]);
+        assert!(nested_workflow.validate().is_err());
+
+        let mut nested_task = Workflow::new("a".to_string());
+        nested_task.add_task(task("b/c"), vec![]);
+        assert!(nested_task.validate().is_err());
+
+        nested_task.nodes[0].task.id = "b-c".to_string();
+        assert!(nested_task.validate().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_tasks_run_after_parents_with_their_outputs() {
+        let (manager, executor) = manager();
+        let mut workflow = Workflow::new("chain".to_string());
+        workflow
+            .add_task(task("aggregate"), vec!["left".to_string(), "right".to_string()])
+            .add_task(task("left"), vec!["preprocess".to_string()])
+            .add_task(task("right"), vec!["preprocess".to_string()])
+            .add_task(task("preprocess"), vec![]);
+
+        let workflow_id = manager.submit_workflow(workflow).await.unwrap();
+        assert_eq!(wait_until_finished(&manager, &workflow_id).await, TaskStatus::Completed);
+
+        let started = executor.started.lock().unwrap().clone();
+        assert_eq!(started.first().map(String::as_str), Some("preprocess"));
+        assert_eq!(started.last().map(String::as_str), Some("aggregate"));
+
+        let output = manager.get_task_state(&workflow_id, "aggregate").await.unwrap().output.unwrap();
+        assert_eq!(output, b"aggregate");
+        let finished = manager.get_task_state(&workflow_id, "left").await.unwrap();
+        assert_eq!(finished.status, TaskStatus::Completed);
+    }
+
+    #[tokio::test]
+    async fn test_failure_propagates_to_descendants() {
+        let (manager, executor) = manager();
+        let mut workflow = Workflow::new("failing".to_string());
+        workflow
+            .add_task(task("root"), vec![])
+            .add_task(task("fail-middle"), vec!["root".to_string()])
+            .add_task(task("leaf"), vec!["fail-middle".to_string()])
+            .add_task(task("sibling"), vec!["root".to_string()]);
+
+        let workflow_id = manager.submit_workflow(workflow).await.unwrap();
+        assert_eq!(wait_until_finished(&manager, &workflow_id).await, TaskStatus::Failed);
+
+        let leaf = manager.get_task_state(&workflow_id, "leaf").await.unwrap();
+        assert_eq!(leaf.status, TaskStatus::Failed);
+        assert_eq!(leaf.error.as_deref(), Some("Upstream task fail-middle failed"));
+        assert_eq!(manager.get_task_state(&workflow_id, "sibling").await.unwrap().status, TaskStatus::Completed);
+        assert!(!executor.started.lock().unwrap().contains(&"leaf".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_wide_fan_out_waits_for_the_scheduler() {
+        let executor = Arc::new(RecordingExecutor::default());
+        // Three cores are available in high performance mode
+        let allocator = Arc::new(ResourceAllocator::new(ResourceMode::HighPerformance, None, resources(4)));
+        let mut scheduler = TaskScheduler::new(2, Duration::from_secs(3600));
+        scheduler.set_cpu_executor(executor.clone());
+        scheduler.set_allocator(allocator.clone());
+        let manager = WorkflowManager::new(Arc::new(scheduler));
+
+        let mut workflow = Workflow::new("fan-out".to_string());
+        workflow.add_task(task("root"), vec![]);
+        for index in 0..50 {
+            workflow.add_task(task(&format!("busy-{}", index)), vec!["root".to_string()]);
+        }
+
+        let workflow_id = manager.submit_workflow(workflow).await.unwrap();
+        assert_eq!(wait_until_finished(&manager, &workflow_id).await, TaskStatus::Completed);
+        assert_eq!(executor.started.lock().unwrap().len(), 51);
+        assert_eq!(allocator.reserved().unwrap().cpu_cores, 0);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_and_retention() {
+        let (mut manager, executor) = manager();
+        manager.set_max_finished_workflows(1);
+        let mut events = manager.scheduler.events().subscribe();
+        let mut workflow = Workflow::new("cancelled".to_string());
+        workflow
+            .add_task(task("slow-root"), vec![])
+            .add_task(task("child"), vec!["slow-root".to_string()]);
+
+        let workflow_id = manager.submit_workflow(workflow).await.unwrap();
+        while manager.scheduler.running_task_count().await == 0 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert!(manager.remove_workflow(&workflow_id).await.is_err());
+        manager.cancel_workflow(&workflow_id).await.unwrap();
+        assert_eq!(manager.get_workflow_status(&workflow_id).await.unwrap(), TaskStatus::Cancelled);
+        assert_eq!(manager.get_task_state(&workflow_id, "slow-root").await.unwrap().status, TaskStatus::Cancelled);
+        assert_eq!(manager.get_task_state(&workflow_id, "child").await.unwrap().status, TaskStatus::Cancelled);
+
+        // The running task is cancelled on the scheduler and emits a terminal event
+        loop {
+            let event = events.recv().await.unwrap();
+            if event.task_id == "cancelled/slow-root" && event.kind.is_terminal() {
+                assert_eq!(event.kind, TaskEventKind::Cancelled);
+                break;
+            }
+        }
+        assert_eq!(manager.scheduler.get_task_status("cancelled/slow-root").await, Some(TaskStatus::Cancelled));
+        assert_eq!(manager.scheduler.running_task_count().await, 0);
+
+        // Only the most recent finished workflows are kept, even if they finish within
+        // the same second and sort before older ones by ID
+        let mut next = Workflow::new("a-next".to_string());
+        next.add_task(task("only"), vec![]);
+        manager.submit_workflow(next).await.unwrap();
+        wait_until_finished(&manager, "a-next").await;
+        let mut last = Workflow::new("last".to_string());
+        last.add_task(task("only"), vec![]);
+        manager.submit_workflow(last).await.unwrap();
+        assert!(manager.get_workflow_status("cancelled").await.is_err());
+        assert!(manager.get_workflow_status("a-next").await.is_ok());
+
+        wait_until_finished(&manager, "last").await;
+        manager.remove_workflow("last").await.unwrap();
+        assert!(manager.get_workflow_status("last").await.is_err());
+        assert!(!executor.started.lock().unwrap().contains(&"child".to_string()));
+    }
+}